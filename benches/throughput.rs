@@ -0,0 +1,59 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use nrs_language_server::chumsky::parse;
+use nrs_language_server::document_symbol::get_document_symbols;
+use nrs_language_server::reference::get_reference;
+use nrs_language_server::semantic_token::semantic_token_from_ast;
+use nrs_language_server::synthetic::synthetic_source;
+use ropey::Rope;
+
+/// Number of functions in the generated sources.
+const SIZES: &[usize] = &[10, 100, 1000];
+
+fn parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for &size in SIZES {
+        let src = synthetic_source(size);
+        group.throughput(Throughput::Bytes(src.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &src, |b, src| {
+            b.iter(|| parse(black_box(src)))
+        });
+    }
+    group.finish();
+}
+
+fn indexing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("index");
+    for &size in SIZES {
+        let src = synthetic_source(size);
+        let rope = Rope::from_str(&src);
+        let ast = parse(&src).0.expect("synthetic source parses");
+        // The last function's first parameter, referenced once in its body
+        let offset = src.rfind("(a, b)").unwrap() + 1;
+        group.throughput(Throughput::Bytes(src.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &ast, |b, ast| {
+            b.iter(|| {
+                (
+                    get_document_symbols(ast, &rope),
+                    get_reference(ast, black_box(offset), true),
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+fn semantic_tokens(c: &mut Criterion) {
+    let mut group = c.benchmark_group("semantic_tokens");
+    for &size in SIZES {
+        let src = synthetic_source(size);
+        let ast = parse(&src).0.expect("synthetic source parses");
+        group.throughput(Throughput::Bytes(src.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &ast, |b, ast| {
+            b.iter(|| semantic_token_from_ast(black_box(ast)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parsing, indexing, semantic_tokens);
+criterion_main!(benches);
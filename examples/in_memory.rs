@@ -0,0 +1,29 @@
+use nrs_language_server::test_util::serve_in_memory;
+use serde_json::json;
+
+#[tokio::main]
+async fn main() {
+    let (mut client, _server) = serve_in_memory();
+    let initialize = client
+        .request("initialize", json!({ "capabilities": {} }))
+        .await;
+    assert!(initialize["result"]["capabilities"].is_object());
+    client.notify("initialized", json!({})).await;
+    client
+        .notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": {
+                "uri": "file:///main.nrs",
+                "languageId": "nrs",
+                "version": 1,
+                "text": "fn main() { x }\n",
+            }}),
+        )
+        .await;
+    let diagnostics = client.notification("textDocument/publishDiagnostics").await;
+    println!("{}", diagnostics["params"]["diagnostics"]);
+    assert_eq!(
+        diagnostics["params"]["diagnostics"][0]["code"],
+        "unresolved-symbol"
+    );
+}
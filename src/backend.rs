@@ -0,0 +1,3310 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::builtin::{default_builtins, load_builtins, Builtin};
+use crate::call_hierarchy::{get_calls, get_function_at};
+use crate::chumsky::{
+    is_keyword, parse, return_type_inference, skip_padding, token_at, type_inference, Func,
+    ImCompleteSemanticToken, Span, Token,
+};
+use crate::client::LspClient;
+use crate::completion::{call_snippet, completion, expects_bool, fuzzy_match, visible_names};
+use crate::condition_assignment::assignments_in_conditions;
+use crate::config::{trace_allows, Config};
+use crate::diagnostic_docs::code_documentation;
+use crate::diagnostic_limit::sort_and_limit;
+use crate::doc_comment::doc_comment;
+use crate::document_color::{color_to_hex, document_colors};
+use crate::document_link::symbol_links;
+use crate::document_symbol::{
+    completion_kind, get_document_symbols, signature, ImCompleteDocumentSymbol,
+};
+use crate::fix_all::combine_fixes;
+use crate::folding_range::folding_ranges;
+use crate::formatter::{format, line_edits, on_enter};
+use crate::hover::number_hover;
+use crate::idle::IdleTimer;
+use crate::indentation::{mixed_indentation_lines, normalize_indentation};
+use crate::inline_value::get_variables;
+use crate::jump_definition::get_definition;
+use crate::line_comment::toggle_line_comment;
+use crate::line_ending::{mixed_line_endings, normalize_line_endings, LineEnding};
+use crate::line_length::long_lines;
+use crate::lru::LruKeys;
+use crate::manifest::ProjectManifest;
+use crate::match_brace::{match_brace, unclosed_bracket};
+use crate::node_index::NodeIndex;
+use crate::progress::ProgressGuard;
+use crate::reference::{get_container, get_reference};
+use crate::selection_range::get_selection_spans;
+use crate::semantic_cache::SemanticCache;
+use crate::semantic_token::{
+    semantic_token_edits, semantic_token_from_ast, LEGEND_MODIFIER, LEGEND_TYPE,
+};
+use crate::shadowing::ShadowKind;
+use crate::sort_members::sort_functions;
+use crate::trailing_comma::trailing_commas;
+use crate::unresolved_symbol::get_unresolved_symbols;
+use crate::utils::{
+    clamp_position, content_hash, is_binary, offset_to_position, position_to_offset, strip_bom,
+    uri_to_path, BOM,
+};
+use crate::wrap_block::wrap_in_block;
+use dashmap::{DashMap, DashSet};
+use ropey::Rope;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp::jsonrpc::{Error, ErrorCode, Result};
+use tower_lsp::lsp_types::notification::{LogTrace, Notification};
+use tower_lsp::lsp_types::*;
+use tower_lsp::{Client, ClientSocket, LanguageServer, LspService};
+/// URI schemes of the documents semantic tokens are provided for.
+const DOCUMENT_SCHEMES: &[&str] = &["file", "untitled"];
+
+/// Id of the `workspace/didChangeWatchedFiles` registration.
+const WATCHED_FILES_REGISTRATION: &str = "gen/watchedFiles";
+
+/// Small program exercising every construct of the language, analyzed once on `initialized`.
+const WARMUP_SOURCE: &str = "// warmup
+fn add(a, b) {
+    a + b
+}
+
+fn main() {
+    let list = [1, 0x2, 3.5];
+    let text = \"#ff0000\";
+    if add(1, 2) == 3 { print(text) } else { print(list) }
+}
+";
+
+/// Most top-level symbols sent in a single partial result.
+const PARTIAL_RESULT_CHUNK_SIZE: usize = 100;
+
+/// Number of recently completed or navigated to symbols boosted in completion.
+const RECENT_SYMBOLS_CAPACITY: usize = 50;
+
+/// LSP `RequestFailed`: the request was valid but the server couldn't complete it.
+const REQUEST_FAILED: i64 = -32803;
+
+#[derive(Debug)]
+pub struct Backend<C = Client> {
+    client: C,
+    ast_map: DashMap<String, HashMap<String, Func>>,
+    /// Node spans of each AST, for finding the node under a position.
+    node_index_map: DashMap<String, NodeIndex>,
+    document_map: DashMap<String, Rope>,
+    semantic_token_map: DashMap<String, Vec<ImCompleteSemanticToken>>,
+    /// The last full semantic tokens sent for each document with their result id, which
+    /// `semanticTokens/full/delta` requests are answered against.
+    semantic_token_result_map: DashMap<String, (String, Vec<SemanticToken>)>,
+    /// Document symbols along with the version of the document they were computed for.
+    document_symbol_map: DashMap<String, (Option<i32>, Vec<DocumentSymbol>)>,
+    /// The last hover of each document, with the version and position it was computed for.
+    hover_map: DashMap<String, (Option<i32>, Position, Option<Hover>)>,
+    content_hash_map: DashMap<String, u64>,
+    /// Semantic analyses of the functions of each document, redone only for changed functions.
+    semantic_map: DashMap<String, SemanticCache>,
+    /// Whether each document started with a byte order mark, stripped from its rope.
+    bom_map: DashMap<String, bool>,
+    /// Every diagnostic of the last analysis of each document, before `lint.maxDiagnostics`
+    /// cuts the published ones.
+    diagnostic_map: DashMap<String, Vec<Diagnostic>>,
+    open_documents: DashSet<String>,
+    /// Documents read from disk because a request came for them before they were opened.
+    disk_documents: DashSet<String>,
+    version_map: DashMap<String, i32>,
+    /// Version of the document each entry of `ast_map` was analyzed at, behind `version_map`
+    /// while an analysis is still under way.
+    ast_version_map: DashMap<String, i32>,
+    /// Documents that missed a change notification, whose content may not match the client's.
+    desynced_documents: DashSet<String>,
+    lru: Mutex<LruKeys>,
+    recent_symbols: Mutex<LruKeys>,
+    config: RwLock<Config>,
+    /// Manifest of each workspace root, scoping the files read from disk and watched.
+    manifests: RwLock<Vec<ProjectManifest>>,
+    /// Functions available without being defined, from `gen.builtinsPath` or the embedded set.
+    builtins: RwLock<Vec<Builtin>>,
+    client_capabilities: RwLock<ClientCapabilities>,
+    trace: RwLock<TraceValue>,
+    /// Set once the client sent `initialized`, before which document notifications are queued.
+    initialized: AtomicBool,
+    pending_notifications: Mutex<Vec<DocumentNotification>>,
+    idle_timer: IdleTimer,
+    /// Formats documents, swapped in tests for one slow enough to time out.
+    formatter: fn(&str, &FormattingOptions) -> String,
+}
+
+impl<C: LspClient> Backend<C> {
+    pub fn new(client: C) -> Self {
+        Backend {
+            client,
+            ast_map: DashMap::new(),
+            node_index_map: DashMap::new(),
+            document_map: DashMap::new(),
+            semantic_token_map: DashMap::new(),
+            semantic_token_result_map: DashMap::new(),
+            document_symbol_map: DashMap::new(),
+            hover_map: DashMap::new(),
+            content_hash_map: DashMap::new(),
+            semantic_map: DashMap::new(),
+            bom_map: DashMap::new(),
+            diagnostic_map: DashMap::new(),
+            open_documents: DashSet::new(),
+            disk_documents: DashSet::new(),
+            version_map: DashMap::new(),
+            ast_version_map: DashMap::new(),
+            desynced_documents: DashSet::new(),
+            lru: Mutex::new(LruKeys::default()),
+            recent_symbols: Mutex::new(LruKeys::default()),
+            config: RwLock::new(Config::default()),
+            manifests: RwLock::new(vec![]),
+            builtins: RwLock::new(default_builtins()),
+            client_capabilities: RwLock::new(ClientCapabilities::default()),
+            trace: RwLock::new(TraceValue::Off),
+            initialized: AtomicBool::new(false),
+            pending_notifications: Mutex::new(vec![]),
+            idle_timer: IdleTimer::default(),
+            formatter: format,
+        }
+    }
+
+    /// The timer restarted by every message, which expires after `gen.idleShutdownSeconds`.
+    pub fn idle_timer(&self) -> IdleTimer {
+        self.idle_timer.clone()
+    }
+}
+
+impl Backend {
+    /// The language server with its custom methods registered, ready to be served.
+    pub fn service() -> (LspService<Backend>, ClientSocket) {
+        LspService::build(Backend::new)
+            .custom_method("$/setTrace", Backend::set_trace)
+            .custom_method("gen/referencesGrouped", Backend::references_grouped)
+            .custom_method("gen/formatString", Backend::format_string)
+            .custom_method("gen/reloadConfig", Backend::reload_config)
+            .custom_method("gen/symbolAt", Backend::symbol_at)
+            .finish()
+    }
+}
+
+#[tower_lsp::async_trait]
+impl<C: LspClient> LanguageServer for Backend<C> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let config = Config::from_settings(params.initialization_options.as_ref());
+        let trigger_characters = config.completion.trigger_characters();
+        let features = config.features.clone();
+        let file_glob = config.file_glob();
+        *self.trace.write().unwrap() = config.trace.server.or(params.trace).unwrap_or_default();
+        self.idle_timer.set_timeout(config.idle_shutdown_seconds);
+        *self.config.write().unwrap() = config;
+        self.reload_builtins().await;
+        *self.client_capabilities.write().unwrap() = params.capabilities;
+        let roots = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+            None => params.root_uri.into_iter().collect::<Vec<_>>(),
+        };
+        for root in roots {
+            self.load_manifest(&root).await;
+        }
+        Ok(InitializeResult {
+            server_info: None,
+            offset_encoding: None,
+            capabilities: ServerCapabilities {
+                inlay_hint_provider: features.inlay_hints.then_some(OneOf::Left(true)),
+                hover_provider: features
+                    .hover
+                    .then_some(HoverProviderCapability::Simple(true)),
+                color_provider: Some(ColorProviderCapability::Simple(true)),
+                text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                    TextDocumentSyncKind::INCREMENTAL,
+                )),
+                completion_provider: features.completion.then(|| CompletionOptions {
+                    resolve_provider: Some(true),
+                    trigger_characters: Some(trigger_characters),
+                    work_done_progress_options: Default::default(),
+                    all_commit_characters: None,
+                    completion_item: None,
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        "dummy.do_something".to_string(),
+                        "gen.matchBrace".to_string(),
+                        "gen.enclosingFunction".to_string(),
+                        "gen.recordCompletion".to_string(),
+                        "gen.toggleComment".to_string(),
+                        "gen.peekDefinition".to_string(),
+                        "gen.reindexWorkspace".to_string(),
+                        "gen.fixAll".to_string(),
+                        "gen.documentStats".to_string(),
+                        "gen.normalizeLineEndings".to_string(),
+                    ],
+                    work_done_progress_options: Default::default(),
+                }),
+
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                        supported: Some(true),
+                        change_notifications: Some(OneOf::Left(true)),
+                    }),
+                    file_operations: None,
+                }),
+                semantic_tokens_provider: features.semantic_tokens.then(|| {
+                    SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(
+                        SemanticTokensRegistrationOptions {
+                            text_document_registration_options: {
+                                TextDocumentRegistrationOptions {
+                                    // Documents are keyed by their URI, never read from disk, so
+                                    // unsaved `untitled:` buffers get tokens as well. Files with a
+                                    // configured extension do whatever language the client gave
+                                    // them.
+                                    document_selector: Some(
+                                        DOCUMENT_SCHEMES
+                                            .iter()
+                                            .map(|scheme| DocumentFilter {
+                                                language: Some("nrs".to_string()),
+                                                scheme: Some(scheme.to_string()),
+                                                pattern: None,
+                                            })
+                                            .chain(std::iter::once(DocumentFilter {
+                                                language: None,
+                                                scheme: Some("file".to_string()),
+                                                pattern: Some(file_glob),
+                                            }))
+                                            .collect(),
+                                    ),
+                                }
+                            },
+                            semantic_tokens_options: SemanticTokensOptions {
+                                work_done_progress_options: WorkDoneProgressOptions::default(),
+                                legend: SemanticTokensLegend {
+                                    token_types: LEGEND_TYPE.into(),
+                                    token_modifiers: LEGEND_MODIFIER.into(),
+                                },
+                                range: Some(true),
+                                full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                            },
+                            static_registration_options: StaticRegistrationOptions::default(),
+                        },
+                    )
+                }),
+                // definition: Some(GotoCapability::default()),
+                definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                }),
+                document_formatting_provider: features.formatting.then_some(OneOf::Left(true)),
+                document_range_formatting_provider: features
+                    .formatting
+                    .then_some(OneOf::Left(true)),
+                document_on_type_formatting_provider: features.formatting.then(|| {
+                    DocumentOnTypeFormattingOptions {
+                        first_trigger_character: "\n".to_string(),
+                        more_trigger_character: None,
+                    }
+                }),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                inline_value_provider: Some(OneOf::Left(true)),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                ..ServerCapabilities::default()
+            },
+        })
+    }
+    async fn initialized(&self, _: InitializedParams) {
+        self.log_message(MessageType::INFO, "initialized!").await;
+        let pending = {
+            let mut pending_notifications = self.pending_notifications.lock().unwrap();
+            self.initialized.store(true, Ordering::SeqCst);
+            std::mem::take(&mut *pending_notifications)
+        };
+        for notification in pending {
+            self.send_document_notification(notification).await;
+        }
+        if self.watched_files_registration_support() {
+            self.watch_files().await;
+        }
+        // Run the lexer, parser and formatter once so the first real request doesn't pay for
+        // their first-use costs. Documents already open in the client arrive right after
+        // through `didOpen` and are analyzed there.
+        let started = Instant::now();
+        let (ast, _, _) = parse(WARMUP_SOURCE);
+        if let Some(ast) = ast {
+            get_unresolved_symbols(&ast);
+        }
+        format(WARMUP_SOURCE, &FormattingOptions::default());
+        self.log_message(
+            MessageType::INFO,
+            format!("warmed up in {:?}", started.elapsed()),
+        )
+        .await;
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        self.log_message(MessageType::INFO, "file opened!").await;
+        // A document opened again, e.g. when the editor reloads it, starts from scratch rather
+        // than keeping the last good AST or tokens of its previous content
+        self.forget_document(params.text_document.uri.as_str());
+        self.open_documents
+            .insert(params.text_document.uri.to_string());
+        self.resync(&params.text_document.uri, params.text_document.version);
+        self.on_change(TextDocumentItem {
+            uri: params.text_document.uri,
+            text: params.text_document.text,
+            version: params.text_document.version,
+        })
+        .await
+    }
+
+    async fn did_change(&self, mut params: DidChangeTextDocumentParams) {
+        let uri = &params.text_document.uri;
+        let version = params.text_document.version;
+        let previous = self.version_map.insert(uri.to_string(), version);
+        if params.content_changes.is_empty() {
+            // Only the version changed
+            self.carry_over_version(uri, previous, version);
+            return;
+        }
+        if params
+            .content_changes
+            .iter()
+            .any(|change| change.range.is_none())
+        {
+            // The whole content was sent, whatever was missed before doesn't matter anymore
+            self.resync(uri, version);
+        } else if let Some(previous) = previous.filter(|previous| version > previous + 1) {
+            if self.desynced_documents.insert(uri.to_string()) {
+                self.log_message(
+                    MessageType::WARNING,
+                    format!(
+                        "{} skipped from version {} to {}, reopen it to resynchronize",
+                        uri, previous, version
+                    ),
+                )
+                .await;
+            }
+        }
+        let text = match params.content_changes.as_mut_slice() {
+            [change] if change.range.is_none() => std::mem::take(&mut change.text),
+            changes => {
+                let mut rope = self
+                    .document_map
+                    .get(uri.as_str())
+                    .map(|rope| rope.clone())
+                    .unwrap_or_default();
+                // The byte order mark isn't part of the rope, it's written back once the changes
+                // are applied
+                let mut bom = self.has_bom(uri);
+                // Each change is relative to the content the previous ones left
+                for change in changes {
+                    match change.range {
+                        Some(range) => {
+                            let start = position_to_offset(&rope, range.start);
+                            let end = position_to_offset(&rope, range.end);
+                            if start <= end {
+                                rope.remove(start..end);
+                                rope.insert(start, &change.text);
+                            }
+                        }
+                        None => {
+                            let text = strip_bom(&change.text);
+                            bom = text.is_some();
+                            rope = Rope::from_str(text.unwrap_or(&change.text));
+                        }
+                    }
+                }
+                if bom {
+                    rope.insert_char(0, BOM);
+                }
+                rope.to_string()
+            }
+        };
+        // The diagnostics already published for this exact content are still accurate
+        if self.content_hash(&params.text_document.uri) == Some(content_hash(&text)) {
+            self.carry_over_version(uri, previous, version);
+            return;
+        }
+        self.on_change(TextDocumentItem {
+            uri: params.text_document.uri,
+            text,
+            version: params.text_document.version,
+        })
+        .await
+    }
+
+    async fn did_save(&self, _: DidSaveTextDocumentParams) {
+        self.log_message(MessageType::INFO, "file saved!").await;
+    }
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.log_message(MessageType::INFO, "file closed!").await;
+        self.open_documents
+            .remove(params.text_document.uri.as_str());
+        self.version_map.remove(params.text_document.uri.as_str());
+        self.ast_version_map
+            .remove(params.text_document.uri.as_str());
+        self.desynced_documents
+            .remove(params.text_document.uri.as_str());
+        let keep_closed = self.config.read().unwrap().diagnostics.keep_closed;
+        if !keep_closed || params.text_document.uri.scheme() != "file" {
+            self.notify(DocumentNotification::Diagnostics {
+                uri: params.text_document.uri,
+                diagnostics: vec![],
+                version: None,
+            })
+            .await;
+        }
+        self.evict_documents();
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        self.prepare_document(&params.text_document_position_params.text_document.uri)
+            .await;
+        let definition = async {
+            let uri = params.text_document_position_params.text_document.uri;
+            let ast = self.ast_map.get(uri.as_str())?;
+            let rope = self.document_map.get(uri.as_str())?;
+
+            let position = params.text_document_position_params.position;
+            let offset = position_to_offset(&rope, position);
+            // self.client.log_message(MessageType::INFO, &format!("{:#?}, {}", ast.value(), offset)).await;
+            let span = get_definition(&ast, offset);
+            self.log_trace("goto_definition", Some(format!("{:?}", span)))
+                .await;
+            if let Some((name, _)) = &span {
+                self.record_recent_symbol(name);
+            }
+            span.and_then(|(_, range)| {
+                let start_position = offset_to_position(range.start, &rope)?;
+                let end_position = offset_to_position(range.end, &rope)?;
+
+                let selection_range = Range::new(start_position, end_position);
+
+                if !self.definition_link_support() {
+                    return Some(GotoDefinitionResponse::Scalar(Location::new(
+                        uri,
+                        selection_range,
+                    )));
+                }
+                // Functions span their whole declaration, bindings are just their name
+                let target_range = match ast.values().find(|v| v.name.1 == range) {
+                    Some(v) => Range::new(
+                        offset_to_position(v.span.start, &rope)?,
+                        offset_to_position(v.span.end, &rope)?,
+                    ),
+                    None => selection_range,
+                };
+                let origin_selection_range =
+                    token_at(&rope.to_string(), offset).and_then(|(_, span)| {
+                        Some(Range::new(
+                            offset_to_position(span.start, &rope)?,
+                            offset_to_position(span.end, &rope)?,
+                        ))
+                    });
+                Some(GotoDefinitionResponse::Link(vec![LocationLink {
+                    origin_selection_range,
+                    target_uri: uri,
+                    target_range,
+                    target_selection_range: selection_range,
+                }]))
+            })
+        }
+        .await;
+        Ok(definition)
+    }
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        self.prepare_document(&params.text_document_position_params.text_document.uri)
+            .await;
+        if !self.config.read().unwrap().features.hover {
+            return Ok(None);
+        }
+        let uri = params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+        let version = self.version_map.get(uri.as_str()).map(|version| *version);
+        // The mouse resting over a symbol sends the same request again and again
+        if let Some(cached) = self.hover_map.get(uri.as_str()) {
+            if cached.0 == version && cached.1 == position {
+                return Ok(cached.2.clone());
+            }
+        }
+        let hover = || -> Option<Hover> {
+            let rope = self.document_map.get(uri.as_str())?;
+
+            let offset = position_to_offset(&rope, position);
+            let (contents, span) = number_hover(&rope.to_string(), offset)
+                .or_else(|| self.builtin_hover(&uri, &rope, offset))?;
+            let start_position = offset_to_position(span.start, &rope)?;
+            let end_position = offset_to_position(span.end, &rope)?;
+            Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: contents,
+                }),
+                range: Some(Range::new(start_position, end_position)),
+            })
+        }();
+        self.hover_map
+            .insert(uri.to_string(), (version, position, hover.clone()));
+        Ok(hover)
+    }
+
+    async fn document_color(&self, params: DocumentColorParams) -> Result<Vec<ColorInformation>> {
+        self.prepare_document(&params.text_document.uri).await;
+        let colors = || -> Option<Vec<ColorInformation>> {
+            let uri = params.text_document.uri;
+            let rope = self.document_map.get(uri.as_str())?;
+            let colors = document_colors(&rope.to_string())
+                .into_iter()
+                .filter_map(|(color, span)| {
+                    let start_position = offset_to_position(span.start, &rope)?;
+                    let end_position = offset_to_position(span.end, &rope)?;
+                    Some(ColorInformation {
+                        range: Range::new(start_position, end_position),
+                        color,
+                    })
+                })
+                .collect();
+            Some(colors)
+        }();
+        Ok(colors.unwrap_or_default())
+    }
+
+    async fn color_presentation(
+        &self,
+        params: ColorPresentationParams,
+    ) -> Result<Vec<ColorPresentation>> {
+        let label = color_to_hex(&params.color);
+        Ok(vec![ColorPresentation {
+            text_edit: Some(TextEdit::new(params.range, label.clone())),
+            label,
+            additional_text_edits: None,
+        }])
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        self.prepare_document(&params.text_document_position.text_document.uri)
+            .await;
+        let reference_list = || -> Option<Vec<Location>> {
+            let uri = params.text_document_position.text_document.uri;
+            let ast = self.ast_map.get(&uri.to_string())?;
+            let rope = self.document_map.get(&uri.to_string())?;
+
+            let position = params.text_document_position.position;
+            let offset = position_to_offset(&rope, position);
+            let reference_list = get_reference(&ast, offset, false);
+            let ret = reference_list
+                .into_iter()
+                .filter_map(|(_, range)| {
+                    let start_position = offset_to_position(range.start, &rope)?;
+                    let end_position = offset_to_position(range.end, &rope)?;
+
+                    let range = Range::new(start_position, end_position);
+
+                    Some(Location::new(uri.clone(), range))
+                })
+                .collect::<Vec<_>>();
+            Some(ret)
+        }();
+        Ok(reference_list)
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        self.prepare_document(&params.text_document.uri).await;
+        let uri = params.text_document.uri;
+        let ranges = self
+            .document_map
+            .get(uri.as_str())
+            .map(|rope| folding_ranges(&rope.to_string()));
+        Ok(ranges)
+    }
+
+    async fn inline_value(&self, params: InlineValueParams) -> Result<Option<Vec<InlineValue>>> {
+        self.prepare_document(&params.text_document.uri).await;
+        let inline_values = || -> Option<Vec<InlineValue>> {
+            let uri = params.text_document.uri;
+            let ast = self.ast_map.get(uri.as_str())?;
+            let rope = self.document_map.get(uri.as_str())?;
+            let frame = position_to_offset(&rope, params.context.stopped_location.start);
+            let range = position_to_offset(&rope, params.range.start)
+                ..position_to_offset(&rope, params.range.end);
+            let inline_values = get_variables(&ast, frame, &range)
+                .into_iter()
+                .filter_map(|(name, span)| {
+                    let start_position = offset_to_position(span.start, &rope)?;
+                    let end_position = offset_to_position(span.end, &rope)?;
+                    Some(InlineValue::VariableLookup(InlineValueVariableLookup {
+                        range: Range::new(start_position, end_position),
+                        variable_name: Some(name),
+                        case_sensitive_lookup: true,
+                    }))
+                })
+                .collect();
+            Some(inline_values)
+        }();
+        Ok(inline_values)
+    }
+
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> Result<Option<Vec<SelectionRange>>> {
+        self.prepare_document(&params.text_document.uri).await;
+        let uri = params.text_document.uri;
+        let index = self.node_index_map.get(uri.as_str());
+        let rope = match self.document_map.get(uri.as_str()) {
+            Some(rope) => rope,
+            None => return Ok(None),
+        };
+        // One chain per position, in the order the client sent them, even when a position
+        // resolves to nothing.
+        let selection_ranges = params
+            .positions
+            .into_iter()
+            .map(|position| {
+                let spans = || -> Option<Vec<Range>> {
+                    let index = index.as_ref()?;
+                    let offset = position_to_offset(&rope, position);
+                    get_selection_spans(index, offset)
+                        .into_iter()
+                        .map(|span| {
+                            let start_position = offset_to_position(span.start, &rope)?;
+                            let end_position = offset_to_position(span.end, &rope)?;
+                            Some(Range::new(start_position, end_position))
+                        })
+                        .collect()
+                }()
+                .unwrap_or_default();
+                spans
+                    .into_iter()
+                    .fold(None, |parent, range| {
+                        Some(SelectionRange {
+                            range,
+                            parent: parent.map(Box::new),
+                        })
+                    })
+                    .unwrap_or(SelectionRange {
+                        range: Range::new(position, position),
+                        parent: None,
+                    })
+            })
+            .collect::<Vec<_>>();
+        Ok(Some(selection_ranges))
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        self.prepare_document(&params.text_document.uri).await;
+        self.log_trace("semantic_token_full", None).await;
+        let tokens = self.full_semantic_tokens(&params.text_document.uri);
+        Ok(tokens.map(SemanticTokensResult::Tokens))
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensFullDeltaResult>> {
+        self.prepare_document(&params.text_document.uri).await;
+        self.log_trace("semantic_token_full_delta", None).await;
+        let uri = params.text_document.uri;
+        let previous = self
+            .semantic_token_result_map
+            .get(uri.as_str())
+            .filter(|previous| previous.0 == params.previous_result_id)
+            .map(|previous| previous.1.clone());
+        let tokens = match self.full_semantic_tokens(&uri) {
+            Some(tokens) => tokens,
+            None => return Ok(None),
+        };
+        // Without the tokens the client holds, the best that can be sent is all of them
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return Ok(Some(SemanticTokensFullDeltaResult::Tokens(tokens))),
+        };
+        Ok(Some(SemanticTokensFullDeltaResult::TokensDelta(
+            SemanticTokensDelta {
+                edits: semantic_token_edits(&previous, &tokens.data),
+                result_id: tokens.result_id,
+            },
+        )))
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> Result<Option<SemanticTokensRangeResult>> {
+        self.prepare_document(&params.text_document.uri).await;
+        if !self.config.read().unwrap().features.semantic_tokens {
+            return Ok(None);
+        }
+        let uri = params.text_document.uri.to_string();
+        let semantic_tokens = || -> Option<Vec<SemanticToken>> {
+            let rope = self.document_map.get(&uri)?;
+            let range_start = position_to_offset(&rope, params.range.start);
+            let range_end = position_to_offset(&rope, params.range.end);
+            if range_start > range_end {
+                return None;
+            }
+            let mut im_complete_tokens = self
+                .semantic_token_map
+                .get(&uri)?
+                .iter()
+                .filter(|token| token.start >= range_start && token.start < range_end)
+                .cloned()
+                .collect::<Vec<_>>();
+            if let Some(ast) = self.ast_map.get(&uri) {
+                im_complete_tokens.extend(
+                    semantic_token_from_ast(&ast)
+                        .into_iter()
+                        .filter(|token| token.start >= range_start && token.start < range_end),
+                );
+            }
+            im_complete_tokens.sort_by_key(|a| a.start);
+            let mut pre_line = 0;
+            let mut pre_start = 0;
+            let semantic_tokens = im_complete_tokens
+                .iter()
+                .filter_map(|token| {
+                    let line = rope.try_byte_to_line(token.start).ok()? as u32;
+                    let first = rope.try_line_to_char(line as usize).ok()? as u32;
+                    let start = rope.try_byte_to_char(token.start).ok()? as u32 - first;
+                    let ret = Some(SemanticToken {
+                        delta_line: line - pre_line,
+                        delta_start: if start >= pre_start {
+                            start - pre_start
+                        } else {
+                            start
+                        },
+                        length: token.length as u32,
+                        token_type: token.token_type as u32,
+                        token_modifiers_bitset: token.token_modifiers,
+                    });
+                    pre_line = line;
+                    pre_start = start;
+                    ret
+                })
+                .collect::<Vec<_>>();
+            Some(semantic_tokens)
+        }();
+        if let Some(semantic_token) = semantic_tokens {
+            return Ok(Some(SemanticTokensRangeResult::Tokens(SemanticTokens {
+                result_id: None,
+                data: semantic_token,
+            })));
+        }
+        Ok(None)
+    }
+
+    async fn inlay_hint(
+        &self,
+        params: tower_lsp::lsp_types::InlayHintParams,
+    ) -> Result<Option<Vec<InlayHint>>> {
+        self.prepare_document(&params.text_document.uri).await;
+        self.log_trace("inlay hint", None).await;
+        if !self.config.read().unwrap().features.inlay_hints {
+            return Ok(None);
+        }
+        Ok(self.inlay_hints(&params.text_document.uri))
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        if !self.config.read().unwrap().features.completion {
+            return Ok(None);
+        }
+        let uri = params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        self.prepare_document(&uri).await;
+        let snippet_support = self.snippet_support();
+        let label_details_support = self.label_details_support();
+        let max_completions = self.config.read().unwrap().completion.max_completions;
+        let completions = || -> Option<CompletionList> {
+            let rope = self.document_map.get(&uri.to_string())?;
+            // Documents that never parsed still get keywords and the closing bracket
+            let no_ast = HashMap::new();
+            let ast = self.ast_map.get(&uri.to_string());
+            let ast = ast.as_deref().unwrap_or(&no_ast);
+            let offset = position_to_offset(&rope, position);
+            // Completing the callee of an existing call, the arguments are already there
+            let before_call = rope.get_chars_at(offset).is_some_and(|mut chars| {
+                chars.find(|c| !c.is_alphanumeric() && *c != '_') == Some('(')
+            });
+            let query = typed_prefix(&rope, offset);
+            let expects_bool = expects_bool(
+                ast,
+                &rope.slice(..offset - query.chars().count()).to_string(),
+            );
+            let completions = completion(ast, offset);
+            let file_name = uri
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .unwrap_or_default()
+                .to_string();
+            let mut ret = Vec::with_capacity(completions.len());
+            // Definitions in the document take precedence over built-ins of the same name
+            for builtin in self.builtins.read().unwrap().iter() {
+                if completions.contains_key(&builtin.name) {
+                    continue;
+                }
+                let (insert_text, insert_text_format) = if snippet_support && !before_call {
+                    (
+                        call_snippet(&builtin.name, &builtin.params),
+                        InsertTextFormat::SNIPPET,
+                    )
+                } else {
+                    (builtin.name.clone(), InsertTextFormat::PLAIN_TEXT)
+                };
+                let mut item = CompletionItem {
+                    label: builtin.name.clone(),
+                    kind: Some(completion_kind(SymbolKind::FUNCTION)),
+                    documentation: Some(Documentation::MarkupContent(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: builtin.doc.clone(),
+                    })),
+                    insert_text: Some(insert_text),
+                    insert_text_format: Some(insert_text_format),
+                    ..Default::default()
+                };
+                set_function_details(
+                    &mut item,
+                    &builtin.params,
+                    "built-in",
+                    label_details_support,
+                );
+                ret.push(item);
+            }
+            for (_, item) in completions {
+                let kind = item.symbol_kind().map(completion_kind);
+                match item {
+                    crate::completion::ImCompleteCompletionItem::Variable(var) => {
+                        ret.push(CompletionItem {
+                            label: var.clone(),
+                            insert_text: Some(var.clone()),
+                            kind,
+                            detail: Some(var),
+                            ..Default::default()
+                        });
+                    }
+                    crate::completion::ImCompleteCompletionItem::Function(name, args) => {
+                        let (insert_text, insert_text_format) = if snippet_support && !before_call {
+                            (call_snippet(&name, &args), InsertTextFormat::SNIPPET)
+                        } else {
+                            (name.clone(), InsertTextFormat::PLAIN_TEXT)
+                        };
+                        let mut item = CompletionItem {
+                            label: name.clone(),
+                            kind,
+                            insert_text: Some(insert_text),
+                            insert_text_format: Some(insert_text_format),
+                            // The doc comment is only looked up once the item is selected
+                            data: serde_json::to_value(CompletionData {
+                                uri: uri.clone(),
+                                function: name,
+                            })
+                            .ok(),
+                            ..Default::default()
+                        };
+                        set_function_details(&mut item, &args, &file_name, label_details_support);
+                        ret.push(item);
+                    }
+                    crate::completion::ImCompleteCompletionItem::Keyword(keyword) => {
+                        ret.push(CompletionItem {
+                            label: keyword,
+                            kind: Some(CompletionItemKind::KEYWORD),
+                            ..Default::default()
+                        });
+                    }
+                }
+            }
+            ret.retain(|item| fuzzy_match(&query, &item.label));
+            // The whole word under the cursor is replaced, what was typed before the cursor as
+            // well as the rest of the word when completing in the middle of it. At the start of
+            // a word nothing was typed yet, the completion goes in front of it.
+            let word_start = offset - query.chars().count();
+            let word_end = if query.is_empty() {
+                offset
+            } else {
+                offset
+                    + rope
+                        .chars_at(offset)
+                        .take_while(|c| c.is_alphanumeric() || *c == '_')
+                        .count()
+            };
+            let word_range = Range::new(
+                offset_to_position(word_start, &rope)?,
+                offset_to_position(word_end, &rope)?,
+            );
+            // Where a boolean is expected `true` and `false` sort first, then recently used
+            // symbols, most recent on top, then the ones starting with what was typed, the rest
+            // by label
+            let recent_symbols = self.recent_symbols.lock().unwrap();
+            for item in ret.iter_mut() {
+                let is_bool = item.label == "true" || item.label == "false";
+                item.sort_text = Some(match recent_symbols.recency(&item.label) {
+                    _ if expects_bool && is_bool => format!("0{}", item.label),
+                    Some(recency) => format!("1{:02}{}", recency, item.label),
+                    None if item.label.starts_with(&query) => format!("2{}", item.label),
+                    None => format!("3{}", item.label),
+                });
+                let new_text = item.insert_text.take().unwrap_or(item.label.clone());
+                item.text_edit = Some(CompletionTextEdit::Edit(TextEdit::new(
+                    word_range, new_text,
+                )));
+                item.command = Some(Command::new(
+                    "record completion".to_string(),
+                    "gen.recordCompletion".to_string(),
+                    Some(vec![Value::String(item.label.clone())]),
+                ));
+            }
+            if query.is_empty() {
+                ret.extend(closing_bracket_completion(&rope, offset));
+            }
+            // `completion` collects into a `HashMap`, sort so the order is stable between requests
+            ret.sort_by(|a, b| {
+                a.sort_text
+                    .cmp(&b.sort_text)
+                    .then_with(|| a.label.cmp(&b.label))
+            });
+            // The client asks again as the user types, with a narrower query
+            let is_incomplete = ret.len() > max_completions;
+            ret.truncate(max_completions);
+            Some(CompletionList {
+                is_incomplete,
+                items: ret,
+            })
+        }();
+        Ok(completions.map(CompletionResponse::List))
+    }
+
+    async fn completion_resolve(&self, mut item: CompletionItem) -> Result<CompletionItem> {
+        let documentation = || -> Option<Documentation> {
+            let data = serde_json::from_value::<CompletionData>(item.data.clone()?).ok()?;
+            let ast = self.ast_map.get(data.uri.as_str())?;
+            let rope = self.document_map.get(data.uri.as_str())?;
+            let function = ast.get(&data.function)?;
+            let doc = doc_comment(&rope, function.name.1.start)?;
+            Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: doc,
+            }))
+        }();
+        if documentation.is_some() {
+            item.documentation = documentation;
+        }
+        Ok(item)
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        self.prepare_document(&params.text_document_position_params.text_document.uri)
+            .await;
+        let item = || -> Option<CallHierarchyItem> {
+            let uri = params.text_document_position_params.text_document.uri;
+            let ast = self.ast_map.get(uri.as_str())?;
+            let rope = self.document_map.get(uri.as_str())?;
+            let offset = position_to_offset(&rope, params.text_document_position_params.position);
+            let func = get_function_at(&ast, offset)?;
+            to_call_hierarchy_item(uri.clone(), func, &rope)
+        }();
+        Ok(item.map(|item| vec![item]))
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        self.prepare_document(&params.item.uri).await;
+        let calls = || -> Option<Vec<CallHierarchyIncomingCall>> {
+            let uri = params.item.uri;
+            let ast = self.ast_map.get(uri.as_str())?;
+            let rope = self.document_map.get(uri.as_str())?;
+            let mut calls = ast
+                .values()
+                .filter_map(|caller| {
+                    let from_ranges = get_calls(&ast, caller)
+                        .into_iter()
+                        .filter(|(name, _)| *name == params.item.name)
+                        .map(|(_, span)| span_to_range(&span, &rope))
+                        .collect::<Option<Vec<_>>>()?;
+                    if from_ranges.is_empty() {
+                        return None;
+                    }
+                    Some(CallHierarchyIncomingCall {
+                        from: to_call_hierarchy_item(uri.clone(), caller, &rope)?,
+                        from_ranges,
+                    })
+                })
+                .collect::<Vec<_>>();
+            calls.sort_by_key(|call| call.from.range.start);
+            Some(calls)
+        }();
+        Ok(calls)
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        self.prepare_document(&params.item.uri).await;
+        let calls = || -> Option<Vec<CallHierarchyOutgoingCall>> {
+            let uri = params.item.uri;
+            let ast = self.ast_map.get(uri.as_str())?;
+            let rope = self.document_map.get(uri.as_str())?;
+            let caller = ast.get(&params.item.name)?;
+            // One entry per callee, in the order they are first called
+            let mut calls: Vec<CallHierarchyOutgoingCall> = vec![];
+            for (name, span) in get_calls(&ast, caller) {
+                let range = span_to_range(&span, &rope)?;
+                match calls.iter_mut().find(|call| call.to.name == name) {
+                    Some(call) => call.from_ranges.push(range),
+                    None => calls.push(CallHierarchyOutgoingCall {
+                        to: to_call_hierarchy_item(uri.clone(), ast.get(&name)?, &rope)?,
+                        from_ranges: vec![range],
+                    }),
+                }
+            }
+            Some(calls)
+        }();
+        Ok(calls)
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let uri = params.text_document.uri;
+        self.prepare_document(&uri).await;
+        let rope = match self.document_map.get(uri.as_str()) {
+            Some(rope) => rope,
+            None => return Ok(None),
+        };
+        let offset = position_to_offset(&rope, params.position);
+        match token_at(&rope.to_string(), offset) {
+            Some((token, _)) if is_keyword(&token.to_string()) => Err(Error::invalid_params(
+                format!("`{}` is a keyword and cannot be renamed", token),
+            )),
+            Some((Token::Ident(_), _)) => {
+                let range = self.ast_map.get(uri.as_str()).and_then(|ast| {
+                    let (_, span) = get_reference(&ast, offset, true)
+                        .into_iter()
+                        .find(|(_, span)| span.start <= offset && offset < span.end)?;
+                    let start_position = offset_to_position(span.start, &rope)?;
+                    let end_position = offset_to_position(span.end, &rope)?;
+                    Some(Range::new(start_position, end_position))
+                });
+                Ok(range.map(PrepareRenameResponse::Range))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        if is_keyword(&params.new_name) {
+            return Err(Error::invalid_params(format!(
+                "`{}` is a keyword and cannot be used as a name",
+                params.new_name
+            )));
+        }
+        if self.is_desynced(&params.text_document_position.text_document.uri) {
+            return Ok(None);
+        }
+        self.prepare_document(&params.text_document_position.text_document.uri)
+            .await;
+        let new_name = params.new_name.clone();
+        let renamed = || -> Option<(WorkspaceEdit, bool)> {
+            let uri = params.text_document_position.text_document.uri;
+            let ast = self.ast_map.get(&uri.to_string())?;
+            let rope = self.document_map.get(&uri.to_string())?;
+
+            let position = params.text_document_position.position;
+            let offset = position_to_offset(&rope, position);
+            let reference_list = get_reference(&ast, offset, true);
+            let new_name = params.new_name;
+            // Wherever the symbol appears, another symbol already named `new_name` would end up
+            // shadowing it or shadowed by it
+            let collides = reference_list.iter().any(|(name, span)| {
+                visible_names(&ast, span.start)
+                    .iter()
+                    .any(|visible| *visible == new_name && visible != name)
+            });
+            if !reference_list.is_empty() {
+                let edit_list = reference_list
+                    .into_iter()
+                    .filter_map(|(_, range)| {
+                        let start_position = offset_to_position(range.start, &rope)?;
+                        let end_position = offset_to_position(range.end, &rope)?;
+                        Some(TextEdit::new(
+                            Range::new(start_position, end_position),
+                            new_name.clone(),
+                        ))
+                    })
+                    .collect::<Vec<_>>();
+                Some((self.document_edit(uri, edit_list), collides))
+            } else {
+                None
+            }
+        }();
+        let (workspace_edit, collides) = match renamed {
+            Some(renamed) => renamed,
+            None => return Ok(None),
+        };
+        if collides {
+            let message = format!("`{}` is already used in the scope of this symbol", new_name);
+            if self.config.read().unwrap().rename.refuse_collisions {
+                return Err(Error {
+                    code: ErrorCode::ServerError(REQUEST_FAILED),
+                    message,
+                    data: None,
+                });
+            }
+            self.client
+                .show_message(MessageType::WARNING, format!("{}, renamed anyway", message))
+                .await;
+        }
+        Ok(Some(workspace_edit))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        self.prepare_document(&uri).await;
+        if self.is_desynced(&uri) {
+            return Ok(None);
+        }
+        // Kinds are hierarchical, asking for `refactor` includes `refactor.rewrite`
+        let rewrite = CodeActionKind::REFACTOR_REWRITE;
+        let wants_refactors = params.context.only.as_ref().is_none_or(|only| {
+            only.iter().any(|kind| {
+                rewrite == *kind || rewrite.as_str().starts_with(&format!("{}.", kind.as_str()))
+            })
+        });
+        let mut actions = params
+            .context
+            .diagnostics
+            .into_iter()
+            .flat_map(|diagnostic| {
+                let fixes = diagnostic
+                    .data
+                    .clone()
+                    .and_then(|data| serde_json::from_value::<Vec<QuickFix>>(data).ok())
+                    .unwrap_or_default();
+                let uri = uri.clone();
+                fixes.into_iter().map(move |fix| {
+                    CodeActionOrCommand::CodeAction(CodeAction {
+                        title: fix.title,
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        diagnostics: Some(vec![diagnostic.clone()]),
+                        edit: Some(WorkspaceEdit::new(HashMap::from([(
+                            uri.clone(),
+                            fix.edits,
+                        )]))),
+                        ..Default::default()
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        if wants_refactors {
+            actions.extend(self.sort_functions_action(&uri));
+            actions.extend(self.wrap_in_block_action(&uri, params.range));
+        }
+        Ok(Some(actions))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        self.prepare_document(&params.text_document.uri).await;
+        let uri = params.text_document.uri;
+        let version = self.version_map.get(uri.as_str()).map(|version| *version);
+        // Outline views ask again and again for the same version
+        let cached = self
+            .document_symbol_map
+            .get(uri.as_str())
+            .filter(|cached| cached.0 == version)
+            .map(|cached| cached.1.clone());
+        let document_symbols = cached.or_else(|| {
+            let ast = self.ast_map.get(uri.as_str())?;
+            let rope = self.document_map.get(uri.as_str())?;
+            let document_symbols = get_document_symbols(&ast, &rope)
+                .into_iter()
+                .map(|symbol| to_document_symbol(symbol, &rope))
+                .collect::<Option<Vec<_>>>()?;
+            self.document_symbol_map
+                .insert(uri.to_string(), (version, document_symbols.clone()));
+            Some(document_symbols)
+        });
+        let (token, document_symbols) = match (
+            params.partial_result_params.partial_result_token,
+            document_symbols,
+        ) {
+            (Some(token), Some(document_symbols)) => (token, document_symbols),
+            (_, document_symbols) => {
+                return Ok(document_symbols.map(DocumentSymbolResponse::Nested))
+            }
+        };
+        // Once partial results are sent they make up the whole result, the response itself has
+        // to be empty
+        for chunk in document_symbols.chunks(PARTIAL_RESULT_CHUNK_SIZE) {
+            self.client
+                .send_notification::<DocumentSymbolPartialResult>(
+                    DocumentSymbolPartialResultParams {
+                        token: token.clone(),
+                        value: chunk.to_vec(),
+                    },
+                )
+                .await;
+        }
+        Ok(Some(DocumentSymbolResponse::Nested(vec![])))
+    }
+
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        let uri = params.text_document.uri;
+        self.prepare_document(&uri).await;
+        let links = || -> Option<Vec<DocumentLink>> {
+            let rope = self.document_map.get(uri.as_str())?;
+            let links = symbol_links(&rope.to_string())
+                .into_iter()
+                .filter_map(|(symbol, span)| {
+                    Some(DocumentLink {
+                        range: span_to_range(&span, &rope)?,
+                        // Looking the symbol up waits for the link to be followed
+                        target: None,
+                        tooltip: Some(format!("Go to `{}`", symbol)),
+                        data: serde_json::to_value(DocumentLinkData {
+                            uri: uri.clone(),
+                            symbol,
+                        })
+                        .ok(),
+                    })
+                })
+                .collect();
+            Some(links)
+        }();
+        Ok(links)
+    }
+
+    async fn document_link_resolve(&self, mut link: DocumentLink) -> Result<DocumentLink> {
+        let target = || -> Option<Url> {
+            let data = serde_json::from_value::<DocumentLinkData>(link.data.clone()?).ok()?;
+            let location = self.symbol_definition(&data.uri, &data.symbol)?;
+            // Clients open `file:` links at the `#L<line>,<column>` fragment, counted from 1
+            let mut target = location.uri;
+            target.set_fragment(Some(&format!(
+                "L{},{}",
+                location.range.start.line + 1,
+                location.range.start.character + 1
+            )));
+            Some(target)
+        }();
+        if target.is_some() {
+            link.target = target;
+        }
+        Ok(link)
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri;
+        self.prepare_document(&uri).await;
+        let formatted = match self.format_document(&uri, &params.options).await? {
+            Some(formatted) => formatted,
+            None => return Ok(None),
+        };
+        let minimal_edits = self.config.read().unwrap().formatter.minimal_edits;
+        let edits = || -> Option<Vec<TextEdit>> {
+            let rope = self.document_map.get(uri.as_str())?;
+            if minimal_edits {
+                if let Some(edits) = line_edits(&rope.to_string(), &formatted) {
+                    return Some(edits);
+                }
+            }
+            let end_position = offset_to_position(rope.len_chars(), &rope)?;
+            Some(vec![TextEdit::new(
+                Range::new(Position::new(0, 0), end_position),
+                formatted,
+            )])
+        }();
+        Ok(edits.map(|edits| self.keep_bom(&uri, edits)))
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        if !self.config.read().unwrap().features.formatting
+            || params.ch != "\n"
+            || self.is_desynced(&params.text_document_position.text_document.uri)
+        {
+            return Ok(None);
+        }
+        let uri = params.text_document_position.text_document.uri;
+        self.prepare_document(&uri).await;
+        let edits = self.document_map.get(uri.as_str()).map(|rope| {
+            on_enter(
+                &rope.to_string(),
+                params.text_document_position.position.line as usize,
+                &params.options,
+            )
+        });
+        Ok(edits)
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let uri = params.text_document.uri;
+        self.prepare_document(&uri).await;
+        let formatted = match self.format_document(&uri, &params.options).await? {
+            Some(formatted) => Rope::from_str(&formatted),
+            None => return Ok(None),
+        };
+        let edits = || -> Option<Vec<TextEdit>> {
+            let rope = self.document_map.get(uri.as_str())?;
+            // The formatter keeps line numbers stable, so the selected lines can be swapped in
+            // as a whole
+            let start_line = params.range.start.line as usize;
+            let end_line = (params.range.end.line as usize + 1).min(rope.len_lines());
+            if start_line >= end_line {
+                return None;
+            }
+            let start = rope.line_to_char(start_line);
+            let end = rope.line_to_char(end_line);
+            let new_text = formatted
+                .slice(formatted.line_to_char(start_line)..formatted.line_to_char(end_line))
+                .to_string();
+            Some(vec![TextEdit::new(
+                Range::new(
+                    offset_to_position(start, &rope)?,
+                    offset_to_position(end, &rope)?,
+                ),
+                new_text,
+            )])
+        }();
+        Ok(edits)
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        let query = params.query.to_lowercase();
+        let token = params.partial_result_params.partial_result_token;
+        let uris = self
+            .ast_map
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect::<Vec<_>>();
+        let mut symbols = vec![];
+        for uri in uris {
+            let chunk = || -> Option<Vec<SymbolInformation>> {
+                let ast = self.ast_map.get(&uri)?;
+                let rope = self.document_map.get(&uri)?;
+                let url = Url::parse(&uri).ok()?;
+                let chunk = ast
+                    .values()
+                    .filter(|v| v.name.0.to_lowercase().contains(&query))
+                    .filter_map(|v| {
+                        let start_position = offset_to_position(v.name.1.start, &rope)?;
+                        let end_position = offset_to_position(v.name.1.end, &rope)?;
+                        #[allow(deprecated)]
+                        Some(SymbolInformation {
+                            name: v.name.0.clone(),
+                            kind: SymbolKind::FUNCTION,
+                            tags: None,
+                            deprecated: None,
+                            location: Location::new(
+                                url.clone(),
+                                Range::new(start_position, end_position),
+                            ),
+                            container_name: None,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                Some(chunk)
+            }()
+            .unwrap_or_default();
+            if chunk.is_empty() {
+                continue;
+            }
+            // Once partial results are sent they make up the whole result, the response itself
+            // has to be empty
+            match &token {
+                Some(token) => {
+                    if let Ok(value) = serde_json::to_value(&chunk) {
+                        self.client
+                            .send_notification::<PartialResult>(PartialResultParams {
+                                token: token.clone(),
+                                value,
+                            })
+                            .await;
+                    }
+                }
+                None => symbols.extend(chunk),
+            }
+        }
+        Ok(Some(symbols))
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        self.log_message(MessageType::INFO, "configuration changed!")
+            .await;
+        let empty = match &params.settings {
+            Value::Null => true,
+            Value::Object(settings) => settings.is_empty(),
+            _ => false,
+        };
+        // Clients using the pull model only notify that something changed, the settings
+        // themselves have to be asked for
+        let config = if !empty {
+            Config::from_settings(Some(&params.settings))
+        } else if self.configuration_support() {
+            match self.pull_config().await {
+                Ok(config) => config,
+                Err(err) => {
+                    self.log_message(
+                        MessageType::ERROR,
+                        format!("failed to pull the configuration: {}", err.message),
+                    )
+                    .await;
+                    return;
+                }
+            }
+        } else {
+            return;
+        };
+        self.apply_config(config).await;
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        self.log_message(MessageType::INFO, "workspace folders changed!")
+            .await;
+        for folder in params.event.removed {
+            if let Some(root) = uri_to_path(&folder.uri) {
+                self.manifests
+                    .write()
+                    .unwrap()
+                    .retain(|manifest| manifest.root != root);
+            }
+        }
+        for folder in params.event.added {
+            self.load_manifest(&folder.uri).await;
+        }
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        self.log_message(MessageType::INFO, "watched files have changed!")
+            .await;
+        // Documents read from disk are read again on their next request
+        for change in params.changes {
+            if self.disk_documents.remove(change.uri.as_str()).is_some() {
+                self.forget_document(change.uri.as_str());
+            }
+        }
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        if params.command == "gen.matchBrace" {
+            let params: UriPositionParams = command_argument(&params, "{ uri, position }")?;
+            self.prepare_document(&params.uri).await;
+            let position = self.match_brace(params);
+            return Ok(position.and_then(|position| serde_json::to_value(position).ok()));
+        }
+        if params.command == "gen.recordCompletion" {
+            let name: String = command_argument(&params, "a symbol name")?;
+            self.record_recent_symbol(&name);
+            return Ok(None);
+        }
+        if params.command == "gen.enclosingFunction" {
+            let params: UriPositionParams = command_argument(&params, "{ uri, position }")?;
+            self.prepare_document(&params.uri).await;
+            let range = self.enclosing_function(params);
+            return Ok(range.and_then(|range| serde_json::to_value(range).ok()));
+        }
+        if params.command == "gen.peekDefinition" {
+            let params: UriPositionParams = command_argument(&params, "{ uri, position }")?;
+            self.prepare_document(&params.uri).await;
+            let location = self.definition_location(params);
+            return Ok(location.and_then(|location| serde_json::to_value(location).ok()));
+        }
+        if params.command == "gen.toggleComment" {
+            let params: UriRangeParams = command_argument(&params, "{ uri, range }")?;
+            self.prepare_document(&params.uri).await;
+            let edits = self.toggle_comment(params);
+            return Ok(edits.and_then(|edits| serde_json::to_value(edits).ok()));
+        }
+        if params.command == "gen.fixAll" {
+            let params: UriParams = command_argument(&params, "{ uri }")?;
+            self.prepare_document(&params.uri).await;
+            let (edit, applied) = self.fix_all(&params.uri);
+            self.log_message(
+                MessageType::INFO,
+                format!("{} fixes applied to {}", applied, params.uri),
+            )
+            .await;
+            return self.apply_or_return_edit(edit).await;
+        }
+        if params.command == "gen.documentStats" {
+            let params: UriParams = command_argument(&params, "{ uri }")?;
+            self.prepare_document(&params.uri).await;
+            let stats = self.document_stats(&params.uri);
+            return Ok(stats.and_then(|stats| serde_json::to_value(stats).ok()));
+        }
+        if params.command == "gen.normalizeLineEndings" {
+            let params: UriParams = command_argument(&params, "{ uri }")?;
+            self.prepare_document(&params.uri).await;
+            let edits = self.normalize_line_endings(&params.uri);
+            return Ok(edits.and_then(|edits| serde_json::to_value(edits).ok()));
+        }
+        if params.command == "gen.reindexWorkspace" {
+            if self
+                .confirm("Discard all cached analyses and re-analyze the open documents?")
+                .await
+            {
+                self.reindex().await;
+            }
+            return Ok(None);
+        }
+
+        self.log_message(MessageType::INFO, "command executed!")
+            .await;
+
+        self.apply_or_return_edit(WorkspaceEdit::default()).await
+    }
+}
+/// Deserializes the single argument of a command, failing with `InvalidParams` when it is
+/// missing or doesn't have the `expected` shape.
+fn command_argument<T: DeserializeOwned>(
+    params: &ExecuteCommandParams,
+    expected: &str,
+) -> Result<T> {
+    let invalid = || {
+        Error::invalid_params(format!(
+            "{} expects a single argument: {}",
+            params.command, expected
+        ))
+    };
+    match params.arguments.as_slice() {
+        [argument] => serde_json::from_value(argument.clone()).map_err(|_| invalid()),
+        _ => Err(invalid()),
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize, Serialize)]
+struct InlayHintParams {
+    path: String,
+}
+
+#[allow(dead_code)]
+enum CustomNotification {}
+impl Notification for CustomNotification {
+    type Params = InlayHintParams;
+    const METHOD: &'static str = "custom/notification";
+}
+/// The inlay hints of a document, pushed after it has been analyzed.
+#[derive(Debug, Deserialize, Serialize)]
+struct InlayHintsNotificationParams {
+    uri: Url,
+    hints: Vec<InlayHint>,
+}
+
+/// A chunk of document symbols, streamed with `$/progress` under the `partialResultToken` the
+/// client sent along with the request.
+#[derive(Debug, Deserialize, Serialize)]
+struct DocumentSymbolPartialResultParams {
+    token: ProgressToken,
+    value: Vec<DocumentSymbol>,
+}
+
+enum DocumentSymbolPartialResult {}
+impl Notification for DocumentSymbolPartialResult {
+    type Params = DocumentSymbolPartialResultParams;
+    const METHOD: &'static str = "$/progress";
+}
+
+enum InlayHintsNotification {}
+impl Notification for InlayHintsNotification {
+    type Params = InlayHintsNotificationParams;
+    const METHOD: &'static str = "gen/inlayHints";
+}
+/// A notification about the analysis of a document, which must wait for `initialized`.
+#[derive(Debug)]
+enum DocumentNotification {
+    Diagnostics {
+        uri: Url,
+        diagnostics: Vec<Diagnostic>,
+        version: Option<i32>,
+    },
+    InlayHints(InlayHintsNotificationParams),
+}
+
+/// A `$/progress` notification carrying a chunk of a partial result.
+#[derive(Debug, Deserialize, Serialize)]
+struct PartialResultParams {
+    token: ProgressToken,
+    value: Value,
+}
+
+enum PartialResult {}
+impl Notification for PartialResult {
+    type Params = PartialResultParams;
+    const METHOD: &'static str = "$/progress";
+}
+/// References inside one function, `container` being its name.
+#[derive(Debug, Serialize)]
+struct ReferenceGroup {
+    container: Option<String>,
+    locations: Vec<Location>,
+}
+/// A fix carried in a diagnostic's `data`, offered back as a quick fix code action.
+#[derive(Debug, Deserialize, Serialize)]
+struct QuickFix {
+    title: String,
+    edits: Vec<TextEdit>,
+}
+/// Sizes of a document, as answered to `gen.documentStats`.
+#[derive(Debug, Serialize)]
+struct DocumentStats {
+    lines: usize,
+    /// Functions and the `let` bindings in them, as listed by `textDocument/documentSymbol`.
+    symbols: usize,
+    functions: usize,
+    /// Diagnostics of the last analysis, including the ones `lint.maxDiagnostics` cut.
+    diagnostics: usize,
+}
+#[derive(Debug, Deserialize)]
+struct UriParams {
+    uri: Url,
+}
+#[derive(Debug, Deserialize)]
+struct UriPositionParams {
+    uri: Url,
+    position: Position,
+}
+/// What resolving a function completion needs to find its definition again.
+#[derive(Debug, Deserialize, Serialize)]
+struct CompletionData {
+    uri: Url,
+    function: String,
+}
+/// The answer to `gen/symbolAt`.
+#[derive(Debug, Serialize)]
+struct SymbolAt {
+    name: String,
+    kind: SymbolKind,
+    /// The inferred type of a variable, the signature of a function.
+    r#type: Option<String>,
+    /// Built-ins are defined nowhere.
+    definition: Option<Location>,
+}
+/// What resolving a document link needs to look its symbol up.
+#[derive(Debug, Deserialize, Serialize)]
+struct DocumentLinkData {
+    uri: Url,
+    symbol: String,
+}
+#[derive(Debug, Deserialize)]
+struct FormatStringParams {
+    text: String,
+    options: FormattingOptions,
+}
+#[derive(Debug, Deserialize)]
+struct UriRangeParams {
+    uri: Url,
+    range: Range,
+}
+struct TextDocumentItem {
+    uri: Url,
+    text: String,
+    version: i32,
+}
+impl<C: LspClient> Backend<C> {
+    /// Like `textDocument/references`, with the locations grouped by their enclosing function,
+    /// in the order the functions first reference the symbol.
+    async fn references_grouped(&self, params: ReferenceParams) -> Result<Vec<ReferenceGroup>> {
+        self.prepare_document(&params.text_document_position.text_document.uri)
+            .await;
+        let groups = || -> Option<Vec<ReferenceGroup>> {
+            let uri = params.text_document_position.text_document.uri;
+            let ast = self.ast_map.get(uri.as_str())?;
+            let rope = self.document_map.get(uri.as_str())?;
+
+            let position = params.text_document_position.position;
+            let offset = position_to_offset(&rope, position);
+            let include_declaration = params.context.include_declaration;
+            let mut groups: Vec<ReferenceGroup> = vec![];
+            for (_, span) in get_reference(&ast, offset, include_declaration) {
+                let start_position = offset_to_position(span.start, &rope)?;
+                let end_position = offset_to_position(span.end, &rope)?;
+                let location = Location::new(uri.clone(), Range::new(start_position, end_position));
+                let container = get_container(&ast, span.start);
+                match groups.iter_mut().find(|group| group.container == container) {
+                    Some(group) => group.locations.push(location),
+                    None => groups.push(ReferenceGroup {
+                        container,
+                        locations: vec![location],
+                    }),
+                }
+            }
+            Some(groups)
+        }();
+        Ok(groups.unwrap_or_default())
+    }
+
+    /// Formats text that doesn't belong to any document, answering `null` like
+    /// `textDocument/formatting` does when formatting is disabled or the text has syntax errors.
+    async fn format_string(&self, params: FormatStringParams) -> Result<Option<String>> {
+        if !self.config.read().unwrap().features.formatting {
+            return Ok(None);
+        }
+        // The formatter never sees the byte order mark, so put it back where it was
+        let (text, bom) = match strip_bom(&params.text) {
+            Some(text) => (text.to_string(), true),
+            None => (params.text, false),
+        };
+        let formatted = self.format_text(text, &params.options, "the text").await?;
+        Ok(formatted.map(|formatted| {
+            if bom {
+                format!("{}{}", BOM, formatted)
+            } else {
+                formatted
+            }
+        }))
+    }
+
+    /// Pulls the configuration and applies it without waiting for
+    /// `workspace/didChangeConfiguration`, for extensions managing the settings themselves.
+    /// Answers with the configuration now in effect.
+    async fn reload_config(&self) -> Result<Config> {
+        if !self.configuration_support() {
+            return Err(Error {
+                code: ErrorCode::ServerError(REQUEST_FAILED),
+                message: "the client doesn't support workspace/configuration".to_string(),
+                data: None,
+            });
+        }
+        let config = self.pull_config().await?;
+        self.apply_config(config).await;
+        Ok(self.config.read().unwrap().clone())
+    }
+
+    /// The symbol at a position, summed up for extensions building their own tooltips. Symbols
+    /// resolve like they do for hover and go to definition, built-ins included.
+    async fn symbol_at(&self, params: UriPositionParams) -> Result<Option<SymbolAt>> {
+        self.prepare_document(&params.uri).await;
+        let symbol = || -> Option<SymbolAt> {
+            let rope = self.document_map.get(params.uri.as_str())?;
+            let offset = position_to_offset(&rope, params.position);
+            let ast = self.ast_map.get(params.uri.as_str());
+            let definition = ast
+                .as_ref()
+                .and_then(|ast| Some((ast, get_definition(ast, offset)?)));
+            let (ast, (name, span)) = match definition {
+                Some(definition) => definition,
+                None => {
+                    let (builtin, _) = self.builtin_at(&params.uri, &rope, offset)?;
+                    return Some(SymbolAt {
+                        name: builtin.name.clone(),
+                        kind: SymbolKind::FUNCTION,
+                        r#type: Some(builtin.signature()),
+                        definition: None,
+                    });
+                }
+            };
+            let location = Location::new(params.uri.clone(), span_to_range(&span, &rope)?);
+            let symbol = match ast.get(&name).filter(|func| func.name.1 == span) {
+                Some(func) => SymbolAt {
+                    name,
+                    kind: SymbolKind::FUNCTION,
+                    r#type: Some(match return_type_inference(&func.body) {
+                        Some(return_type) => format!("{} -> {}", signature(func), return_type),
+                        None => signature(func),
+                    }),
+                    definition: Some(location),
+                },
+                None => {
+                    let mut types = HashMap::new();
+                    for func in ast.values() {
+                        type_inference(&func.body, &mut types);
+                    }
+                    SymbolAt {
+                        name,
+                        kind: SymbolKind::VARIABLE,
+                        // Parameters have no inferred type
+                        r#type: types.get(&span).map(type_name),
+                        definition: Some(location),
+                    }
+                }
+            };
+            Some(symbol)
+        }();
+        Ok(symbol)
+    }
+
+    async fn set_trace(&self, params: SetTraceParams) {
+        *self.trace.write().unwrap() = params.value;
+    }
+
+    /// `window/logMessage`, dropped when the current trace level filters out `typ`.
+    async fn log_message<M: std::fmt::Display>(&self, typ: MessageType, message: M) {
+        let trace = *self.trace.read().unwrap();
+        if trace_allows(trace, typ) {
+            self.client.log_message(typ, message.to_string()).await;
+        }
+    }
+
+    /// `$/logTrace` for an internal operation on `uri`, only sent at the `verbose` level.
+    async fn trace_operation(&self, operation: &str, uri: &Url, started: Instant) {
+        if *self.trace.read().unwrap() != TraceValue::Verbose {
+            return;
+        }
+        self.client
+            .send_notification::<LogTrace>(LogTraceParams {
+                message: format!("{} {}", operation, uri),
+                verbose: Some(format!("took {:?}", started.elapsed())),
+            })
+            .await;
+    }
+
+    /// `$/logTrace`, sent only while tracing is on. `verbose` is only included at the
+    /// `verbose` level.
+    async fn log_trace(&self, message: &str, verbose: Option<String>) {
+        let trace = *self.trace.read().unwrap();
+        if trace == TraceValue::Off {
+            return;
+        }
+        self.client
+            .send_notification::<LogTrace>(LogTraceParams {
+                message: message.to_string(),
+                verbose: verbose.filter(|_| trace == TraceValue::Verbose),
+            })
+            .await;
+    }
+
+    /// Type hints for `uri`, and return type hints when `inlay_hints.return_types` is on.
+    fn inlay_hints(&self, uri: &Url) -> Option<Vec<InlayHint>> {
+        let mut hashmap = HashMap::new();
+        if let Some(ast) = self.ast_map.get(uri.as_str()) {
+            ast.iter().for_each(|(_, v)| {
+                type_inference(&v.body, &mut hashmap);
+            });
+        }
+
+        let document = self.document_map.get(uri.as_str())?;
+        let mut inlay_hint_list = hashmap
+            .into_iter()
+            .map(|(k, v)| (k.start, k.end, type_name(&v)))
+            .filter_map(|item| {
+                // let start_position = offset_to_position(item.0, document)?;
+                let end_position = offset_to_position(item.1, &document)?;
+                let inlay_hint = InlayHint {
+                    text_edits: None,
+                    tooltip: None,
+                    kind: Some(InlayHintKind::TYPE),
+                    padding_left: None,
+                    padding_right: None,
+                    data: None,
+                    position: end_position,
+                    label: InlayHintLabel::LabelParts(vec![InlayHintLabelPart {
+                        value: item.2,
+                        tooltip: None,
+                        location: Some(Location {
+                            uri: uri.clone(),
+                            range: Range {
+                                start: Position::new(0, 4),
+                                end: Position::new(0, 5),
+                            },
+                        }),
+                        command: None,
+                    }]),
+                };
+                Some(inlay_hint)
+            })
+            .collect::<Vec<_>>();
+
+        if self.config.read().unwrap().inlay_hints.return_types {
+            if let Some(ast) = self.ast_map.get(uri.as_str()) {
+                inlay_hint_list.extend(ast.values().filter_map(|v| {
+                    let return_type = return_type_inference(&v.body)?;
+                    // Parameters are plain identifiers, so the first `{` after the name opens the body
+                    let brace = v.name.1.end
+                        + document
+                            .get_chars_at(v.name.1.end)?
+                            .position(|c| c == '{')?;
+                    Some(InlayHint {
+                        text_edits: None,
+                        tooltip: None,
+                        kind: Some(InlayHintKind::TYPE),
+                        padding_left: None,
+                        padding_right: Some(true),
+                        data: None,
+                        position: offset_to_position(brace, &document)?,
+                        label: InlayHintLabel::String(format!("-> {}", return_type)),
+                    })
+                }));
+            }
+        }
+
+        Some(inlay_hint_list)
+    }
+
+    fn definition_link_support(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities
+            .text_document
+            .as_ref()
+            .and_then(|text_document| text_document.definition.as_ref())
+            .and_then(|definition| definition.link_support)
+            .unwrap_or(false)
+    }
+
+    fn document_changes_support(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.workspace_edit.as_ref())
+            .and_then(|workspace_edit| workspace_edit.document_changes)
+            .unwrap_or(false)
+    }
+
+    /// A workspace edit applying `edits` to `uri`. Clients supporting `documentChanges` get the
+    /// version the edits were computed for, so they can reject them once the document changed.
+    fn document_edit(&self, uri: Url, edits: Vec<TextEdit>) -> WorkspaceEdit {
+        if !self.document_changes_support() {
+            return WorkspaceEdit::new(HashMap::from([(uri, edits)]));
+        }
+        let version = self.version_map.get(uri.as_str()).map(|version| *version);
+        WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version },
+                edits: edits.into_iter().map(OneOf::Left).collect(),
+            }])),
+            ..Default::default()
+        }
+    }
+
+    fn code_description_support(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities
+            .text_document
+            .as_ref()
+            .and_then(|text_document| text_document.publish_diagnostics.as_ref())
+            .and_then(|publish_diagnostics| publish_diagnostics.code_description_support)
+            .unwrap_or(false)
+    }
+
+    fn apply_edit_support(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.apply_edit)
+            .unwrap_or(false)
+    }
+
+    /// Finishes a command producing `edit`: the server applies it when the client accepts
+    /// `workspace/applyEdit` requests, otherwise it becomes the command result for the client to
+    /// apply.
+    async fn apply_or_return_edit(&self, edit: WorkspaceEdit) -> Result<Option<Value>> {
+        if !self.apply_edit_support() {
+            return Ok(serde_json::to_value(edit).ok());
+        }
+        match self.client.apply_edit(edit).await {
+            Ok(res) if res.applied => self.log_message(MessageType::INFO, "applied").await,
+            Ok(_) => self.log_message(MessageType::INFO, "rejected").await,
+            Err(err) => self.log_message(MessageType::ERROR, err).await,
+        }
+        Ok(None)
+    }
+
+    fn show_message_request_support(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities
+            .window
+            .as_ref()
+            .is_some_and(|window| window.show_message.is_some())
+    }
+
+    fn watched_files_registration_support(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+            .and_then(|watched_files| watched_files.dynamic_registration)
+            .unwrap_or(false)
+    }
+
+    /// Asks the client to report changes to the files with a configured extension in the source
+    /// directories of the workspace, which documents read from disk are read again after.
+    async fn watch_files(&self) {
+        let glob = self.config.read().unwrap().file_glob();
+        let globs = {
+            let manifests = self.manifests.read().unwrap();
+            if manifests.is_empty() {
+                vec![glob]
+            } else {
+                manifests
+                    .iter()
+                    .flat_map(|manifest| manifest.globs(&glob))
+                    .collect()
+            }
+        };
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: globs
+                .into_iter()
+                .map(|glob| FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(glob),
+                    kind: None,
+                })
+                .collect(),
+        };
+        let registration = Registration {
+            id: WATCHED_FILES_REGISTRATION.to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        };
+        if let Err(err) = self.client.register_capability(vec![registration]).await {
+            self.log_message(
+                MessageType::ERROR,
+                format!("failed to watch files: {}", err.message),
+            )
+            .await;
+        }
+    }
+
+    fn work_done_progress_support(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false)
+    }
+
+    /// Asks the user to confirm a destructive action with a Yes/No `window/showMessageRequest`.
+    /// Clients that can't be asked are assumed to agree, a dismissed request is a refusal.
+    async fn confirm(&self, message: &str) -> bool {
+        if !self.show_message_request_support() {
+            return true;
+        }
+        let actions = ["Yes", "No"]
+            .iter()
+            .map(|title| MessageActionItem {
+                title: title.to_string(),
+                properties: HashMap::new(),
+            })
+            .collect();
+        match self
+            .client
+            .show_message_request(MessageType::WARNING, message.to_string(), Some(actions))
+            .await
+        {
+            Ok(Some(action)) => action.title == "Yes",
+            Ok(None) => false,
+            Err(err) => {
+                self.log_message(MessageType::ERROR, err).await;
+                false
+            }
+        }
+    }
+
+    /// The `gen` section of the client settings, asked for with `workspace/configuration`.
+    async fn pull_config(&self) -> Result<Config> {
+        let items = vec![ConfigurationItem {
+            scope_uri: None,
+            section: Some("gen".to_string()),
+        }];
+        let sections = self.client.configuration(items).await?;
+        Ok(Config::from_section(sections.first()))
+    }
+
+    async fn apply_config(&self, config: Config) {
+        self.idle_timer.set_timeout(config.idle_shutdown_seconds);
+        *self.config.write().unwrap() = config;
+        self.reload_builtins().await;
+    }
+
+    fn configuration_support(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or(false)
+    }
+
+    fn label_details_support(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities
+            .text_document
+            .as_ref()
+            .and_then(|text_document| text_document.completion.as_ref())
+            .and_then(|completion| completion.completion_item.as_ref())
+            .and_then(|completion_item| completion_item.label_details_support)
+            .unwrap_or(false)
+    }
+
+    fn snippet_support(&self) -> bool {
+        let capabilities = self.client_capabilities.read().unwrap();
+        capabilities
+            .text_document
+            .as_ref()
+            .and_then(|text_document| text_document.completion.as_ref())
+            .and_then(|completion| completion.completion_item.as_ref())
+            .and_then(|completion_item| completion_item.snippet_support)
+            .unwrap_or(false)
+    }
+
+    /// Formats the whole document, or returns `None` when it isn't open or has syntax errors
+    /// and `gen.formatter.skipOnError` is set.
+    async fn format_document(
+        &self,
+        uri: &Url,
+        options: &FormattingOptions,
+    ) -> Result<Option<String>> {
+        let config = self.config.read().unwrap().clone();
+        if !config.features.formatting || self.is_desynced(uri) {
+            return Ok(None);
+        }
+        let text = match self.document_map.get(uri.as_str()) {
+            Some(rope) => rope.to_string(),
+            None => return Ok(None),
+        };
+        self.format_text(text, options, uri.as_str()).await
+    }
+
+    /// Formats `text`, or returns `None` when it has syntax errors and
+    /// `gen.formatter.skipOnError` is set. `name` designates the text in messages.
+    async fn format_text(
+        &self,
+        text: String,
+        options: &FormattingOptions,
+        name: &str,
+    ) -> Result<Option<String>> {
+        let config = self.config.read().unwrap().clone();
+        let skip_on_error = config.formatter.skip_on_error;
+        let options = options.clone();
+        let format = self.formatter;
+        // The formatter is synchronous, run it on the blocking pool so the timeout can fire. A
+        // timed out task still runs to completion, its result is just dropped.
+        let task = tokio::task::spawn_blocking(move || {
+            if skip_on_error && !parse(&text).1.is_empty() {
+                return None;
+            }
+            Some(format(&text, &options))
+        });
+        let timeout = Duration::from_millis(config.formatter.timeout_ms);
+        let formatted = match tokio::time::timeout(timeout, task).await {
+            Ok(formatted) => formatted.map_err(|_| Error::internal_error())?,
+            Err(_) => {
+                return Err(Error {
+                    code: ErrorCode::ServerError(REQUEST_FAILED),
+                    message: format!(
+                        "formatting {} took longer than {}ms",
+                        name, config.formatter.timeout_ms
+                    ),
+                    data: None,
+                })
+            }
+        };
+        if formatted.is_none() {
+            self.log_message(
+                MessageType::INFO,
+                format!("{} has syntax errors, skipping formatting", name),
+            )
+            .await;
+        }
+        Ok(formatted)
+    }
+
+    /// Records `version` as the latest one of `uri`, whose content is known to be complete.
+    fn resync(&self, uri: &Url, version: i32) {
+        self.version_map.insert(uri.to_string(), version);
+        self.desynced_documents.remove(uri.as_str());
+    }
+
+    /// Whether `uri` missed a change, in which case features producing edits answer with nothing
+    /// rather than edits computed from stale content.
+    fn is_desynced(&self, uri: &Url) -> bool {
+        self.desynced_documents.contains(uri.as_str())
+    }
+
+    /// Gets `uri` ready for a request about it: read from disk when the client never opened it,
+    /// parsed again when its AST is behind its content. Every handler taking a document starts
+    /// with this, so they all see it in the same state.
+    async fn prepare_document(&self, uri: &Url) {
+        self.load_from_disk(uri).await;
+        self.refresh_ast(uri);
+    }
+
+    fn has_bom(&self, uri: &Url) -> bool {
+        self.bom_map.get(uri.as_str()).is_some_and(|bom| *bom)
+    }
+
+    /// `edits` of `uri` as the client applies them: to its text, which still starts with the
+    /// byte order mark stripped from the rope. Edits replacing the document from its very start
+    /// would drop it, so they write it back, their end counting it when on the first line.
+    fn keep_bom(&self, uri: &Url, mut edits: Vec<TextEdit>) -> Vec<TextEdit> {
+        if !self.has_bom(uri) {
+            return edits;
+        }
+        for edit in &mut edits {
+            if edit.range.start == Position::new(0, 0) {
+                edit.new_text.insert(0, BOM);
+                if edit.range.end.line == 0 {
+                    edit.range.end.character += 1;
+                }
+            }
+        }
+        edits
+    }
+
+    /// Marks what was computed for version `previous` of `uri` as computed for `version`, when
+    /// both have the same content, so requests don't reparse it or miss their caches.
+    fn carry_over_version(&self, uri: &Url, previous: Option<i32>, version: i32) {
+        if let Some(mut ast_version) = self.ast_version_map.get_mut(uri.as_str()) {
+            if Some(*ast_version) == previous {
+                *ast_version = version;
+            }
+        }
+        if let Some(mut symbols) = self.document_symbol_map.get_mut(uri.as_str()) {
+            if symbols.0 == previous {
+                symbols.0 = Some(version);
+            }
+        }
+        if let Some(mut hover) = self.hover_map.get_mut(uri.as_str()) {
+            if hover.0 == previous {
+                hover.0 = Some(version);
+            }
+        }
+    }
+
+    /// Parses `uri` again when its AST is from an older version than the document, which
+    /// happens when a request arrives while the analysis of a change is still under way.
+    /// Requests get here through [`prepare_document`](Self::prepare_document), so they never
+    /// answer against stale structure.
+    fn refresh_ast(&self, uri: &Url) {
+        let current = match self.version_map.get(uri.as_str()) {
+            Some(version) => *version,
+            None => return,
+        };
+        if self
+            .ast_version_map
+            .get(uri.as_str())
+            .is_some_and(|version| *version == current)
+        {
+            return;
+        }
+        let text = match self.document_map.get(uri.as_str()) {
+            Some(rope) => rope.to_string(),
+            None => return,
+        };
+        // Like `on_change`, the last good AST is kept when the document doesn't parse
+        if let (Some(ast), _, _) = parse(&text) {
+            self.node_index_map
+                .insert(uri.to_string(), NodeIndex::new(&ast));
+            self.ast_map.insert(uri.to_string(), ast);
+        }
+        self.ast_version_map.insert(uri.to_string(), current);
+    }
+
+    /// Semantic tokens of the whole document, remembered as the base of later deltas.
+    fn full_semantic_tokens(&self, uri: &Url) -> Option<SemanticTokens> {
+        if !self.config.read().unwrap().features.semantic_tokens {
+            return None;
+        }
+        let range_only_threshold = self
+            .config
+            .read()
+            .unwrap()
+            .semantic_tokens
+            .range_only_threshold;
+        let rope = self.document_map.get(uri.as_str())?;
+        if rope.len_chars() > range_only_threshold {
+            return None;
+        }
+        let mut im_complete_tokens = self.semantic_token_map.get(uri.as_str())?.clone();
+        let ast = self.ast_map.get(uri.as_str())?;
+        im_complete_tokens.extend(semantic_token_from_ast(&ast));
+        im_complete_tokens.sort_by_key(|a| a.start);
+        let mut pre_line = 0;
+        let mut pre_start = 0;
+        let data = im_complete_tokens
+            .iter()
+            .filter_map(|token| {
+                let line = rope.try_byte_to_line(token.start).ok()? as u32;
+                let first = rope.try_line_to_char(line as usize).ok()? as u32;
+                let start = rope.try_byte_to_char(token.start).ok()? as u32 - first;
+                let delta_line = line - pre_line;
+                let delta_start = if delta_line == 0 {
+                    start - pre_start
+                } else {
+                    start
+                };
+                let ret = Some(SemanticToken {
+                    delta_line,
+                    delta_start,
+                    length: token.length as u32,
+                    token_type: token.token_type as u32,
+                    token_modifiers_bitset: token.token_modifiers,
+                });
+                pre_line = line;
+                pre_start = start;
+                ret
+            })
+            .collect::<Vec<_>>();
+        // Tokens only depend on the content, so its hash identifies them
+        let result_id = format!("{:x}", self.content_hash(uri)?);
+        self.semantic_token_result_map
+            .insert(uri.to_string(), (result_id.clone(), data.clone()));
+        Some(SemanticTokens {
+            result_id: Some(result_id),
+            data,
+        })
+    }
+
+    /// Hash of the last analyzed content of `uri`, used to skip re-analyzing unchanged text.
+    fn content_hash(&self, uri: &Url) -> Option<u64> {
+        self.content_hash_map.get(uri.as_str()).map(|hash| *hash)
+    }
+
+    fn match_brace(&self, params: UriPositionParams) -> Option<Position> {
+        let rope = self.document_map.get(params.uri.as_str())?;
+        let offset = position_to_offset(&rope, params.position);
+        let matched = match_brace(&rope.to_string(), offset)?;
+        offset_to_position(matched, &rope)
+    }
+
+    /// Whether `name` is one of the built-ins currently loaded.
+    fn is_builtin(&self, name: &str) -> bool {
+        self.builtins
+            .read()
+            .unwrap()
+            .iter()
+            .any(|builtin| builtin.name == name)
+    }
+
+    /// Reads the built-ins from `gen.builtinsPath`, falling back to the embedded ones when it
+    /// isn't set or can't be read.
+    async fn reload_builtins(&self) {
+        let path = self.config.read().unwrap().builtins_path.clone();
+        let builtins = match path.map(|path| (load_builtins(&path), path)) {
+            Some((Ok(builtins), _)) => builtins,
+            Some((Err(err), path)) => {
+                self.log_message(
+                    MessageType::ERROR,
+                    format!("failed to load built-ins from {}: {}", path.display(), err),
+                )
+                .await;
+                default_builtins()
+            }
+            None => default_builtins(),
+        };
+        *self.builtins.write().unwrap() = builtins;
+        // Hovers over built-ins may have changed
+        self.hover_map.clear();
+    }
+
+    /// Markdown hover for the built-in named by the identifier at `offset`, unless the
+    /// document defines something of the same name there.
+    fn builtin_hover(&self, uri: &Url, rope: &Rope, offset: usize) -> Option<(String, Span)> {
+        let (builtin, span) = self.builtin_at(uri, rope, offset)?;
+        Some((
+            format!("```nrs\n{}\n```\n---\n{}", builtin.signature(), builtin.doc),
+            span,
+        ))
+    }
+
+    /// The built-in named by the identifier at `offset`, unless the document defines a symbol of
+    /// the same name there, along with the span of the identifier.
+    fn builtin_at(&self, uri: &Url, rope: &Rope, offset: usize) -> Option<(Builtin, Span)> {
+        let (name, span) = match token_at(&rope.to_string(), offset)? {
+            (Token::Ident(name), span) => (name, span),
+            _ => return None,
+        };
+        if let Some(ast) = self.ast_map.get(uri.as_str()) {
+            if get_definition(&ast, offset).is_some() {
+                return None;
+            }
+        }
+        let builtins = self.builtins.read().unwrap();
+        let builtin = builtins.iter().find(|builtin| builtin.name == name)?;
+        let chars = rope.chars().collect::<Vec<_>>();
+        let start = skip_padding(&chars, span.start);
+        Some((builtin.clone(), start..span.end))
+    }
+
+    /// Remembers `name` as completed or navigated to, boosting it in later completions.
+    fn record_recent_symbol(&self, name: &str) {
+        let mut recent_symbols = self.recent_symbols.lock().unwrap();
+        recent_symbols.touch(name);
+        recent_symbols.evict(RECENT_SYMBOLS_CAPACITY, |_| true);
+    }
+
+    fn sort_functions_action(&self, uri: &Url) -> Option<CodeActionOrCommand> {
+        let rope = self.document_map.get(uri.as_str())?;
+        let (span, new_text) = sort_functions(&rope.to_string())?;
+        let edit = TextEdit::new(span_to_range(&span, &rope)?, new_text);
+        Some(CodeActionOrCommand::CodeAction(CodeAction {
+            title: "Sort functions by name".to_string(),
+            kind: Some(CodeActionKind::REFACTOR_REWRITE),
+            edit: Some(WorkspaceEdit::new(HashMap::from([(
+                uri.clone(),
+                vec![edit],
+            )]))),
+            ..Default::default()
+        }))
+    }
+
+    fn wrap_in_block_action(&self, uri: &Url, range: Range) -> Option<CodeActionOrCommand> {
+        if range.start == range.end {
+            return None;
+        }
+        let ast = self.ast_map.get(uri.as_str())?;
+        let rope = self.document_map.get(uri.as_str())?;
+        // Selecting whole lines ends the selection at the start of the next one
+        let end_line = if range.end.character == 0 && range.end.line > range.start.line {
+            range.end.line - 1
+        } else {
+            range.end.line
+        };
+        let indent = {
+            let config = self.config.read().unwrap();
+            if config.formatter.insert_spaces {
+                " ".repeat(config.formatter.tab_size as usize)
+            } else {
+                "\t".to_string()
+            }
+        };
+        let edit = wrap_in_block(
+            &rope,
+            &ast,
+            range.start.line as usize,
+            end_line as usize,
+            &indent,
+        )?;
+        Some(CodeActionOrCommand::CodeAction(CodeAction {
+            title: "Wrap in block".to_string(),
+            kind: Some(CodeActionKind::REFACTOR_REWRITE),
+            edit: Some(WorkspaceEdit::new(HashMap::from([(
+                uri.clone(),
+                vec![edit],
+            )]))),
+            ..Default::default()
+        }))
+    }
+
+    /// Where the symbol at `params.position` is defined, as `textDocument/definition` answers
+    /// clients without link support.
+    fn definition_location(&self, params: UriPositionParams) -> Option<Location> {
+        let ast = self.ast_map.get(params.uri.as_str())?;
+        let rope = self.document_map.get(params.uri.as_str())?;
+        let offset = position_to_offset(&rope, params.position);
+        let (_, span) = get_definition(&ast, offset)?;
+        Some(Location::new(params.uri, span_to_range(&span, &rope)?))
+    }
+
+    /// Where the function named `symbol` is defined, in `uri` when it defines one, else in any
+    /// other analyzed document.
+    fn symbol_definition(&self, uri: &Url, symbol: &str) -> Option<Location> {
+        let locate = |uri: &str| -> Option<Location> {
+            let ast = self.ast_map.get(uri)?;
+            let rope = self.document_map.get(uri)?;
+            let func = ast.get(symbol)?;
+            Some(Location::new(
+                Url::parse(uri).ok()?,
+                span_to_range(&func.name.1, &rope)?,
+            ))
+        };
+        locate(uri.as_str()).or_else(|| {
+            let uris = self
+                .ast_map
+                .iter()
+                .map(|entry| entry.key().clone())
+                .collect::<Vec<_>>();
+            uris.iter().find_map(|uri| locate(uri))
+        })
+    }
+
+    fn enclosing_function(&self, params: UriPositionParams) -> Option<Range> {
+        let index = self.node_index_map.get(params.uri.as_str())?;
+        let rope = self.document_map.get(params.uri.as_str())?;
+        let offset = position_to_offset(&rope, params.position);
+        // Selection spans are ordered outermost first, which is always the function
+        let span = get_selection_spans(&index, offset).into_iter().next()?;
+        let start_position = offset_to_position(span.start, &rope)?;
+        let end_position = offset_to_position(span.end, &rope)?;
+        Some(Range::new(start_position, end_position))
+    }
+
+    fn document_stats(&self, uri: &Url) -> Option<DocumentStats> {
+        let rope = self.document_map.get(uri.as_str())?;
+        let (symbols, functions) = self.ast_map.get(uri.as_str()).map_or((0, 0), |ast| {
+            let symbols = get_document_symbols(&ast, &rope);
+            let bindings = symbols
+                .iter()
+                .map(|symbol| symbol.children.len())
+                .sum::<usize>();
+            (symbols.len() + bindings, symbols.len())
+        });
+        let diagnostics = self
+            .diagnostic_map
+            .get(uri.as_str())
+            .map_or(0, |diagnostics| diagnostics.len());
+        Some(DocumentStats {
+            lines: rope.len_lines(),
+            symbols,
+            functions,
+            diagnostics,
+        })
+    }
+
+    /// Every fix that is the only one offered for its diagnostic, combined into a single edit
+    /// skipping the fixes overlapping earlier ones, along with the number of fixes kept.
+    fn fix_all(&self, uri: &Url) -> (WorkspaceEdit, usize) {
+        if self.is_desynced(uri) {
+            return (WorkspaceEdit::default(), 0);
+        }
+        let fixes = self
+            .diagnostic_map
+            .get(uri.as_str())
+            .map(|diagnostics| {
+                diagnostics
+                    .iter()
+                    .filter_map(|diagnostic| {
+                        let data = diagnostic.data.clone()?;
+                        let mut fixes = serde_json::from_value::<Vec<QuickFix>>(data).ok()?;
+                        // Picking between several fixes is left to the user
+                        (fixes.len() == 1).then(|| fixes.remove(0).edits)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let (edits, applied) = combine_fixes(fixes);
+        let edit = WorkspaceEdit::new(HashMap::from([(uri.clone(), self.keep_bom(uri, edits))]));
+        (edit, applied)
+    }
+
+    /// Edits giving every line of `uri` the configured line ending, or the one most of its lines
+    /// use.
+    fn normalize_line_endings(&self, uri: &Url) -> Option<Vec<TextEdit>> {
+        let text = self.document_map.get(uri.as_str())?.to_string();
+        let configured = self.config.read().unwrap().formatter.line_ending;
+        let target = configured.or_else(|| Some(mixed_line_endings(&text)?.0));
+        Some(target.map_or(vec![], |target| normalize_line_endings(&text, target)))
+    }
+
+    fn toggle_comment(&self, params: UriRangeParams) -> Option<Vec<TextEdit>> {
+        let rope = self.document_map.get(params.uri.as_str())?;
+        let start = clamp_position(&rope, params.range.start);
+        let end = clamp_position(&rope, params.range.end);
+        if start > end {
+            return None;
+        }
+        // A selection ending at the very start of a line doesn't cover that line
+        let end_line = if end.line > start.line && end.character == 0 {
+            end.line - 1
+        } else {
+            end.line
+        };
+        Some(toggle_line_comment(
+            &rope.to_string(),
+            start.line as usize,
+            end_line as usize,
+        ))
+    }
+
+    async fn on_change(&self, mut params: TextDocumentItem) {
+        if is_binary(&params.text) {
+            self.log_message(
+                MessageType::WARNING,
+                format!("{} is not valid UTF-8 text, skipping analysis", params.uri),
+            )
+            .await;
+            self.forget_document(params.uri.as_str());
+            return;
+        }
+        self.content_hash_map
+            .insert(params.uri.to_string(), content_hash(&params.text));
+        let bom = strip_bom(&params.text).map(str::to_string);
+        self.bom_map.insert(params.uri.to_string(), bom.is_some());
+        if let Some(text) = bom {
+            params.text = text;
+        }
+        let rope = ropey::Rope::from_str(&params.text);
+        self.document_map
+            .insert(params.uri.to_string(), rope.clone());
+        let started = Instant::now();
+        let background = self.config.read().unwrap().background_analysis;
+        let (ast, errors, semantic_tokens) = if background {
+            // Requests for this document in the meantime reparse it themselves, see
+            // `refresh_ast`, other requests are answered while it parses
+            let text = params.text.clone();
+            match tokio::task::spawn_blocking(move || parse(&text)).await {
+                Ok(parsed) => parsed,
+                Err(err) => std::panic::resume_unwind(err.into_panic()),
+            }
+        } else {
+            parse(&params.text)
+        };
+        self.trace_operation("parse", &params.uri, started).await;
+        let started = Instant::now();
+        // self.client
+        //     .log_message(MessageType::INFO, format!("{:?}", errors))
+        //     .await;
+        let mut diagnostics = errors
+            .into_iter()
+            .filter_map(|item| {
+                let (message, span) = match item.reason() {
+                    chumsky::error::SimpleReason::Unclosed { span, delimiter } => {
+                        (format!("Unclosed delimiter {}", delimiter), span.clone())
+                    }
+                    chumsky::error::SimpleReason::Unexpected => (
+                        format!(
+                            "{}, expected {}",
+                            if item.found().is_some() {
+                                "Unexpected token in input"
+                            } else {
+                                "Unexpected end of input"
+                            },
+                            if item.expected().len() == 0 {
+                                "something else".to_string()
+                            } else {
+                                item.expected()
+                                    .map(|expected| match expected {
+                                        Some(expected) => expected.to_string(),
+                                        None => "end of input".to_string(),
+                                    })
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            }
+                        ),
+                        item.span(),
+                    ),
+                    chumsky::error::SimpleReason::Custom(msg) => (msg.to_string(), item.span()),
+                };
+
+                || -> Option<Diagnostic> {
+                    // let start_line = rope.try_char_to_line(span.start)?;
+                    // let first_char = rope.try_line_to_char(start_line)?;
+                    // let start_column = span.start - first_char;
+                    let start_position = offset_to_position(span.start, &rope)?;
+                    let end_position = offset_to_position(span.end, &rope)?;
+                    // let end_line = rope.try_char_to_line(span.end)?;
+                    // let first_char = rope.try_line_to_char(end_line)?;
+                    // let end_column = span.end - first_char;
+                    Some(Diagnostic::new_simple(
+                        Range::new(start_position, end_position),
+                        message,
+                    ))
+                }()
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(ast) = &ast {
+            let chars = params.text.chars().collect::<Vec<_>>();
+            let (unresolved, shadowed) = self
+                .semantic_map
+                .entry(params.uri.to_string())
+                .or_default()
+                .analyze(ast, &chars);
+            diagnostics.extend(
+                unresolved
+                    .into_iter()
+                    .filter(|symbol| !self.is_builtin(&symbol.name.0))
+                    .filter_map(|symbol| {
+                        let (name, span) = symbol.name;
+                        let start_position = offset_to_position(span.start, &rope)?;
+                        let end_position = offset_to_position(span.end, &rope)?;
+                        let range = Range::new(start_position, end_position);
+                        let fixes = symbol
+                            .suggestions
+                            .into_iter()
+                            .map(|suggestion| QuickFix {
+                                title: format!("Change to `{}`", suggestion),
+                                edits: vec![TextEdit::new(range, suggestion)],
+                            })
+                            .collect::<Vec<_>>();
+                        Some(Diagnostic {
+                            range,
+                            severity: Some(DiagnosticSeverity::ERROR),
+                            code: Some(NumberOrString::String("unresolved-symbol".to_string())),
+                            message: format!("cannot find `{}` in this scope", name),
+                            data: serde_json::to_value(fixes).ok(),
+                            ..Default::default()
+                        })
+                    }),
+            );
+            diagnostics.extend(shadowed.into_iter().filter_map(|binding| {
+                let (name, span) = binding.name;
+                let range = Range::new(
+                    offset_to_position(span.start, &rope)?,
+                    offset_to_position(span.end, &rope)?,
+                );
+                let previous = Range::new(
+                    offset_to_position(binding.previous.start, &rope)?,
+                    offset_to_position(binding.previous.end, &rope)?,
+                );
+                let edits = get_reference(ast, span.start, true)
+                    .into_iter()
+                    .filter_map(|(_, span)| {
+                        let start_position = offset_to_position(span.start, &rope)?;
+                        let end_position = offset_to_position(span.end, &rope)?;
+                        Some(TextEdit::new(
+                            Range::new(start_position, end_position),
+                            binding.fresh_name.clone(),
+                        ))
+                    })
+                    .collect();
+                let fix = QuickFix {
+                    title: format!("Rename to `{}`", binding.fresh_name),
+                    edits,
+                };
+                let (severity, code, message) = match binding.kind {
+                    ShadowKind::Shadow => (
+                        DiagnosticSeverity::HINT,
+                        "shadowed-binding",
+                        format!("`{}` shadows an earlier binding", name),
+                    ),
+                    ShadowKind::Redeclaration => (
+                        DiagnosticSeverity::ERROR,
+                        "redeclared-binding",
+                        format!("`{}` is already declared in this scope", name),
+                    ),
+                };
+                Some(Diagnostic {
+                    range,
+                    severity: Some(severity),
+                    code: Some(NumberOrString::String(code.to_string())),
+                    message,
+                    related_information: Some(vec![DiagnosticRelatedInformation {
+                        location: Location::new(params.uri.clone(), previous),
+                        message: format!("`{}` previously declared here", name),
+                    }]),
+                    data: serde_json::to_value(vec![fix]).ok(),
+                    ..Default::default()
+                })
+            }));
+        }
+
+        if let Some((dominant, line)) = mixed_line_endings(&params.text) {
+            let target = self
+                .config
+                .read()
+                .unwrap()
+                .formatter
+                .line_ending
+                .unwrap_or(dominant);
+            let fix = QuickFix {
+                title: format!(
+                    "Convert line endings to {}",
+                    if target == LineEnding::Lf {
+                        "LF"
+                    } else {
+                        "CRLF"
+                    }
+                ),
+                edits: normalize_line_endings(&params.text, target),
+            };
+            let len = rope
+                .line(line)
+                .chars()
+                .take_while(|c| *c != '\r' && *c != '\n')
+                .count();
+            diagnostics.push(Diagnostic {
+                range: Range::new(
+                    Position::new(line as u32, 0),
+                    Position::new(line as u32, len as u32),
+                ),
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                code: Some(NumberOrString::String("mixed-line-endings".to_string())),
+                message: "line endings mix LF and CRLF".to_string(),
+                data: serde_json::to_value(vec![fix]).ok(),
+                ..Default::default()
+            });
+        }
+
+        let mixed_lines = mixed_indentation_lines(&params.text);
+        if !mixed_lines.is_empty() {
+            let (insert_spaces, tab_size) = {
+                let config = self.config.read().unwrap();
+                (config.formatter.insert_spaces, config.formatter.tab_size)
+            };
+            let fix = QuickFix {
+                title: format!(
+                    "Convert indentation to {}",
+                    if insert_spaces { "spaces" } else { "tabs" }
+                ),
+                edits: normalize_indentation(&params.text, insert_spaces, tab_size)
+                    .into_iter()
+                    .map(|edit| {
+                        let range = Range::new(
+                            Position::new(edit.line as u32, 0),
+                            Position::new(edit.line as u32, edit.len as u32),
+                        );
+                        TextEdit::new(range, edit.indent)
+                    })
+                    .collect(),
+            };
+            let fixes = serde_json::to_value(vec![fix]).ok();
+            diagnostics.extend(mixed_lines.into_iter().map(|line| {
+                let indent_len = rope
+                    .line(line)
+                    .chars()
+                    .take_while(|c| *c == ' ' || *c == '\t')
+                    .count();
+                Diagnostic {
+                    range: Range::new(
+                        Position::new(line as u32, 0),
+                        Position::new(line as u32, indent_len as u32),
+                    ),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("mixed-indentation".to_string())),
+                    message: "indentation mixes tabs and spaces".to_string(),
+                    data: fixes.clone(),
+                    ..Default::default()
+                }
+            }));
+        }
+
+        let (max_line_length, tab_size) = {
+            let config = self.config.read().unwrap();
+            (config.lint.max_line_length, config.formatter.tab_size)
+        };
+        if let Some(max_line_length) = max_line_length {
+            diagnostics.extend(
+                long_lines(&params.text, max_line_length, tab_size as usize)
+                    .into_iter()
+                    .map(|long_line| Diagnostic {
+                        range: Range::new(
+                            Position::new(long_line.line as u32, long_line.overflow_start as u32),
+                            Position::new(long_line.line as u32, long_line.len as u32),
+                        ),
+                        severity: Some(DiagnosticSeverity::WARNING),
+                        code: Some(NumberOrString::String("max-line-length".to_string())),
+                        message: format!(
+                            "line is {} columns wide, the maximum is {}",
+                            long_line.width, max_line_length
+                        ),
+                        ..Default::default()
+                    }),
+            );
+        }
+
+        if self.config.read().unwrap().lint.trailing_comma {
+            diagnostics.extend(
+                trailing_commas(&params.text)
+                    .into_iter()
+                    .filter_map(|span| {
+                        let start_position = offset_to_position(span.start, &rope)?;
+                        let end_position = offset_to_position(span.end, &rope)?;
+                        let range = Range::new(start_position, end_position);
+                        let fix = QuickFix {
+                            title: "Remove trailing comma".to_string(),
+                            edits: vec![TextEdit::new(range, String::new())],
+                        };
+                        Some(Diagnostic {
+                            range,
+                            severity: Some(DiagnosticSeverity::HINT),
+                            code: Some(NumberOrString::String("trailing-comma".to_string())),
+                            message: "trailing comma".to_string(),
+                            data: serde_json::to_value(vec![fix]).ok(),
+                            ..Default::default()
+                        })
+                    }),
+            );
+        }
+
+        diagnostics.extend(
+            assignments_in_conditions(&params.text)
+                .into_iter()
+                .filter_map(|span| {
+                    let range = span_to_range(&span, &rope)?;
+                    let fix = QuickFix {
+                        title: "Change to `==`".to_string(),
+                        edits: vec![TextEdit::new(range, "==".to_string())],
+                    };
+                    Some(Diagnostic {
+                        range,
+                        severity: Some(DiagnosticSeverity::WARNING),
+                        code: Some(NumberOrString::String(
+                            "assignment-in-condition".to_string(),
+                        )),
+                        message: "`=` in a condition, did you mean `==`?".to_string(),
+                        data: serde_json::to_value(vec![fix]).ok(),
+                        ..Default::default()
+                    })
+                }),
+        );
+
+        self.trace_operation("analyze", &params.uri, started).await;
+
+        self.diagnostic_map
+            .insert(params.uri.to_string(), diagnostics.clone());
+        // Documents read from disk aren't shown in the editor, neither are their diagnostics
+        let opened = self.open_documents.contains(params.uri.as_str());
+        if opened && self.config.read().unwrap().features.diagnostics {
+            let max_diagnostics = self.config.read().unwrap().lint.max_diagnostics;
+            sort_and_limit(&mut diagnostics, max_diagnostics);
+            if self.code_description_support() {
+                for diagnostic in diagnostics.iter_mut() {
+                    if let Some(NumberOrString::String(code)) = &diagnostic.code {
+                        diagnostic.code_description =
+                            code_documentation(code).map(|href| CodeDescription { href });
+                    }
+                }
+            }
+            self.notify(DocumentNotification::Diagnostics {
+                uri: params.uri.clone(),
+                diagnostics,
+                version: Some(params.version),
+            })
+            .await;
+        }
+
+        if let Some(ast) = ast {
+            self.node_index_map
+                .insert(params.uri.to_string(), NodeIndex::new(&ast));
+            self.ast_map.insert(params.uri.to_string(), ast);
+        }
+        self.ast_version_map
+            .insert(params.uri.to_string(), params.version);
+        self.document_symbol_map.remove(params.uri.as_str());
+        self.hover_map.remove(params.uri.as_str());
+        // self.client
+        //     .log_message(MessageType::INFO, &format!("{:?}", semantic_tokens))
+        //     .await;
+        self.semantic_token_map
+            .insert(params.uri.to_string(), semantic_tokens);
+        let push_inlay_hints = {
+            let config = self.config.read().unwrap();
+            config.features.inlay_hints && config.inlay_hints.push
+        };
+        if opened && push_inlay_hints {
+            if let Some(hints) = self.inlay_hints(&params.uri) {
+                self.notify(DocumentNotification::InlayHints(
+                    InlayHintsNotificationParams {
+                        uri: params.uri.clone(),
+                        hints,
+                    },
+                ))
+                .await;
+            }
+        }
+        self.lru.lock().unwrap().touch(params.uri.as_str());
+        self.evict_documents();
+    }
+
+    /// Analyzes the file behind `uri` when a request comes for a document the client never
+    /// opened, so it is answered as if it had been.
+    async fn load_from_disk(&self, uri: &Url) {
+        if self.document_map.contains_key(uri.as_str())
+            || self.open_documents.contains(uri.as_str())
+        {
+            return;
+        }
+        let path = match uri_to_path(uri) {
+            Some(path) => path,
+            None => return,
+        };
+        if !self.config.read().unwrap().handles_file(&path) || !self.in_workspace_scope(&path) {
+            return;
+        }
+        let text = match tokio::fs::read_to_string(&path).await {
+            Ok(text) => text,
+            Err(_) => return,
+        };
+        self.disk_documents.insert(uri.to_string());
+        self.on_change(TextDocumentItem {
+            uri: uri.clone(),
+            text,
+            version: 0,
+        })
+        .await
+    }
+
+    /// Reads the manifest of the workspace root `uri`, replacing the one it had.
+    async fn load_manifest(&self, uri: &Url) {
+        let root = match uri_to_path(uri) {
+            Some(root) => root,
+            None => return,
+        };
+        match ProjectManifest::load(&root).await {
+            Ok(manifest) => {
+                let mut manifests = self.manifests.write().unwrap();
+                manifests.retain(|manifest| manifest.root != root);
+                manifests.push(manifest);
+            }
+            Err(err) => self.log_message(MessageType::ERROR, err).await,
+        }
+    }
+
+    /// Whether the manifests of the workspace roots `path` is under include it. Files outside
+    /// every root are in scope, as they are when no workspace was opened.
+    fn in_workspace_scope(&self, path: &std::path::Path) -> bool {
+        let manifests = self.manifests.read().unwrap();
+        let mut covering = manifests
+            .iter()
+            .filter(|manifest| manifest.covers(path))
+            .peekable();
+        covering.peek().is_none() || covering.any(|manifest| manifest.includes(path))
+    }
+
+    /// Sends `notification`, or queues it until `initialized` when the client isn't ready for it
+    /// yet, as a document opened right after `initialize` would otherwise be.
+    async fn notify(&self, notification: DocumentNotification) {
+        {
+            let mut pending_notifications = self.pending_notifications.lock().unwrap();
+            if !self.initialized.load(Ordering::SeqCst) {
+                pending_notifications.push(notification);
+                return;
+            }
+        }
+        self.send_document_notification(notification).await;
+    }
+
+    async fn send_document_notification(&self, notification: DocumentNotification) {
+        match notification {
+            DocumentNotification::Diagnostics {
+                uri,
+                diagnostics,
+                version,
+            } => {
+                self.client
+                    .publish_diagnostics(uri, diagnostics, version)
+                    .await
+            }
+            DocumentNotification::InlayHints(params) => {
+                self.client
+                    .send_notification::<InlayHintsNotification>(params)
+                    .await
+            }
+        }
+    }
+
+    /// Drops the cached parse results of the least recently used closed documents once the cache
+    /// holds more than `cache.capacity` documents.
+    fn evict_documents(&self) {
+        let capacity = self.config.read().unwrap().cache.capacity;
+        let evicted = self
+            .lru
+            .lock()
+            .unwrap()
+            .evict(capacity, |uri| !self.open_documents.contains(uri));
+        for uri in evicted {
+            self.forget_document(&uri);
+        }
+    }
+
+    /// Drops the analyses of every document, closed ones included, then analyzes the open ones
+    /// again from their current content.
+    async fn reindex(&self) {
+        let open_documents = self
+            .open_documents
+            .iter()
+            .filter_map(|uri| {
+                let text = self.document_map.get(uri.as_str())?.to_string();
+                let version = self
+                    .version_map
+                    .get(uri.as_str())
+                    .map_or(0, |version| *version);
+                Some((Url::parse(&uri).ok()?, text, version))
+            })
+            .collect::<Vec<_>>();
+        let cached = self
+            .document_map
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect::<Vec<_>>();
+        for uri in cached {
+            self.forget_document(&uri);
+        }
+        let progress = if self.work_done_progress_support() {
+            ProgressGuard::begin(self.client.clone(), "Reindexing").await
+        } else {
+            None
+        };
+        let total = open_documents.len();
+        for (i, (uri, text, version)) in open_documents.into_iter().enumerate() {
+            if let Some(progress) = &progress {
+                progress
+                    .report(uri.to_string(), (i * 100 / total) as u32)
+                    .await;
+            }
+            self.on_change(TextDocumentItem { uri, text, version })
+                .await;
+        }
+        if let Some(progress) = progress {
+            progress
+                .end(Some(format!("{} documents reindexed", total)))
+                .await;
+        }
+    }
+
+    /// Drops everything cached about `uri`.
+    fn forget_document(&self, uri: &str) {
+        self.ast_map.remove(uri);
+        self.ast_version_map.remove(uri);
+        self.node_index_map.remove(uri);
+        self.document_map.remove(uri);
+        self.semantic_token_map.remove(uri);
+        self.semantic_token_result_map.remove(uri);
+        self.document_symbol_map.remove(uri);
+        self.hover_map.remove(uri);
+        self.content_hash_map.remove(uri);
+        self.semantic_map.remove(uri);
+        self.bom_map.remove(uri);
+        self.diagnostic_map.remove(uri);
+        self.disk_documents.remove(uri);
+        self.lru.lock().unwrap().remove(uri);
+    }
+}
+
+/// How types are written in inlay hints and `gen/symbolAt` answers.
+fn type_name(value: &crate::chumsky::Value) -> String {
+    match value {
+        crate::chumsky::Value::Null => "null".to_string(),
+        crate::chumsky::Value::Bool(_) => "bool".to_string(),
+        crate::chumsky::Value::Num(_) => "number".to_string(),
+        crate::chumsky::Value::Str(_) => "string".to_string(),
+        crate::chumsky::Value::List(_) => "[]".to_string(),
+        crate::chumsky::Value::Func(_) => value.to_string(),
+    }
+}
+
+/// Shows the parameters of a function completion right after its label and where it comes
+/// from, `source`, on the side. Clients without label details get both in `detail` instead.
+fn set_function_details(
+    item: &mut CompletionItem,
+    params: &[String],
+    source: &str,
+    label_details_support: bool,
+) {
+    let params = format!("({})", params.join(", "));
+    let signature = format!("fn {}{}", item.label, params);
+    if label_details_support {
+        item.detail = Some(signature);
+        item.label_details = Some(CompletionItemLabelDetails {
+            detail: Some(params),
+            description: Some(source.to_string()),
+        });
+    } else {
+        item.detail = Some(format!("{} ({})", signature, source));
+    }
+}
+
+/// An item closing the innermost bracket left open before `offset`. On a line holding nothing
+/// else, the closer is aligned with the line of its opening bracket.
+fn closing_bracket_completion(rope: &Rope, offset: usize) -> Option<CompletionItem> {
+    let (open, close) = unclosed_bracket(&rope.to_string(), offset)?;
+    let line = rope.char_to_line(offset);
+    let line_start = rope.line_to_char(line);
+    let before_cursor = rope.slice(line_start..offset).to_string();
+    let mut new_text = close.to_string();
+    let mut start = offset;
+    if before_cursor.trim().is_empty() {
+        let indentation = rope
+            .line(rope.char_to_line(open))
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect::<String>();
+        new_text = format!("{}{}", indentation, close);
+        start = line_start;
+    }
+    let range = Range::new(
+        offset_to_position(start, rope)?,
+        offset_to_position(offset, rope)?,
+    );
+    Some(CompletionItem {
+        label: close.to_string(),
+        kind: Some(CompletionItemKind::OPERATOR),
+        detail: Some(format!("close the `{}` left open", rope.char(open))),
+        // Sorted first, nothing parses right until the bracket is closed
+        sort_text: Some(format!("0{}", close)),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, new_text))),
+        ..Default::default()
+    })
+}
+
+/// The part of an identifier right before `offset`, which completions are filtered by.
+fn typed_prefix(rope: &Rope, offset: usize) -> String {
+    let mut prefix = rope
+        .chars_at(offset)
+        .reversed()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect::<Vec<_>>();
+    prefix.reverse();
+    prefix.into_iter().collect()
+}
+
+fn to_call_hierarchy_item(uri: Url, func: &Func, rope: &Rope) -> Option<CallHierarchyItem> {
+    Some(CallHierarchyItem {
+        name: func.name.0.clone(),
+        kind: SymbolKind::FUNCTION,
+        tags: None,
+        detail: Some(signature(func)),
+        uri,
+        range: span_to_range(&func.span, rope)?,
+        selection_range: span_to_range(&func.name.1, rope)?,
+        data: None,
+    })
+}
+
+fn span_to_range(span: &Span, rope: &Rope) -> Option<Range> {
+    Some(Range::new(
+        offset_to_position(span.start, rope)?,
+        offset_to_position(span.end, rope)?,
+    ))
+}
+
+fn to_document_symbol(symbol: ImCompleteDocumentSymbol, rope: &Rope) -> Option<DocumentSymbol> {
+    let range = Range::new(
+        offset_to_position(symbol.span.start, rope)?,
+        offset_to_position(symbol.span.end, rope)?,
+    );
+    let selection_range = Range::new(
+        offset_to_position(symbol.selection_span.start, rope)?,
+        offset_to_position(symbol.selection_span.end, rope)?,
+    );
+    let children = symbol
+        .children
+        .into_iter()
+        .map(|child| to_document_symbol(child, rope))
+        .collect::<Option<Vec<_>>>()?;
+    #[allow(deprecated)]
+    Some(DocumentSymbol {
+        name: symbol.name,
+        detail: symbol.detail,
+        kind: symbol.kind,
+        tags: symbol.deprecated.then(|| vec![SymbolTag::DEPRECATED]),
+        deprecated: Some(symbol.deprecated),
+        range,
+        selection_range,
+        children: (!children.is_empty()).then_some(children),
+    })
+}
+
+#[cfg(test)]
+mod tests;
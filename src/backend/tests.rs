@@ -0,0 +1,3445 @@
+use serde_json::json;
+
+use super::*;
+use crate::test_util::MockClient;
+
+fn uri() -> Url {
+    Url::parse("file:///workspace/main.nrs").unwrap()
+}
+
+/// A backend past `initialized`, for a client with default capabilities and settings.
+async fn server() -> (Backend<MockClient>, MockClient) {
+    server_with(json!({}), ClientCapabilities::default()).await
+}
+
+/// A backend past `initialized`, with `settings` as the `gen` section of its settings.
+async fn server_with(
+    settings: Value,
+    capabilities: ClientCapabilities,
+) -> (Backend<MockClient>, MockClient) {
+    let client = MockClient::default();
+    let backend = Backend::new(client.clone());
+    backend
+        .initialize(InitializeParams {
+            initialization_options: Some(json!({ "gen": settings })),
+            capabilities,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    backend.initialized(InitializedParams {}).await;
+    (backend, client)
+}
+
+/// Opens [`uri`] with `text` at version 1.
+async fn open(backend: &Backend<MockClient>, text: &str) {
+    open_uri(backend, &uri(), text).await;
+}
+
+/// Opens `uri` with `text` at version 1.
+async fn open_uri(backend: &Backend<MockClient>, uri: &Url, text: &str) {
+    backend
+        .did_open(DidOpenTextDocumentParams {
+            text_document: tower_lsp::lsp_types::TextDocumentItem::new(
+                uri.clone(),
+                "nrs".to_string(),
+                1,
+                text.to_string(),
+            ),
+        })
+        .await;
+}
+
+fn codes(diagnostics: &[Diagnostic]) -> Vec<String> {
+    diagnostics
+        .iter()
+        .filter_map(|diagnostic| match &diagnostic.code {
+            Some(NumberOrString::String(code)) => Some(code.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn publishes_diagnostics_through_the_client() {
+    let (backend, client) = server().await;
+    open(&backend, "fn main() {\n    x\n}\n").await;
+    let published = client.published_diagnostics();
+    assert_eq!(published.len(), 1);
+    assert_eq!(published[0].uri, uri());
+    assert_eq!(published[0].version, Some(1));
+    assert_eq!(codes(&published[0].diagnostics), ["unresolved-symbol"]);
+    assert_eq!(
+        published[0].diagnostics[0].range,
+        Range::new(Position::new(1, 4), Position::new(1, 5))
+    );
+}
+
+#[tokio::test]
+async fn binary_content_is_skipped_with_a_single_warning() {
+    let (backend, client) = server_with(
+        json!({ "trace": { "server": "messages" } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    let bytes = [
+        0x7f, b'E', b'L', b'F', 0x02, 0x01, 0x01, 0x00, 0xff, 0xfe, 0x80,
+    ];
+    open(&backend, &String::from_utf8_lossy(&bytes)).await;
+    let warnings = client
+        .log_messages()
+        .into_iter()
+        .filter(|(typ, _)| *typ == MessageType::WARNING)
+        .collect::<Vec<_>>();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].1.contains("not valid UTF-8"));
+    assert!(client.published_diagnostics().is_empty());
+    assert!(!backend.document_map.contains_key(uri().as_str()));
+}
+
+#[tokio::test]
+async fn replacement_characters_in_source_are_analyzed() {
+    let (backend, client) = server().await;
+    open(&backend, "fn main() {\n    \"\u{fffd}\"\n}\n").await;
+    assert_eq!(client.diagnostics(&uri()), Some(vec![]));
+    assert!(backend.ast_map.contains_key(uri().as_str()));
+}
+
+/// Runs `command` with its single `argument`.
+async fn execute(
+    backend: &Backend<MockClient>,
+    command: &str,
+    argument: Value,
+) -> Result<Option<Value>> {
+    backend
+        .execute_command(ExecuteCommandParams {
+            command: command.to_string(),
+            arguments: vec![argument],
+            work_done_progress_params: Default::default(),
+        })
+        .await
+}
+
+#[tokio::test]
+async fn match_brace_command() {
+    let (backend, _) = server().await;
+    open(&backend, "fn main() {\n    [1, 2]\n}\n").await;
+    let at = |line, character| json!({ "uri": uri(), "position": Position::new(line, character) });
+    let matched = execute(&backend, "gen.matchBrace", at(0, 10))
+        .await
+        .unwrap();
+    assert_eq!(matched, Some(json!({ "line": 2, "character": 0 })));
+    let matched = execute(&backend, "gen.matchBrace", at(1, 9)).await.unwrap();
+    assert_eq!(matched, Some(json!({ "line": 1, "character": 4 })));
+    let matched = execute(&backend, "gen.matchBrace", at(1, 5)).await.unwrap();
+    assert_eq!(matched, None);
+}
+
+fn workspace_symbol_params(query: &str, token: Option<&str>) -> WorkspaceSymbolParams {
+    WorkspaceSymbolParams {
+        query: query.to_string(),
+        work_done_progress_params: Default::default(),
+        partial_result_params: tower_lsp::lsp_types::PartialResultParams {
+            partial_result_token: token.map(|token| NumberOrString::String(token.to_string())),
+        },
+    }
+}
+
+#[tokio::test]
+async fn workspace_symbols_stream_partial_results() {
+    let (backend, client) = server().await;
+    let other = Url::parse("file:///workspace/other.nrs").unwrap();
+    open(&backend, "fn main() {\n    1\n}\n").await;
+    open_uri(
+        &backend,
+        &other,
+        "fn main_loop() {\n    2\n}\nfn helper() {\n    3\n}\n",
+    )
+    .await;
+
+    let symbols = backend
+        .symbol(workspace_symbol_params("main", Some("symbols")))
+        .await
+        .unwrap();
+    assert_eq!(symbols, Some(vec![]));
+    let chunks = client.notifications("$/progress");
+    assert_eq!(chunks.len(), 2);
+    let mut names = chunks
+        .iter()
+        .flat_map(|chunk| {
+            assert_eq!(chunk["token"], "symbols");
+            chunk["value"].as_array().unwrap().clone()
+        })
+        .map(|symbol| symbol["name"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["main", "main_loop"]);
+}
+
+#[tokio::test]
+async fn workspace_symbols_without_a_token_come_in_the_response() {
+    let (backend, client) = server().await;
+    open(&backend, "fn main() {\n    1\n}\nfn helper() {\n    2\n}\n").await;
+    let symbols = backend
+        .symbol(workspace_symbol_params("HELP", None))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(symbols[0].name, "helper");
+    assert_eq!(symbols[0].location.uri, uri());
+    assert!(client.notifications("$/progress").is_empty());
+}
+
+fn position_params(line: u32, character: u32) -> TextDocumentPositionParams {
+    TextDocumentPositionParams::new(
+        TextDocumentIdentifier::new(uri()),
+        Position::new(line, character),
+    )
+}
+
+#[tokio::test]
+async fn positions_past_the_end_are_clamped() {
+    let (backend, _) = server().await;
+    open(&backend, "fn main() {\n    let x = 1;\n    x + 0x10\n}").await;
+
+    let hover = |line, character| {
+        backend.hover(HoverParams {
+            text_document_position_params: position_params(line, character),
+            work_done_progress_params: Default::default(),
+        })
+    };
+    // Past the end of its line the cursor lands right after `0x10`, not on it
+    assert_eq!(hover(2, 99).await.unwrap(), None);
+    assert!(hover(2, 9).await.unwrap().is_some());
+    assert_eq!(hover(99, 99).await.unwrap(), None);
+
+    let definition = backend
+        .goto_definition(GotoDefinitionParams {
+            text_document_position_params: position_params(42, 0),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(definition, None);
+
+    let completion = backend
+        .completion(CompletionParams {
+            text_document_position: position_params(42, 42),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        })
+        .await
+        .unwrap();
+    let Some(CompletionResponse::List(list)) = completion else {
+        panic!("expected a completion list");
+    };
+    // The cursor lands after the closing brace on the last line
+    let edit_range = list.items.iter().find_map(|item| match &item.text_edit {
+        Some(CompletionTextEdit::Edit(edit)) => Some(edit.range),
+        _ => None,
+    });
+    assert_eq!(
+        edit_range,
+        Some(Range::new(Position::new(3, 1), Position::new(3, 1)))
+    );
+}
+
+#[tokio::test]
+async fn reversed_ranges_get_no_semantic_tokens() {
+    let (backend, _) = server().await;
+    open(&backend, "fn main() {\n    1\n}\n").await;
+    let range_tokens = |start, end| SemanticTokensRangeParams {
+        text_document: TextDocumentIdentifier::new(uri()),
+        range: Range::new(start, end),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    };
+    let tokens = backend
+        .semantic_tokens_range(range_tokens(Position::new(2, 0), Position::new(0, 0)))
+        .await
+        .unwrap();
+    assert_eq!(tokens, None);
+    let tokens = backend
+        .semantic_tokens_range(range_tokens(Position::new(0, 0), Position::new(99, 0)))
+        .await
+        .unwrap();
+    assert!(tokens.is_some());
+}
+
+/// A file written to a directory of its own, removed along with it on drop.
+struct DiskFile {
+    dir: std::path::PathBuf,
+    uri: Url,
+}
+
+impl DiskFile {
+    fn new(name: &str, text: &str) -> DiskFile {
+        let dir = std::env::temp_dir().join(format!(
+            "nrs-language-server-{}-{}",
+            std::process::id(),
+            name
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, text).unwrap();
+        let uri = Url::from_file_path(path).unwrap();
+        DiskFile { dir, uri }
+    }
+}
+
+impl Drop for DiskFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[tokio::test]
+async fn handlers_read_unopened_documents_from_disk() {
+    let text = "fn add(a, b) {\n    a + b\n}\nfn main() {\n    let x = add(1, 2);\n    x\n}\n";
+
+    let (backend, _) = server().await;
+    let file = DiskFile::new("inline_value.nrs", text);
+    let inline_values = backend
+        .inline_value(InlineValueParams {
+            text_document: TextDocumentIdentifier::new(file.uri.clone()),
+            range: Range::new(Position::new(3, 0), Position::new(6, 1)),
+            context: InlineValueContext {
+                frame_id: 0,
+                stopped_location: Range::new(Position::new(5, 4), Position::new(5, 4)),
+            },
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert!(inline_values.is_some_and(|values| !values.is_empty()));
+
+    let (backend, _) = server().await;
+    let file = DiskFile::new("call_hierarchy.nrs", text);
+    let items = backend
+        .prepare_call_hierarchy(CallHierarchyPrepareParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(file.uri.clone()),
+                Position::new(0, 4),
+            ),
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        items.map(|items| items[0].name.clone()),
+        Some("add".to_string())
+    );
+
+    let (backend, _) = server().await;
+    let file = DiskFile::new("semantic_tokens.nrs", text);
+    let tokens = backend
+        .semantic_tokens_full_delta(SemanticTokensDeltaParams {
+            text_document: TextDocumentIdentifier::new(file.uri.clone()),
+            previous_result_id: "unknown".to_string(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(
+        tokens,
+        Some(SemanticTokensFullDeltaResult::Tokens(tokens)) if !tokens.data.is_empty()
+    ));
+}
+
+fn formatting_params() -> DocumentFormattingParams {
+    DocumentFormattingParams {
+        text_document: TextDocumentIdentifier::new(uri()),
+        options: FormattingOptions {
+            tab_size: 4,
+            insert_spaces: true,
+            ..Default::default()
+        },
+        work_done_progress_params: Default::default(),
+    }
+}
+
+#[tokio::test]
+async fn formatting_a_crlf_document_keeps_its_line_endings() {
+    let (backend, _) = server().await;
+    open(&backend, "fn main() {\r\nlet a = 1;\r\na\r\n}\r\n").await;
+    let edits = backend
+        .formatting(formatting_params())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0].new_text, "    let a = 1;\r\n    a");
+}
+
+#[tokio::test]
+async fn formatting_replaces_the_whole_document_without_minimal_edits() {
+    let (backend, _) = server_with(
+        json!({ "formatter": { "minimalEdits": false } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, "fn main() {\n1\n}\n").await;
+    let edits = backend
+        .formatting(formatting_params())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        edits,
+        [TextEdit::new(
+            Range::new(Position::new(0, 0), Position::new(3, 0)),
+            "fn main() {\n    1\n}\n".to_string()
+        )]
+    );
+}
+
+fn completion_params(line: u32, character: u32) -> CompletionParams {
+    CompletionParams {
+        text_document_position: position_params(line, character),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+        context: None,
+    }
+}
+
+/// The items answered to a completion request at `line` and `character`.
+async fn completion_items(
+    backend: &Backend<MockClient>,
+    line: u32,
+    character: u32,
+) -> Vec<CompletionItem> {
+    match backend
+        .completion(completion_params(line, character))
+        .await
+        .unwrap()
+    {
+        Some(CompletionResponse::List(list)) => list.items,
+        Some(CompletionResponse::Array(items)) => items,
+        None => vec![],
+    }
+}
+
+async fn hover_at(backend: &Backend<MockClient>, line: u32, character: u32) -> Option<Hover> {
+    backend
+        .hover(HoverParams {
+            text_document_position_params: position_params(line, character),
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+}
+
+fn hover_text(hover: Option<Hover>) -> Option<String> {
+    match hover?.contents {
+        HoverContents::Markup(markup) => Some(markup.value),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn builtins_complete_and_resolve_on_hover() {
+    let (backend, client) = server().await;
+    open(&backend, "fn main() {\n    len([1, 2])\n}\n").await;
+    assert_eq!(client.diagnostics(&uri()), Some(vec![]));
+
+    let items = completion_items(&backend, 1, 4).await;
+    let len = items.iter().find(|item| item.label == "len").unwrap();
+    assert_eq!(len.kind, Some(CompletionItemKind::FUNCTION));
+    assert!(matches!(
+        &len.documentation,
+        Some(Documentation::MarkupContent(doc)) if doc.value.starts_with("Number of elements")
+    ));
+
+    let hover = hover_text(hover_at(&backend, 1, 5).await).unwrap();
+    assert!(hover.starts_with("```nrs\nfn len(value)\n```"));
+}
+
+#[tokio::test]
+async fn builtins_are_read_from_the_configured_path() {
+    let file = DiskFile::new(
+        "builtins.json",
+        r#"[{ "name": "sqrt", "params": ["x"], "doc": "Square root of `x`." }]"#,
+    );
+    let path = file.uri.to_file_path().unwrap();
+    let (backend, client) = server_with(
+        json!({ "builtinsPath": path }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, "fn main() {\n    sqrt(len(4))\n}\n").await;
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    assert_eq!(codes(&diagnostics), ["unresolved-symbol"]);
+    assert_eq!(diagnostics[0].message, "cannot find `len` in this scope");
+    let hover = hover_text(hover_at(&backend, 1, 5).await).unwrap();
+    assert!(hover.ends_with("Square root of `x`."));
+}
+
+/// The capabilities `initialize` advertises with `settings` as the `gen` section.
+async fn capabilities_with(settings: Value) -> ServerCapabilities {
+    Backend::new(MockClient::default())
+        .initialize(InitializeParams {
+            initialization_options: Some(json!({ "gen": settings })),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .capabilities
+}
+
+#[tokio::test]
+async fn configured_trigger_characters_are_advertised() {
+    let capabilities =
+        capabilities_with(json!({ "completion": { "triggerCharacters": [":", ".", "@"] } })).await;
+    let triggers = capabilities.completion_provider.unwrap().trigger_characters;
+    assert_eq!(
+        triggers,
+        Some(vec![".".to_string(), ":".to_string(), "@".to_string()])
+    );
+
+    let capabilities = capabilities_with(json!({})).await;
+    let triggers = capabilities.completion_provider.unwrap().trigger_characters;
+    assert_eq!(triggers, Some(vec![".".to_string()]));
+}
+
+/// The ranges of `chain`, innermost first.
+fn selection_chain(chain: &SelectionRange) -> Vec<Range> {
+    let mut ranges = vec![chain.range];
+    let mut parent = &chain.parent;
+    while let Some(selection) = parent {
+        ranges.push(selection.range);
+        parent = &selection.parent;
+    }
+    ranges
+}
+
+#[tokio::test]
+async fn selection_ranges_keep_the_order_of_positions() {
+    let (backend, _) = server().await;
+    open(&backend, "fn main() {\n    let x = [1, 2];\n    x\n}\n").await;
+    let positions = vec![
+        Position::new(2, 4),
+        Position::new(1, 13),
+        Position::new(1, 9),
+    ];
+    let chains = backend
+        .selection_range(SelectionRangeParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            positions: positions.clone(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(chains.len(), 3);
+    let chains = chains.iter().map(selection_chain).collect::<Vec<_>>();
+    for (chain, position) in chains.iter().zip(&positions) {
+        let innermost = chain[0];
+        assert!(innermost.start <= *position && *position <= innermost.end);
+        assert_eq!(chain.last(), chains[0].last());
+    }
+    assert_eq!(
+        chains[0][0],
+        Range::new(Position::new(2, 4), Position::new(2, 5))
+    );
+    assert_eq!(
+        chains[1][0],
+        Range::new(Position::new(1, 13), Position::new(1, 14))
+    );
+    assert!(chains[1].len() > chains[0].len());
+    assert_ne!(chains[1], chains[2]);
+}
+
+#[tokio::test]
+async fn documents_over_the_threshold_only_get_range_tokens() {
+    let (backend, _) = server_with(
+        json!({ "semanticTokens": { "rangeOnlyThreshold": 20 } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    let small = Url::parse("file:///workspace/small.nrs").unwrap();
+    open_uri(&backend, &small, "fn main() { 1 }\n").await;
+    open(&backend, "fn main() {\n    let x = 1;\n    x\n}\n").await;
+
+    let full = |uri: &Url| SemanticTokensParams {
+        text_document: TextDocumentIdentifier::new(uri.clone()),
+        work_done_progress_params: Default::default(),
+        partial_result_params: Default::default(),
+    };
+    let tokens = backend.semantic_tokens_full(full(&uri())).await.unwrap();
+    assert_eq!(tokens, None);
+    let tokens = backend.semantic_tokens_full(full(&small)).await.unwrap();
+    assert!(tokens.is_some());
+
+    let tokens = backend
+        .semantic_tokens_range(SemanticTokensRangeParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            range: Range::new(Position::new(1, 0), Position::new(3, 0)),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(
+        tokens,
+        Some(SemanticTokensRangeResult::Tokens(tokens)) if !tokens.data.is_empty()
+    ));
+}
+
+/// Labels of the inlay hints of [`uri`] with their positions.
+async fn inlay_hint_labels(backend: &Backend<MockClient>) -> Vec<(Position, String)> {
+    let hints = backend
+        .inlay_hint(tower_lsp::lsp_types::InlayHintParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            range: Range::new(Position::new(0, 0), Position::new(99, 0)),
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap_or_default();
+    let mut labels = hints
+        .into_iter()
+        .map(|hint| match hint.label {
+            InlayHintLabel::String(label) => (hint.position, label),
+            InlayHintLabel::LabelParts(parts) => (
+                hint.position,
+                parts.into_iter().map(|part| part.value).collect(),
+            ),
+        })
+        .collect::<Vec<_>>();
+    labels.sort_by_key(|(position, _)| (position.line, position.character));
+    labels
+}
+
+#[tokio::test]
+async fn return_type_hints_before_the_body() {
+    let text = "fn answer() {\n    42\n}\n";
+    let (backend, _) = server_with(
+        json!({ "inlayHints": { "returnTypes": true } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, text).await;
+    let labels = inlay_hint_labels(&backend).await;
+    assert!(labels.contains(&(Position::new(0, 12), "-> number".to_string())));
+
+    let (backend, _) = server().await;
+    open(&backend, text).await;
+    let labels = inlay_hint_labels(&backend).await;
+    assert!(labels.iter().all(|(_, label)| !label.starts_with("->")));
+}
+
+/// Replaces the whole text of [`uri`], at `version`.
+async fn change(backend: &Backend<MockClient>, version: i32, text: &str) {
+    backend
+        .did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri(), version),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: text.to_string(),
+            }],
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn content_hash_follows_edits() {
+    let (backend, client) = server().await;
+    open(&backend, "fn main() {\n    1\n}\n").await;
+    let opened = backend.content_hash(&uri()).unwrap();
+
+    // Sending the same text again changes nothing, and isn't analyzed again
+    change(&backend, 2, "fn main() {\n    1\n}\n").await;
+    assert_eq!(backend.content_hash(&uri()), Some(opened));
+    assert_eq!(client.published_diagnostics().len(), 1);
+
+    change(&backend, 3, "fn main() {\n    2\n}\n").await;
+    let edited = backend.content_hash(&uri()).unwrap();
+    assert_ne!(edited, opened);
+    assert_eq!(client.published_diagnostics().len(), 2);
+
+    change(&backend, 4, "fn main() {\n    1\n}\n").await;
+    assert_eq!(backend.content_hash(&uri()), Some(opened));
+}
+
+#[tokio::test]
+async fn keywords_cannot_be_renamed() {
+    let (backend, _) = server().await;
+    open(&backend, "fn main() {\n    let x = 1;\n    x\n}\n").await;
+    let error = backend
+        .prepare_rename(position_params(1, 5))
+        .await
+        .unwrap_err();
+    assert_eq!(error.code, tower_lsp::jsonrpc::ErrorCode::InvalidParams);
+    assert!(error.message.contains("`let` is a keyword"));
+
+    let range = backend.prepare_rename(position_params(1, 8)).await.unwrap();
+    assert_eq!(
+        range,
+        Some(PrepareRenameResponse::Range(Range::new(
+            Position::new(1, 8),
+            Position::new(1, 9)
+        )))
+    );
+
+    let error = backend
+        .rename(RenameParams {
+            text_document_position: position_params(1, 8),
+            new_name: "if".to_string(),
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap_err();
+    assert!(error.message.contains("`if` is a keyword"));
+}
+
+/// The quick fixes offered for `diagnostics` in `range` of [`uri`].
+async fn quick_fixes(
+    backend: &Backend<MockClient>,
+    range: Range,
+    diagnostics: Vec<Diagnostic>,
+) -> Vec<CodeActionOrCommand> {
+    backend
+        .code_action(CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            range,
+            context: CodeActionContext {
+                diagnostics,
+                only: Some(vec![CodeActionKind::QUICKFIX]),
+                trigger_kind: None,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn unresolved_symbols_offer_corrections() {
+    let (backend, client) = server().await;
+    open(&backend, "fn main(count) {\n    cuont + 1\n}\n").await;
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    assert_eq!(codes(&diagnostics), ["unresolved-symbol"]);
+    let range = Range::new(Position::new(1, 4), Position::new(1, 9));
+    assert_eq!(diagnostics[0].range, range);
+
+    let actions = quick_fixes(&backend, range, diagnostics).await;
+    let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+        panic!("expected a single code action, got {:?}", actions);
+    };
+    assert_eq!(action.title, "Change to `count`");
+    assert_eq!(action.kind, Some(CodeActionKind::QUICKFIX));
+    let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri()];
+    assert_eq!(edits, &[TextEdit::new(range, "count".to_string())]);
+}
+
+#[tokio::test]
+async fn deprecated_symbols_are_tagged() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "/// @deprecated use `new`\nfn old(a) {\n    a\n}\nfn new(a, b) {\n    a + b\n}\n",
+    )
+    .await;
+    let symbols = backend
+        .document_symbol(DocumentSymbolParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    let Some(DocumentSymbolResponse::Nested(mut symbols)) = symbols else {
+        panic!("expected nested document symbols");
+    };
+    symbols.sort_by_key(|symbol| symbol.range.start.line);
+    let outline = symbols
+        .iter()
+        .map(|symbol| {
+            (
+                symbol.name.as_str(),
+                symbol.detail.as_deref(),
+                symbol.tags.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outline,
+        [
+            ("old", Some("fn old(a)"), Some(vec![SymbolTag::DEPRECATED])),
+            ("new", Some("fn new(a, b)"), None),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn documents_with_syntax_errors_are_not_formatted() {
+    let broken = "fn main() {\nlet x = ;\n}\n";
+    let (backend, client) = server_with(
+        json!({ "trace": { "server": "messages" } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, broken).await;
+    assert_eq!(backend.formatting(formatting_params()).await.unwrap(), None);
+    let range_edits = backend
+        .range_formatting(DocumentRangeFormattingParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            range: Range::new(Position::new(1, 0), Position::new(1, 0)),
+            options: formatting_params().options,
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(range_edits, None);
+    let skipped = client
+        .log_messages()
+        .into_iter()
+        .filter(|(_, message)| message.contains("has syntax errors, skipping formatting"))
+        .count();
+    assert_eq!(skipped, 2);
+
+    let (backend, _) = server_with(
+        json!({ "formatter": { "skipOnError": false } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, broken).await;
+    let edits = backend
+        .formatting(formatting_params())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(edits[0].new_text, "    let x = ;");
+}
+
+fn link_support() -> ClientCapabilities {
+    ClientCapabilities {
+        text_document: Some(TextDocumentClientCapabilities {
+            definition: Some(GotoCapability {
+                dynamic_registration: None,
+                link_support: Some(true),
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+async fn definition_at(
+    backend: &Backend<MockClient>,
+    line: u32,
+    character: u32,
+) -> Option<GotoDefinitionResponse> {
+    backend
+        .goto_definition(GotoDefinitionParams {
+            text_document_position_params: position_params(line, character),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn definitions_are_links_when_the_client_supports_them() {
+    let text = "fn add(a) {\n    a\n}\nfn main() {\n    add(1)\n}\n";
+    let name = Range::new(Position::new(0, 3), Position::new(0, 6));
+
+    let (backend, _) = server().await;
+    open(&backend, text).await;
+    assert_eq!(
+        definition_at(&backend, 4, 5).await,
+        Some(GotoDefinitionResponse::Scalar(Location::new(uri(), name)))
+    );
+
+    let (backend, _) = server_with(json!({}), link_support()).await;
+    open(&backend, text).await;
+    let Some(GotoDefinitionResponse::Link(links)) = definition_at(&backend, 4, 5).await else {
+        panic!("expected a location link");
+    };
+    assert_eq!(links.len(), 1);
+    assert_eq!(links[0].target_uri, uri());
+    assert_eq!(links[0].target_selection_range, name);
+    assert_eq!(links[0].target_range.start, Position::new(0, 0));
+    assert!(links[0].target_range.end >= Position::new(2, 1));
+    assert_eq!(
+        links[0].origin_selection_range,
+        Some(Range::new(Position::new(4, 4), Position::new(4, 7)))
+    );
+
+    // Bindings have no declaration around their name
+    let Some(GotoDefinitionResponse::Link(links)) = definition_at(&backend, 1, 4).await else {
+        panic!("expected a location link");
+    };
+    let argument = Range::new(Position::new(0, 7), Position::new(0, 8));
+    assert_eq!(links[0].target_range, argument);
+    assert_eq!(links[0].target_selection_range, argument);
+}
+
+async fn close_uri(backend: &Backend<MockClient>, uri: &Url) {
+    backend
+        .did_close(DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn closed_documents_are_evicted_past_the_cache_capacity() {
+    let (backend, _) = server_with(
+        json!({ "cache": { "capacity": 2 } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    let uris = (0..4)
+        .map(|i| Url::parse(&format!("file:///workspace/doc{}.nrs", i)).unwrap())
+        .collect::<Vec<_>>();
+    open_uri(&backend, &uris[0], "fn a() { 1 }\n").await;
+    open_uri(&backend, &uris[1], "fn b() { 2 }\n").await;
+    close_uri(&backend, &uris[0]).await;
+    assert!(backend.ast_map.contains_key(uris[0].as_str()));
+
+    open_uri(&backend, &uris[2], "fn c() { 3 }\n").await;
+    open_uri(&backend, &uris[3], "fn d() { 4 }\n").await;
+    assert!(!backend.ast_map.contains_key(uris[0].as_str()));
+    assert!(!backend.document_map.contains_key(uris[0].as_str()));
+    // Open documents stay, even past the capacity
+    for uri in &uris[1..] {
+        assert!(backend.ast_map.contains_key(uri.as_str()));
+    }
+}
+
+#[tokio::test]
+async fn disabled_features_are_not_advertised_or_answered() {
+    let settings = json!({ "features": { "hover": false, "completion": false } });
+    let capabilities = capabilities_with(settings.clone()).await;
+    assert_eq!(capabilities.hover_provider, None);
+    assert_eq!(capabilities.completion_provider, None);
+    assert!(capabilities.document_formatting_provider.is_some());
+
+    let (backend, _) = server_with(settings, ClientCapabilities::default()).await;
+    open(&backend, "fn main() {\n    0xff\n}\n").await;
+    assert_eq!(hover_at(&backend, 1, 5).await, None);
+    assert!(completion_items(&backend, 1, 4).await.is_empty());
+
+    let (backend, _) = server().await;
+    open(&backend, "fn main() {\n    0xff\n}\n").await;
+    assert!(hover_at(&backend, 1, 5).await.is_some());
+}
+
+#[tokio::test]
+async fn trailing_commas_are_hinted_only_when_enabled() {
+    let text = "fn main() {\n    [1, 2,]\n}\n";
+    let (backend, client) = server().await;
+    open(&backend, text).await;
+    assert_eq!(client.diagnostics(&uri()), Some(vec![]));
+
+    let (backend, client) = server_with(
+        json!({ "lint": { "trailingComma": true } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, text).await;
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    assert_eq!(codes(&diagnostics), ["trailing-comma"]);
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::HINT));
+    assert_eq!(
+        diagnostics[0].range,
+        Range::new(Position::new(1, 9), Position::new(1, 10))
+    );
+}
+
+#[tokio::test]
+async fn pushed_inlay_hints_match_the_pulled_ones() {
+    let text = "fn main() {\n    let x = 1;\n    x\n}\n";
+    let (backend, client) = server().await;
+    open(&backend, text).await;
+    assert!(client.notifications("gen/inlayHints").is_empty());
+
+    let (backend, client) = server_with(
+        json!({ "inlayHints": { "push": true } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, text).await;
+    let pushed = client.notifications("gen/inlayHints");
+    assert_eq!(pushed.len(), 1);
+    assert_eq!(pushed[0]["uri"], json!(uri()));
+    let pulled = backend
+        .inlay_hint(tower_lsp::lsp_types::InlayHintParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            range: Range::new(Position::new(0, 0), Position::new(4, 0)),
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!pulled.is_empty());
+    assert_eq!(pushed[0]["hints"], json!(pulled));
+}
+
+#[tokio::test]
+async fn mixed_indentation_is_reported_with_a_fix() {
+    let (backend, client) = server().await;
+    open(&backend, "fn main() {\n \tlet x = 1;\n    x\n}\n").await;
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    assert_eq!(codes(&diagnostics), ["mixed-indentation"]);
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+    let range = Range::new(Position::new(1, 0), Position::new(1, 2));
+    assert_eq!(diagnostics[0].range, range);
+
+    let actions = quick_fixes(&backend, range, diagnostics).await;
+    let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+        panic!("expected a single code action, got {:?}", actions);
+    };
+    assert_eq!(action.title, "Convert indentation to spaces");
+    let edits = &action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri()];
+    assert_eq!(edits, &[TextEdit::new(range, "    ".to_string())]);
+}
+
+/// Set once [`slow_formatting_fails_with_a_timeout`] got its answer, which [`slow_format`] waits
+/// for.
+static FORMATTING_TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+/// A formatter that can't finish before the request times out.
+fn slow_format(src: &str, _: &FormattingOptions) -> String {
+    while !FORMATTING_TIMED_OUT.load(Ordering::SeqCst) {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    src.to_string()
+}
+
+#[tokio::test]
+async fn slow_formatting_fails_with_a_timeout() {
+    let (mut backend, _) = server_with(
+        json!({ "formatter": { "timeoutMs": 20 } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    backend.formatter = slow_format;
+    open(&backend, "fn main() {\n1\n}\n").await;
+    let error = backend.formatting(formatting_params()).await.unwrap_err();
+    FORMATTING_TIMED_OUT.store(true, Ordering::SeqCst);
+    assert_eq!(error.code, ErrorCode::ServerError(REQUEST_FAILED));
+    assert_eq!(
+        error.message,
+        format!("formatting {} took longer than 20ms", uri())
+    );
+}
+
+fn snippet_support() -> ClientCapabilities {
+    ClientCapabilities {
+        text_document: Some(TextDocumentClientCapabilities {
+            completion: Some(CompletionClientCapabilities {
+                completion_item: Some(CompletionItemCapability {
+                    snippet_support: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn functions_complete_as_call_snippets() {
+    let text = "fn add(a, b) {\n    a + b\n}\nfn main() {\n    ad\n}\n";
+    let insertion = |items: Vec<CompletionItem>| {
+        let add = items.into_iter().find(|item| item.label == "add").unwrap();
+        let Some(CompletionTextEdit::Edit(edit)) = add.text_edit else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(
+            edit.range,
+            Range::new(Position::new(4, 4), Position::new(4, 6))
+        );
+        (edit.new_text, add.insert_text_format.unwrap())
+    };
+
+    let (backend, _) = server_with(json!({}), snippet_support()).await;
+    open(&backend, text).await;
+    assert_eq!(
+        insertion(completion_items(&backend, 4, 6).await),
+        (
+            "add(${1:a}, ${2:b})$0".to_string(),
+            InsertTextFormat::SNIPPET
+        )
+    );
+
+    let (backend, _) = server().await;
+    open(&backend, text).await;
+    assert_eq!(
+        insertion(completion_items(&backend, 4, 6).await),
+        ("add".to_string(), InsertTextFormat::PLAIN_TEXT)
+    );
+}
+
+#[tokio::test]
+async fn diagnostics_carry_the_document_version() {
+    let (backend, client) = server().await;
+    open(&backend, "fn main() {\n    1\n}\n").await;
+    change(&backend, 7, "fn main() {\n    x\n}\n").await;
+    save(&backend).await;
+    let versions = client
+        .published_diagnostics()
+        .iter()
+        .map(|published| published.version)
+        .collect::<Vec<_>>();
+    assert_eq!(versions, [Some(1), Some(7)]);
+}
+
+#[tokio::test]
+async fn enclosing_function_command() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "fn one() {\n    1\n}\n\nfn main() {\n    let x = [1, [2, (3)]];\n    x\n}\n",
+    )
+    .await;
+    let at = |line, character| json!({ "uri": uri(), "position": Position::new(line, character) });
+    let range = execute(&backend, "gen.enclosingFunction", at(5, 22))
+        .await
+        .unwrap()
+        .map(|range| serde_json::from_value::<Range>(range).unwrap());
+    assert_eq!(
+        range,
+        Some(Range::new(Position::new(4, 0), Position::new(7, 1)))
+    );
+    let range = execute(&backend, "gen.enclosingFunction", at(3, 0))
+        .await
+        .unwrap();
+    assert_eq!(range, None);
+}
+
+#[tokio::test]
+async fn untitled_documents_get_semantic_tokens() {
+    let capabilities = capabilities_with(json!({})).await;
+    let Some(SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(options)) =
+        capabilities.semantic_tokens_provider
+    else {
+        panic!("expected semantic tokens registration options");
+    };
+    let schemes = options
+        .text_document_registration_options
+        .document_selector
+        .unwrap()
+        .into_iter()
+        .filter_map(|filter| filter.scheme)
+        .collect::<Vec<_>>();
+    assert!(schemes.contains(&"untitled".to_string()));
+
+    let (backend, _) = server().await;
+    let untitled = Url::parse("untitled:Untitled-1").unwrap();
+    open_uri(
+        &backend,
+        &untitled,
+        "fn main() {\n    let x = 1;\n    x\n}\n",
+    )
+    .await;
+    let tokens = backend
+        .semantic_tokens_full(SemanticTokensParams {
+            text_document: TextDocumentIdentifier::new(untitled),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(
+        tokens,
+        Some(SemanticTokensResult::Tokens(tokens)) if !tokens.data.is_empty()
+    ));
+}
+
+async fn save(backend: &Backend<MockClient>) {
+    backend
+        .did_save(DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            text: None,
+        })
+        .await;
+}
+
+fn info_messages(client: &MockClient) -> Vec<String> {
+    client
+        .log_messages()
+        .into_iter()
+        .filter(|(typ, _)| *typ == MessageType::INFO)
+        .map(|(_, message)| message)
+        .collect()
+}
+
+#[tokio::test]
+async fn info_logs_follow_the_trace_level() {
+    let (backend, client) = server().await;
+    save(&backend).await;
+    assert!(info_messages(&client).is_empty());
+
+    backend
+        .set_trace(SetTraceParams {
+            value: TraceValue::Messages,
+        })
+        .await;
+    save(&backend).await;
+    assert_eq!(info_messages(&client), ["file saved!"]);
+
+    // The configured level wins over the one sent with `initialize`
+    let client = MockClient::default();
+    let backend = Backend::new(client.clone());
+    backend
+        .initialize(InitializeParams {
+            initialization_options: Some(json!({ "gen": { "trace": { "server": "off" } } })),
+            trace: Some(TraceValue::Verbose),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    save(&backend).await;
+    assert!(info_messages(&client).is_empty());
+}
+
+/// The messages of the `$/logTrace` notifications sent to `client`.
+fn traces(client: &MockClient) -> Vec<String> {
+    client
+        .notifications("$/logTrace")
+        .into_iter()
+        .map(|trace| trace["message"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn operations_are_traced_only_at_verbose() {
+    let text = "fn main() {\n    1\n}\n";
+    let (backend, client) = server().await;
+    open(&backend, text).await;
+    assert!(traces(&client).is_empty());
+
+    let (backend, client) = server_with(
+        json!({ "trace": { "server": "messages" } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, text).await;
+    assert!(!traces(&client)
+        .iter()
+        .any(|trace| trace.starts_with("parse ") || trace.starts_with("analyze ")));
+
+    let client = MockClient::default();
+    let backend = Backend::new(client.clone());
+    backend
+        .initialize(InitializeParams {
+            trace: Some(TraceValue::Verbose),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    backend.initialized(InitializedParams {}).await;
+    open(&backend, text).await;
+    let operations = client
+        .notifications("$/logTrace")
+        .into_iter()
+        .filter(|trace| {
+            trace["verbose"]
+                .as_str()
+                .is_some_and(|v| v.starts_with("took "))
+        })
+        .map(|trace| trace["message"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        operations,
+        [format!("parse {}", uri()), format!("analyze {}", uri())]
+    );
+}
+
+#[tokio::test]
+async fn references_are_grouped_by_enclosing_function() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "fn add(a, b) {\n    a + b\n}\nfn one() {\n    add(1, 2)\n}\nfn two() {\n    add(add(1, 2), 3)\n}\n",
+    )
+    .await;
+    let groups = backend
+        .references_grouped(ReferenceParams {
+            text_document_position: position_params(0, 4),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: ReferenceContext {
+                include_declaration: true,
+            },
+        })
+        .await
+        .unwrap();
+    let groups = groups
+        .iter()
+        .map(|group| {
+            let lines = group
+                .locations
+                .iter()
+                .map(|location| (location.range.start.line, location.range.start.character))
+                .collect::<Vec<_>>();
+            (group.container.as_deref(), lines)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        groups,
+        [
+            (Some("add"), vec![(0, 3)]),
+            (Some("one"), vec![(4, 4)]),
+            (Some("two"), vec![(7, 4), (7, 8)]),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn recently_used_symbols_sort_first() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "fn alpha() {\n    1\n}\nfn beta() {\n    2\n}\nfn main() {\n    \n}\n",
+    )
+    .await;
+    let labels = |items: Vec<CompletionItem>| {
+        items
+            .into_iter()
+            .map(|item| item.label)
+            .filter(|label| label == "alpha" || label == "beta")
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        labels(completion_items(&backend, 7, 4).await),
+        ["alpha", "beta"]
+    );
+
+    execute(&backend, "gen.recordCompletion", json!("beta"))
+        .await
+        .unwrap();
+    assert_eq!(
+        labels(completion_items(&backend, 7, 4).await),
+        ["beta", "alpha"]
+    );
+
+    // Navigating to a definition counts as a use as well
+    definition_at(&backend, 0, 4).await;
+    assert_eq!(
+        labels(completion_items(&backend, 7, 4).await),
+        ["alpha", "beta"]
+    );
+}
+
+#[tokio::test]
+async fn recent_symbols_are_capped() {
+    let (backend, _) = server().await;
+    for i in 0..=RECENT_SYMBOLS_CAPACITY {
+        backend.record_recent_symbol(&format!("symbol{}", i));
+    }
+    let recent_symbols = backend.recent_symbols.lock().unwrap();
+    assert_eq!(recent_symbols.recency("symbol0"), None);
+    assert_eq!(
+        recent_symbols.recency("symbol1"),
+        Some(RECENT_SYMBOLS_CAPACITY - 1)
+    );
+    assert_eq!(
+        recent_symbols.recency(&format!("symbol{}", RECENT_SYMBOLS_CAPACITY)),
+        Some(0)
+    );
+}
+
+#[tokio::test]
+async fn malformed_command_arguments_are_invalid_params() {
+    let (backend, _) = server().await;
+    open(&backend, "fn main() {\n    1\n}\n").await;
+    let run = |command: &str, arguments: Vec<Value>| {
+        backend.execute_command(ExecuteCommandParams {
+            command: command.to_string(),
+            arguments,
+            work_done_progress_params: Default::default(),
+        })
+    };
+
+    let error = run("gen.matchBrace", vec![]).await.unwrap_err();
+    assert_eq!(error.code, ErrorCode::InvalidParams);
+    assert_eq!(
+        error.message,
+        "gen.matchBrace expects a single argument: { uri, position }"
+    );
+    let error = run("gen.matchBrace", vec![json!({ "uri": uri() })])
+        .await
+        .unwrap_err();
+    assert_eq!(error.code, ErrorCode::InvalidParams);
+    let error = run("gen.recordCompletion", vec![json!(42)])
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.message,
+        "gen.recordCompletion expects a single argument: a symbol name"
+    );
+    let error = run("gen.fixAll", vec![json!({ "uri": uri() }), json!(1)])
+        .await
+        .unwrap_err();
+    assert_eq!(error.code, ErrorCode::InvalidParams);
+
+    assert!(run("gen.documentStats", vec![json!({ "uri": uri() })])
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn enter_between_braces_opens_an_indented_body() {
+    let (backend, _) = server().await;
+    open(&backend, "fn main() {\n}\n").await;
+    let on_type = |ch: &str| DocumentOnTypeFormattingParams {
+        text_document_position: position_params(1, 0),
+        ch: ch.to_string(),
+        options: formatting_params().options,
+    };
+    let edits = backend.on_type_formatting(on_type("\n")).await.unwrap();
+    assert_eq!(
+        edits,
+        Some(vec![TextEdit::new(
+            Range::new(Position::new(1, 0), Position::new(1, 0)),
+            "    \n".to_string()
+        )])
+    );
+    assert_eq!(
+        backend.on_type_formatting(on_type("}")).await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn warms_up_and_analyzes_opened_documents_eagerly() {
+    let (backend, client) = server_with(
+        json!({ "trace": { "server": "messages" } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    assert!(info_messages(&client)
+        .iter()
+        .any(|message| message.starts_with("warmed up in ")));
+
+    open(&backend, "fn main() {\n    let x = 1;\n    x\n}\n").await;
+    assert!(backend.ast_map.contains_key(uri().as_str()));
+    assert!(backend.node_index_map.contains_key(uri().as_str()));
+    assert!(backend.semantic_token_map.contains_key(uri().as_str()));
+    assert!(backend.content_hash(&uri()).is_some());
+}
+
+#[test]
+fn warmup_source_is_valid() {
+    let (ast, errors, _) = parse(WARMUP_SOURCE);
+    assert!(errors.is_empty());
+    assert!(ast.is_some_and(|ast| get_unresolved_symbols(&ast).is_empty()));
+}
+
+#[tokio::test]
+async fn shadowing_is_a_hint_and_redeclaration_an_error() {
+    let (backend, client) = server().await;
+    open(&backend, "fn main(a) {\n    let a = a + 1;\n    a\n}\n").await;
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    assert_eq!(codes(&diagnostics), ["shadowed-binding"]);
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::HINT));
+
+    let range = diagnostics[0].range;
+    let actions = quick_fixes(&backend, range, diagnostics).await;
+    let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+        panic!("expected a single code action, got {:?}", actions);
+    };
+    assert_eq!(action.title, "Rename to `a2`");
+    let mut edits = action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri()].clone();
+    edits.sort_by_key(|edit| edit.range.start);
+    assert_eq!(
+        edits,
+        [
+            TextEdit::new(
+                Range::new(Position::new(1, 8), Position::new(1, 9)),
+                "a2".to_string()
+            ),
+            TextEdit::new(
+                Range::new(Position::new(2, 4), Position::new(2, 5)),
+                "a2".to_string()
+            ),
+        ]
+    );
+
+    change(
+        &backend,
+        2,
+        "fn main() {\n    let x = 1;\n    let x = 2;\n    x\n}\n",
+    )
+    .await;
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    assert_eq!(codes(&diagnostics), ["redeclared-binding"]);
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::ERROR));
+    assert_eq!(
+        diagnostics[0].related_information.as_ref().unwrap()[0]
+            .location
+            .range
+            .start,
+        Position::new(1, 8)
+    );
+}
+
+#[tokio::test]
+async fn command_edits_are_returned_or_applied_by_capability() {
+    let text = "fn main(count) {\n    cuont\n}\n";
+    let argument = json!({ "uri": uri() });
+    let expected = WorkspaceEdit::new(HashMap::from([(
+        uri(),
+        vec![TextEdit::new(
+            Range::new(Position::new(1, 4), Position::new(1, 9)),
+            "count".to_string(),
+        )],
+    )]));
+
+    let (backend, client) = server().await;
+    open(&backend, text).await;
+    let result = execute(&backend, "gen.fixAll", argument.clone())
+        .await
+        .unwrap();
+    assert_eq!(result, Some(json!(expected)));
+    assert!(client.applied_edits().is_empty());
+
+    let (backend, client) = server_with(
+        json!({}),
+        ClientCapabilities {
+            workspace: Some(WorkspaceClientCapabilities {
+                apply_edit: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+    .await;
+    open(&backend, text).await;
+    let result = execute(&backend, "gen.fixAll", argument).await.unwrap();
+    assert_eq!(result, None);
+    assert_eq!(client.applied_edits(), [expected]);
+}
+
+#[tokio::test]
+async fn long_lines_are_reported_from_the_limit_column() {
+    let text = "fn main() {\n    let long_name = 1;\n    long_name\n}\n";
+    let (backend, client) = server().await;
+    open(&backend, text).await;
+    assert_eq!(client.diagnostics(&uri()), Some(vec![]));
+
+    let (backend, client) = server_with(
+        json!({ "lint": { "maxLineLength": 16 } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, text).await;
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    assert_eq!(codes(&diagnostics), ["max-line-length"]);
+    assert_eq!(
+        diagnostics[0].range,
+        Range::new(Position::new(1, 16), Position::new(1, 22))
+    );
+    assert_eq!(
+        diagnostics[0].message,
+        "line is 22 columns wide, the maximum is 16"
+    );
+}
+
+#[tokio::test]
+async fn completion_order_is_stable() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "fn beta(b) {\n    b\n}\nfn alpha(a) {\n    a\n}\nfn main(arg) {\n    let value = 1;\n    a\n}\n",
+    )
+    .await;
+    let labels = || async {
+        completion_items(&backend, 8, 5)
+            .await
+            .into_iter()
+            .map(|item| item.label)
+            .collect::<Vec<_>>()
+    };
+    let first = labels().await;
+    let names = first
+        .iter()
+        .filter(|label| ["alpha", "arg", "beta", "main", "value"].contains(&label.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+    // What starts with the typed `a` comes first, then what only matches it fuzzily, each
+    // alphabetically
+    assert_eq!(names, ["alpha", "arg", "beta", "main", "value"]);
+    assert!(!first.contains(&"let".to_string()));
+    for _ in 0..5 {
+        assert_eq!(labels().await, first);
+    }
+}
+
+#[tokio::test]
+async fn version_gaps_desync_the_document_until_a_full_sync() {
+    let text = "fn main(count) {\n    cuont\n}\n";
+    let (backend, client) = server_with(
+        json!({ "trace": { "server": "messages" } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, text).await;
+    let ranged = |version| DidChangeTextDocumentParams {
+        text_document: VersionedTextDocumentIdentifier::new(uri(), version),
+        content_changes: vec![TextDocumentContentChangeEvent {
+            range: Some(Range::new(Position::new(1, 4), Position::new(1, 9))),
+            range_length: None,
+            text: "cuont".to_string(),
+        }],
+    };
+    backend.did_change(ranged(2)).await;
+    assert!(!backend.is_desynced(&uri()));
+    backend.did_change(ranged(5)).await;
+    backend.did_change(ranged(7)).await;
+    assert!(backend.is_desynced(&uri()));
+    let warnings = client
+        .log_messages()
+        .into_iter()
+        .filter(|(typ, _)| *typ == MessageType::WARNING)
+        .map(|(_, message)| message)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        [format!(
+            "{} skipped from version 2 to 5, reopen it to resynchronize",
+            uri()
+        )]
+    );
+
+    // Features producing edits answer with nothing rather than edits for stale content
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    let range = diagnostics[0].range;
+    let actions = backend
+        .code_action(CodeActionParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            range,
+            context: CodeActionContext {
+                diagnostics,
+                only: None,
+                trigger_kind: None,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(actions, None);
+
+    change(&backend, 8, text).await;
+    assert!(!backend.is_desynced(&uri()));
+    backend.did_change(ranged(10)).await;
+    assert!(backend.is_desynced(&uri()));
+    open(&backend, text).await;
+    assert!(!backend.is_desynced(&uri()));
+}
+
+#[tokio::test]
+async fn toggle_comment_command() {
+    let (backend, _) = server().await;
+    open(&backend, "fn main() {\n    let x = 1;\n    x\n}\n").await;
+    let toggle = |start, end| {
+        execute(
+            &backend,
+            "gen.toggleComment",
+            json!({ "uri": uri(), "range": Range::new(start, end) }),
+        )
+    };
+    let lines = |edits: Option<Value>| {
+        serde_json::from_value::<Vec<TextEdit>>(edits.unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|edit| (edit.range.start.line, edit.new_text))
+            .collect::<Vec<_>>()
+    };
+    let edits = toggle(Position::new(1, 6), Position::new(2, 1))
+        .await
+        .unwrap();
+    assert_eq!(
+        lines(edits),
+        [(1, "// ".to_string()), (2, "// ".to_string())]
+    );
+    // A selection ending at the start of a line doesn't cover it
+    let edits = toggle(Position::new(1, 6), Position::new(2, 0))
+        .await
+        .unwrap();
+    assert_eq!(lines(edits), [(1, "// ".to_string())]);
+    // Positions past the end are clamped to it, inverted ranges toggle nothing
+    let edits = toggle(Position::new(3, 0), Position::new(9, 4))
+        .await
+        .unwrap();
+    assert_eq!(lines(edits), [(3, "// ".to_string())]);
+    let edits = toggle(Position::new(2, 0), Position::new(1, 0))
+        .await
+        .unwrap();
+    assert_eq!(edits, None);
+}
+
+#[tokio::test]
+async fn published_diagnostics_are_capped() {
+    let (backend, client) = server_with(
+        json!({ "lint": { "maxDiagnostics": 2 } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, "fn main() {\n    a + b + c + d\n}\n").await;
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    assert_eq!(
+        codes(&diagnostics),
+        [
+            "unresolved-symbol",
+            "unresolved-symbol",
+            "truncated-diagnostics"
+        ]
+    );
+    assert_eq!(diagnostics[0].message, "cannot find `a` in this scope");
+    assert!(diagnostics[2].message.starts_with("2 more diagnostics"));
+}
+
+/// `tokens` with `edits` applied, as the client does with a semantic tokens delta.
+fn apply_token_edits(tokens: &[SemanticToken], edits: &[SemanticTokensEdit]) -> Vec<SemanticToken> {
+    let mut tokens = tokens.to_vec();
+    for edit in edits.iter().rev() {
+        let start = edit.start as usize / 5;
+        let end = start + edit.delete_count as usize / 5;
+        tokens.splice(start..end, edit.data.clone().unwrap_or_default());
+    }
+    tokens
+}
+
+#[tokio::test]
+async fn token_deltas_after_a_multi_line_deletion_match_a_full_recompute() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "fn main() {\n    let a = 1;\n    let b = [\n        2,\n        3\n    ];\n    a + 0x10\n}\n",
+    )
+    .await;
+    let full = || async {
+        let tokens = backend
+            .semantic_tokens_full(SemanticTokensParams {
+                text_document: TextDocumentIdentifier::new(uri()),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap();
+        match tokens {
+            Some(SemanticTokensResult::Tokens(tokens)) => tokens,
+            _ => panic!("expected semantic tokens"),
+        }
+    };
+    let previous = full().await;
+
+    change(
+        &backend,
+        2,
+        "fn main() {\n    let a = 1;\n    a + 0x10\n}\n",
+    )
+    .await;
+    let delta = backend
+        .semantic_tokens_full_delta(SemanticTokensDeltaParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            previous_result_id: previous.result_id.clone().unwrap(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    let Some(SemanticTokensFullDeltaResult::TokensDelta(delta)) = delta else {
+        panic!("expected a semantic tokens delta");
+    };
+    assert!(!delta.edits.is_empty());
+    let applied = apply_token_edits(&previous.data, &delta.edits);
+    let recomputed = full().await;
+    assert_eq!(applied, recomputed.data);
+    assert_eq!(delta.result_id, recomputed.result_id);
+}
+
+#[tokio::test]
+async fn empty_configuration_changes_pull_the_settings() {
+    let (backend, client) = server_with(
+        json!({}),
+        ClientCapabilities {
+            workspace: Some(WorkspaceClientCapabilities {
+                configuration: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+    .await;
+    let pulled = client.configuration_requests().len();
+    client.set_configuration(json!({ "completion": { "maxCompletions": 7 } }));
+    for settings in [Value::Null, json!({})] {
+        backend
+            .did_change_configuration(DidChangeConfigurationParams { settings })
+            .await;
+    }
+    let requests = client.configuration_requests();
+    assert_eq!(requests.len(), pulled + 2);
+    assert_eq!(requests[pulled][0].section.as_deref(), Some("gen"));
+    assert_eq!(backend.config.read().unwrap().completion.max_completions, 7);
+
+    // Settings sent along are used as they are
+    backend
+        .did_change_configuration(DidChangeConfigurationParams {
+            settings: json!({ "gen": { "completion": { "maxCompletions": 9 } } }),
+        })
+        .await;
+    assert_eq!(client.configuration_requests().len(), pulled + 2);
+    assert_eq!(backend.config.read().unwrap().completion.max_completions, 9);
+}
+
+#[tokio::test]
+async fn empty_configuration_changes_are_ignored_without_pull_support() {
+    let (backend, client) = server_with(
+        json!({ "completion": { "maxCompletions": 3 } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    backend
+        .did_change_configuration(DidChangeConfigurationParams {
+            settings: Value::Null,
+        })
+        .await;
+    assert!(client.configuration_requests().is_empty());
+    assert_eq!(backend.config.read().unwrap().completion.max_completions, 3);
+}
+
+async fn format_string(backend: &Backend<MockClient>, text: &str) -> Option<String> {
+    backend
+        .format_string(FormatStringParams {
+            text: text.to_string(),
+            options: formatting_params().options,
+        })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn format_string_is_idempotent() {
+    let (backend, _) = server().await;
+    let formatted = format_string(&backend, "fn main() {\nlet x = [\n1];\n  x\n}\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        formatted,
+        "fn main() {\n    let x = [\n        1];\n    x\n}\n"
+    );
+    assert_eq!(
+        format_string(&backend, &formatted).await,
+        Some(formatted.clone())
+    );
+
+    // A byte order mark stays where it was
+    let with_bom = format!("{}fn main() {{\n1\n}}\n", BOM);
+    assert_eq!(
+        format_string(&backend, &with_bom).await,
+        Some(format!("{}fn main() {{\n    1\n}}\n", BOM))
+    );
+    assert_eq!(
+        format_string(&backend, "fn main() {\nlet = ;\n}\n").await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn byte_order_marks_do_not_shift_positions() {
+    let (backend, client) = server().await;
+    open(&backend, &format!("{}fn main(a) {{\n    a + x\n}}\n", BOM)).await;
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    assert_eq!(
+        diagnostics[0].range,
+        Range::new(Position::new(1, 8), Position::new(1, 9))
+    );
+    assert_eq!(
+        backend.document_map.get(uri().as_str()).unwrap().char(0),
+        'f'
+    );
+    assert_eq!(
+        definition_at(&backend, 1, 4).await,
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            uri(),
+            Range::new(Position::new(0, 8), Position::new(0, 9))
+        )))
+    );
+}
+
+#[tokio::test]
+async fn resolved_completions_carry_the_doc_comment() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "/// Adds `a` and `b`.\n///\n/// Never overflows.\nfn add(a, b) {\n    a + b\n}\nfn main() {\n    ad\n}\n",
+    )
+    .await;
+    let add = completion_items(&backend, 7, 6)
+        .await
+        .into_iter()
+        .find(|item| item.label == "add")
+        .unwrap();
+    assert_eq!(add.documentation, None);
+    let resolved = backend.completion_resolve(add).await.unwrap();
+    assert_eq!(
+        resolved.documentation,
+        Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: "Adds `a` and `b`.\n\nNever overflows.".to_string(),
+        }))
+    );
+}
+
+#[tokio::test]
+async fn notifications_before_initialized_are_queued() {
+    let client = MockClient::default();
+    let backend = Backend::new(client.clone());
+    backend
+        .initialize(InitializeParams {
+            initialization_options: Some(json!({ "gen": { "inlayHints": { "push": true } } })),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    open(&backend, "fn main() {\n    let a = 1;\n    x\n}\n").await;
+    assert!(client.published_diagnostics().is_empty());
+    assert!(client.notifications("gen/inlayHints").is_empty());
+
+    backend.initialized(InitializedParams {}).await;
+    assert_eq!(
+        codes(&client.diagnostics(&uri()).unwrap()),
+        ["unresolved-symbol"]
+    );
+    assert_eq!(client.notifications("gen/inlayHints").len(), 1);
+
+    change(&backend, 2, "fn main() {\n    1\n}\n").await;
+    assert_eq!(client.published_diagnostics().len(), 2);
+    assert_eq!(client.diagnostics(&uri()), Some(vec![]));
+}
+
+async fn prepare_call_hierarchy_at(
+    backend: &Backend<MockClient>,
+    line: u32,
+    character: u32,
+) -> Option<Vec<CallHierarchyItem>> {
+    backend
+        .prepare_call_hierarchy(CallHierarchyPrepareParams {
+            text_document_position_params: position_params(line, character),
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn call_hierarchy_prepared_from_a_call_site() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "fn add(a, b) {\n    a + b\n}\nfn main(f) {\n    add(1, 2) + f(3)\n}\n",
+    )
+    .await;
+    let from_call = prepare_call_hierarchy_at(&backend, 4, 5).await.unwrap();
+    assert_eq!(
+        prepare_call_hierarchy_at(&backend, 0, 4).await,
+        Some(from_call.clone())
+    );
+    assert_eq!(from_call[0].name, "add");
+    assert_eq!(
+        from_call[0].selection_range,
+        Range::new(Position::new(0, 3), Position::new(0, 6))
+    );
+    // Calling an argument has no function to start from
+    assert_eq!(prepare_call_hierarchy_at(&backend, 4, 16).await, None);
+
+    let incoming = backend
+        .incoming_calls(CallHierarchyIncomingCallsParams {
+            item: from_call[0].clone(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(incoming.len(), 1);
+    assert_eq!(incoming[0].from.name, "main");
+    assert_eq!(
+        incoming[0].from_ranges,
+        [Range::new(Position::new(4, 4), Position::new(4, 7))]
+    );
+}
+
+#[tokio::test]
+async fn closing_clears_diagnostics_unless_kept() {
+    let text = "fn main() {\n    x\n}\n";
+    let (backend, client) = server().await;
+    open(&backend, text).await;
+    assert_eq!(client.diagnostics(&uri()).unwrap().len(), 1);
+    close_uri(&backend, &uri()).await;
+    assert_eq!(client.diagnostics(&uri()), Some(vec![]));
+    assert_eq!(client.published_diagnostics().last().unwrap().version, None);
+
+    let (backend, client) = server_with(
+        json!({ "diagnostics": { "keepClosed": true } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, text).await;
+    close_uri(&backend, &uri()).await;
+    assert_eq!(client.published_diagnostics().len(), 1);
+    assert_eq!(client.diagnostics(&uri()).unwrap().len(), 1);
+
+    // Unsaved documents have nothing left to show diagnostics for
+    let untitled = Url::parse("untitled:Untitled-1").unwrap();
+    open_uri(&backend, &untitled, text).await;
+    close_uri(&backend, &untitled).await;
+    assert_eq!(client.diagnostics(&untitled), Some(vec![]));
+}
+
+#[tokio::test]
+async fn completions_past_the_cap_are_incomplete() {
+    let mut text = (0..5)
+        .map(|i| format!("fn fun_{}() {{ {} }}\n", i, i))
+        .collect::<String>();
+    text.push_str("fn main() {\n    fun\n}\n");
+    let list = |backend: Backend<MockClient>| async move {
+        match backend.completion(completion_params(6, 7)).await.unwrap() {
+            Some(CompletionResponse::List(list)) => list,
+            response => panic!("expected a completion list, got {:?}", response),
+        }
+    };
+
+    let (backend, _) = server().await;
+    open(&backend, &text).await;
+    let all = list(backend).await;
+    assert!(!all.is_incomplete);
+    assert_eq!(
+        all.items.iter().map(|item| &item.label).collect::<Vec<_>>(),
+        ["fun_0", "fun_1", "fun_2", "fun_3", "fun_4"]
+    );
+
+    let (backend, _) = server_with(
+        json!({ "completion": { "maxCompletions": 3 } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, &text).await;
+    let capped = list(backend).await;
+    assert!(capped.is_incomplete);
+    assert_eq!(capped.items, all.items[..3]);
+}
+
+#[tokio::test]
+async fn peek_definition_command() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "fn add(a) {\n    a\n}\nfn main() {\n    let x = add(1);\n    x\n}\n",
+    )
+    .await;
+    for (line, character) in [(4, 13), (5, 4), (1, 4)] {
+        let peeked = execute(
+            &backend,
+            "gen.peekDefinition",
+            json!({ "uri": uri(), "position": Position::new(line, character) }),
+        )
+        .await
+        .unwrap()
+        .map(|location| serde_json::from_value::<Location>(location).unwrap());
+        let GotoDefinitionResponse::Scalar(location) =
+            definition_at(&backend, line, character).await.unwrap()
+        else {
+            panic!("expected a single location");
+        };
+        assert_eq!(peeked, Some(location));
+    }
+    let on_nothing = execute(
+        &backend,
+        "gen.peekDefinition",
+        json!({ "uri": uri(), "position": Position::new(2, 0) }),
+    )
+    .await;
+    assert_eq!(on_nothing, Ok(None));
+}
+
+#[tokio::test]
+async fn sort_functions_is_a_rewrite_refactor() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "/// The second.\nfn b() {\n    2\n}\n/// The first.\nfn a() {\n    1\n}\n",
+    )
+    .await;
+    let actions = |only: Option<Vec<CodeActionKind>>| {
+        let backend = &backend;
+        async move {
+            backend
+                .code_action(CodeActionParams {
+                    text_document: TextDocumentIdentifier::new(uri()),
+                    range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+                    context: CodeActionContext {
+                        diagnostics: vec![],
+                        only,
+                        trigger_kind: None,
+                    },
+                    work_done_progress_params: Default::default(),
+                    partial_result_params: Default::default(),
+                })
+                .await
+                .unwrap()
+                .unwrap_or_default()
+        }
+    };
+    assert!(actions(Some(vec![CodeActionKind::QUICKFIX]))
+        .await
+        .is_empty());
+    for only in [None, Some(vec![CodeActionKind::REFACTOR])] {
+        let actions = actions(only).await;
+        let [CodeActionOrCommand::CodeAction(action)] = actions.as_slice() else {
+            panic!("expected the sort action, got {:?}", actions);
+        };
+        assert_eq!(action.kind, Some(CodeActionKind::REFACTOR_REWRITE));
+        assert_eq!(
+            action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri()],
+            [TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(7, 1)),
+                "/// The first.\nfn a() {\n    1\n}\n/// The second.\nfn b() {\n    2\n}"
+                    .to_string()
+            )]
+        );
+    }
+}
+
+#[tokio::test]
+async fn document_symbols_are_cached_per_version() {
+    let (backend, _) = server().await;
+    open(&backend, "fn a() { 1 }\nfn b() { 2 }\n").await;
+    let symbol_names = || async {
+        let symbols = backend
+            .document_symbol(DocumentSymbolParams {
+                text_document: TextDocumentIdentifier::new(uri()),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap();
+        let Some(DocumentSymbolResponse::Nested(symbols)) = symbols else {
+            panic!("expected nested document symbols");
+        };
+        let mut names = symbols
+            .into_iter()
+            .map(|symbol| symbol.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    };
+    assert_eq!(symbol_names().await, ["a", "b"]);
+
+    // Answered from the cache without looking at the AST again
+    backend.ast_map.insert(uri().to_string(), HashMap::new());
+    assert_eq!(symbol_names().await, ["a", "b"]);
+
+    change(&backend, 2, "fn c() { 3 }\n").await;
+    assert_eq!(symbol_names().await, ["c"]);
+}
+
+#[tokio::test]
+async fn unopened_files_are_hovered_from_disk() {
+    let (backend, client) = server().await;
+    let file = DiskFile::new("hover.nrs", "fn main() {\n    0x10\n}\n");
+    let hover = || async {
+        let hover = backend
+            .hover(HoverParams {
+                text_document_position_params: TextDocumentPositionParams::new(
+                    TextDocumentIdentifier::new(file.uri.clone()),
+                    Position::new(1, 5),
+                ),
+                work_done_progress_params: Default::default(),
+            })
+            .await
+            .unwrap();
+        hover_text(hover).unwrap()
+    };
+    let before = hover().await;
+    assert!(before.contains("16"), "{}", before);
+    // Nothing is shown in the editor for a file it didn't open
+    assert!(client.published_diagnostics().is_empty());
+
+    // Until the watcher reports a change, the file is answered from the first read
+    let path = file.uri.to_file_path().unwrap();
+    std::fs::write(&path, "fn main() {\n    0x20\n}\n").unwrap();
+    assert_eq!(hover().await, before);
+    backend
+        .did_change_watched_files(DidChangeWatchedFilesParams {
+            changes: vec![FileEvent::new(file.uri.clone(), FileChangeType::CHANGED)],
+        })
+        .await;
+    let after = hover().await;
+    assert!(after.contains("32"), "{}", after);
+}
+
+/// Renames the parameter `count` of [`renames_into_a_visible_name_warn_or_fail`] to `new_name`.
+async fn rename_count(
+    backend: &Backend<MockClient>,
+    new_name: &str,
+) -> Result<Option<WorkspaceEdit>> {
+    backend
+        .rename(RenameParams {
+            text_document_position: position_params(0, 9),
+            new_name: new_name.to_string(),
+            work_done_progress_params: Default::default(),
+        })
+        .await
+}
+
+#[tokio::test]
+async fn renames_into_a_visible_name_warn_or_fail() {
+    let text = "fn main(count) {\n    let total = 1;\n    count + total\n}\n";
+
+    let (backend, client) = server().await;
+    open(&backend, text).await;
+    let edit = rename_count(&backend, "fresh").await.unwrap().unwrap();
+    assert_eq!(edit.changes.unwrap()[&uri()].len(), 2);
+    assert!(client.shown_messages().is_empty());
+    for new_name in ["total", "main"] {
+        let edit = rename_count(&backend, new_name).await.unwrap().unwrap();
+        assert_eq!(edit.changes.unwrap()[&uri()].len(), 2);
+    }
+    let shown = client.shown_messages();
+    assert_eq!(shown.len(), 2);
+    assert_eq!(shown[0].0, MessageType::WARNING);
+    assert!(
+        shown[0].1.contains("`total` is already used"),
+        "{}",
+        shown[0].1
+    );
+
+    let (backend, client) = server_with(
+        json!({ "rename": { "refuseCollisions": true } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, text).await;
+    let error = rename_count(&backend, "total").await.unwrap_err();
+    assert!(error.message.contains("`total` is already used"));
+    assert!(client.shown_messages().is_empty());
+    assert!(rename_count(&backend, "fresh").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn reindexing_is_confirmed_first() {
+    let prompting = ClientCapabilities {
+        window: Some(WindowClientCapabilities {
+            show_message: Some(ShowMessageRequestClientCapabilities::default()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let (backend, client) = server_with(json!({}), prompting).await;
+    open(&backend, "fn main() {\n    x\n}\n").await;
+    for (answer, published) in [(None, 1), (Some("No"), 1), (Some("Yes"), 2)] {
+        client.set_message_action(answer);
+        assert_eq!(
+            execute(&backend, "gen.reindexWorkspace", json!(null)).await,
+            Ok(None)
+        );
+        assert_eq!(client.published_diagnostics().len(), published);
+    }
+    assert_eq!(client.shown_messages().len(), 3);
+    assert_eq!(client.shown_messages()[0].0, MessageType::WARNING);
+
+    // Clients that can't be asked reindex right away
+    let (backend, client) = server().await;
+    open(&backend, "fn main() {\n    x\n}\n").await;
+    assert_eq!(
+        execute(&backend, "gen.reindexWorkspace", json!(null)).await,
+        Ok(None)
+    );
+    assert!(client.shown_messages().is_empty());
+    assert_eq!(client.published_diagnostics().len(), 2);
+    assert_eq!(client.diagnostics(&uri()).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn booleans_rank_first_where_one_is_expected() {
+    let labels = |items: Vec<CompletionItem>| {
+        let mut items = items;
+        items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+        items.into_iter().map(|item| item.label).collect::<Vec<_>>()
+    };
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "fn main() {\n    let flag = true;\n    let fl = flag == fl;\n    fl\n}\n",
+    )
+    .await;
+    let compared = labels(completion_items(&backend, 2, 23).await);
+    assert_eq!(compared[0], "false");
+    let plain = labels(completion_items(&backend, 3, 6).await);
+    assert_ne!(plain[0], "false");
+    assert!(plain.contains(&"false".to_string()));
+}
+
+#[tokio::test]
+async fn diagnostics_link_to_their_documentation() {
+    let text = "fn main() {\n    x\n}\n";
+    let (backend, client) = server().await;
+    open(&backend, text).await;
+    assert_eq!(
+        client.diagnostics(&uri()).unwrap()[0].code_description,
+        None
+    );
+
+    let (backend, client) = server_with(
+        json!({}),
+        ClientCapabilities {
+            text_document: Some(TextDocumentClientCapabilities {
+                publish_diagnostics: Some(PublishDiagnosticsClientCapabilities {
+                    code_description_support: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+    .await;
+    open(&backend, text).await;
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    assert_eq!(
+        diagnostics[0].code_description.as_ref().unwrap().href.as_str(),
+        "https://github.com/PedroWitzel/tower-lsp-boilerplate/blob/main/docs/diagnostics.md#unresolved-symbol"
+    );
+}
+
+#[tokio::test]
+async fn unclosed_blocks_complete_their_closing_brace() {
+    let (backend, _) = server().await;
+    open(&backend, "fn main() {\n    let x = 1;\n    x\n    \n").await;
+    let items = completion_items(&backend, 3, 4).await;
+    let close = items.iter().find(|item| item.label == "}").unwrap();
+    assert_eq!(close.sort_text.as_deref(), Some("0}"));
+    assert_eq!(
+        close.text_edit,
+        Some(CompletionTextEdit::Edit(TextEdit::new(
+            Range::new(Position::new(3, 0), Position::new(3, 4)),
+            "}".to_string()
+        )))
+    );
+
+    // A typed prefix asks for a name, not a bracket
+    let items = completion_items(&backend, 2, 5).await;
+    assert!(items.iter().all(|item| item.label != "}"));
+}
+
+#[tokio::test]
+async fn comment_tags_carry_the_tag_modifier() {
+    let (backend, _) = server().await;
+    let capabilities = capabilities_with(json!({})).await;
+    let Some(SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(options)) =
+        capabilities.semantic_tokens_provider
+    else {
+        panic!("expected semantic token registration options");
+    };
+    let tag = options
+        .semantic_tokens_options
+        .legend
+        .token_modifiers
+        .iter()
+        .position(|modifier| modifier.as_str() == "tag")
+        .unwrap();
+
+    open(&backend, "// TODO: x\nfn main() { 1 }\n").await;
+    let Some(SemanticTokensResult::Tokens(tokens)) = backend
+        .semantic_tokens_full(SemanticTokensParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+    else {
+        panic!("expected semantic tokens");
+    };
+    let tagged = tokens
+        .data
+        .iter()
+        .filter(|token| token.token_modifiers_bitset == 1 << tag)
+        .collect::<Vec<_>>();
+    assert_eq!(tagged.len(), 1);
+    assert_eq!((tagged[0].delta_start, tagged[0].length), (3, 4));
+}
+
+#[tokio::test]
+async fn reopened_documents_start_from_scratch() {
+    let (backend, client) = server().await;
+    open(&backend, "fn main() {\n    x\n}\n").await;
+    let symbols = || async {
+        backend
+            .document_symbol(DocumentSymbolParams {
+                text_document: TextDocumentIdentifier::new(uri()),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .await
+            .unwrap()
+    };
+    assert!(symbols().await.is_some());
+
+    // Reloaded with content that doesn't parse, nothing of the previous content is kept
+    open(&backend, "fn other() {\n    1 +\n").await;
+    assert_eq!(symbols().await, None);
+    assert!(definition_at(&backend, 1, 4).await.is_none());
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    assert!(!codes(&diagnostics).contains(&"unresolved-symbol".to_string()));
+
+    open(&backend, "fn other() {\n    1\n}\n").await;
+    let Some(DocumentSymbolResponse::Nested(symbols)) = symbols().await else {
+        panic!("expected nested document symbols");
+    };
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(symbols[0].name, "other");
+    assert_eq!(client.diagnostics(&uri()), Some(vec![]));
+}
+
+#[tokio::test]
+async fn fix_all_combines_every_fix() {
+    let (backend, client) = server_with(
+        json!({ "lint": { "trailingComma": true }, "trace": { "server": "messages" } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(
+        &backend,
+        "fn main(count) {\n    let x = [1, 2,];\n    let x = cuont;\n    x\n}\n",
+    )
+    .await;
+    let fixed = execute(&backend, "gen.fixAll", json!({ "uri": uri() }))
+        .await
+        .unwrap()
+        .unwrap();
+    let edit = |line, start, end, text: &str| {
+        TextEdit::new(
+            Range::new(Position::new(line, start), Position::new(line, end)),
+            text.to_string(),
+        )
+    };
+    assert_eq!(
+        serde_json::from_value::<WorkspaceEdit>(fixed).unwrap(),
+        WorkspaceEdit::new(HashMap::from([(
+            uri(),
+            vec![
+                edit(1, 17, 18, ""),
+                edit(2, 8, 9, "x2"),
+                edit(2, 12, 17, "count"),
+                edit(3, 4, 5, "x2"),
+            ]
+        )]))
+    );
+    assert!(info_messages(&client).contains(&format!("3 fixes applied to {}", uri())));
+}
+
+#[tokio::test]
+async fn renames_are_versioned_document_changes_when_supported() {
+    let text = "fn main(count) {\n    count + 1\n}\n";
+    let edits = vec![
+        OneOf::Left(TextEdit::new(
+            Range::new(Position::new(0, 8), Position::new(0, 13)),
+            "total".to_string(),
+        )),
+        OneOf::Left(TextEdit::new(
+            Range::new(Position::new(1, 4), Position::new(1, 9)),
+            "total".to_string(),
+        )),
+    ];
+    let (backend, _) = server_with(
+        json!({}),
+        ClientCapabilities {
+            workspace: Some(WorkspaceClientCapabilities {
+                workspace_edit: Some(WorkspaceEditClientCapabilities {
+                    document_changes: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+    .await;
+    open(&backend, text).await;
+    change(&backend, 7, text).await;
+    let renamed = rename_count(&backend, "total").await.unwrap().unwrap();
+    assert_eq!(renamed.changes, None);
+    assert_eq!(
+        renamed.document_changes,
+        Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: uri(),
+                version: Some(7),
+            },
+            edits: edits.clone(),
+        }]))
+    );
+
+    // Unopened files are renamed from their content on disk
+    let file = DiskFile::new("rename.nrs", text);
+    let renamed = backend
+        .rename(RenameParams {
+            text_document_position: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(file.uri.clone()),
+                Position::new(0, 9),
+            ),
+            new_name: "total".to_string(),
+            work_done_progress_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    let renamed = renamed.unwrap();
+    let Some(DocumentChanges::Edits(document_edits)) = renamed.document_changes else {
+        panic!("expected document edits");
+    };
+    assert_eq!(document_edits[0].text_document.uri, file.uri);
+    assert_eq!(document_edits[0].edits, edits);
+}
+
+#[tokio::test]
+async fn document_symbols_of_large_files_stream_in_chunks() {
+    let (backend, client) = server().await;
+    let text = (0..250)
+        .map(|i| format!("fn f{}() {{ {} }}\n", i, i))
+        .collect::<String>();
+    open(&backend, &text).await;
+    let symbols = backend
+        .document_symbol(DocumentSymbolParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            work_done_progress_params: Default::default(),
+            partial_result_params: tower_lsp::lsp_types::PartialResultParams {
+                partial_result_token: Some(NumberOrString::Number(7)),
+            },
+        })
+        .await
+        .unwrap();
+    assert_eq!(symbols, Some(DocumentSymbolResponse::Nested(vec![])));
+
+    let chunks = client.notifications("$/progress");
+    assert_eq!(
+        chunks
+            .iter()
+            .map(|chunk| chunk["value"].as_array().unwrap().len())
+            .collect::<Vec<_>>(),
+        [100, 100, 50]
+    );
+    let mut names = chunks
+        .iter()
+        .flat_map(|chunk| {
+            assert_eq!(chunk["token"], 7);
+            chunk["value"].as_array().unwrap().clone()
+        })
+        .map(|symbol| symbol["name"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    names.sort_by_key(|name| name[1..].parse::<usize>().unwrap());
+    assert_eq!(
+        names,
+        (0..250).map(|i| format!("f{}", i)).collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn assignments_in_conditions_are_fixed_to_comparisons() {
+    let (backend, client) = server().await;
+    open(
+        &backend,
+        "fn main(x) {\n    if x = 1 {\n        1\n    } else {\n        2\n    }\n}\n",
+    )
+    .await;
+    let diagnostics = client
+        .diagnostics(&uri())
+        .unwrap()
+        .into_iter()
+        .filter(|diagnostic| {
+            diagnostic.code
+                == Some(NumberOrString::String(
+                    "assignment-in-condition".to_string(),
+                ))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(diagnostics.len(), 1);
+    let range = Range::new(Position::new(1, 9), Position::new(1, 10));
+    assert_eq!(diagnostics[0].range, range);
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+
+    let fixes = quick_fixes(&backend, range, diagnostics).await;
+    let [CodeActionOrCommand::CodeAction(fix)] = fixes.as_slice() else {
+        panic!("expected a single fix, got {:?}", fixes);
+    };
+    assert_eq!(fix.title, "Change to `==`");
+    assert_eq!(
+        fix.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri()],
+        [TextEdit::new(range, "==".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn stale_asts_are_reparsed_before_answering() {
+    let (backend, _) = server().await;
+    open(&backend, "fn main(a) {\n    a\n}\n").await;
+
+    // The content of version 2 arrived, its analysis didn't finish yet
+    let text = "fn main() {\n    let value = 1;\n    value\n}\n";
+    backend
+        .document_map
+        .insert(uri().to_string(), Rope::from_str(text));
+    backend.version_map.insert(uri().to_string(), 2);
+    assert_eq!(*backend.ast_version_map.get(uri().as_str()).unwrap(), 1);
+
+    let definition = definition_at(&backend, 2, 6).await;
+    assert_eq!(
+        definition,
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            uri(),
+            Range::new(Position::new(1, 8), Position::new(1, 13))
+        )))
+    );
+    assert_eq!(*backend.ast_version_map.get(uri().as_str()).unwrap(), 2);
+    assert!(backend.ast_map.get(uri().as_str()).unwrap()["main"]
+        .args
+        .is_empty());
+}
+
+#[tokio::test]
+async fn document_stats_command() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "fn add(a, b) {\n    let c = a + b;\n    c\n}\nfn main() {\n    let x = y;\n    let z = w;\n    x\n}\n",
+    )
+    .await;
+    let stats = execute(&backend, "gen.documentStats", json!({ "uri": uri() })).await;
+    assert_eq!(
+        stats,
+        Ok(Some(json!({
+            // The empty line after the last line break counts, as editors show it
+            "lines": 10,
+            "symbols": 5,
+            "functions": 2,
+            "diagnostics": 2,
+        })))
+    );
+
+    let unknown = Url::parse("file:///workspace/unknown.nrs").unwrap();
+    let stats = execute(&backend, "gen.documentStats", json!({ "uri": unknown })).await;
+    assert_eq!(stats, Ok(None));
+}
+
+#[tokio::test]
+async fn function_completions_have_label_details_when_supported() {
+    let text = "fn add(a, b) {\n    a + b\n}\nfn main() {\n    ad\n}\n";
+    let add =
+        |items: Vec<CompletionItem>| items.into_iter().find(|item| item.label == "add").unwrap();
+
+    let (backend, _) = server().await;
+    open(&backend, text).await;
+    let item = add(completion_items(&backend, 4, 6).await);
+    assert_eq!(item.label_details, None);
+    assert_eq!(item.detail.as_deref(), Some("fn add(a, b) (main.nrs)"));
+
+    let (backend, _) = server_with(
+        json!({}),
+        ClientCapabilities {
+            text_document: Some(TextDocumentClientCapabilities {
+                completion: Some(CompletionClientCapabilities {
+                    completion_item: Some(CompletionItemCapability {
+                        label_details_support: Some(true),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+    .await;
+    open(&backend, text).await;
+    let item = add(completion_items(&backend, 4, 6).await);
+    assert_eq!(item.detail.as_deref(), Some("fn add(a, b)"));
+    assert_eq!(
+        item.label_details,
+        Some(CompletionItemLabelDetails {
+            detail: Some("(a, b)".to_string()),
+            description: Some("main.nrs".to_string()),
+        })
+    );
+}
+
+#[tokio::test]
+async fn requests_are_answered_while_a_change_is_analyzed_in_the_background() {
+    let mut text = (0..50)
+        .map(|i| format!("fn f{}(a) {{\n    a + {}\n}}\n", i, i))
+        .collect::<String>();
+    text.push_str("fn main() {\n    let value = 1;\n    value\n}\n");
+    let main_line = 50 * 3;
+
+    for (background, answered_first) in [(true, true), (false, false)] {
+        let (backend, _) = server_with(
+            json!({ "backgroundAnalysis": background }),
+            ClientCapabilities::default(),
+        )
+        .await;
+        open(&backend, "fn main() {\n    1\n}\n").await;
+        let analyzed = std::sync::atomic::AtomicBool::new(false);
+        let (_, (definition, analyzed_before)) = tokio::join!(
+            async {
+                change(&backend, 2, &text).await;
+                analyzed.store(true, Ordering::SeqCst);
+            },
+            async {
+                let definition = definition_at(&backend, main_line + 2, 6).await;
+                (definition, analyzed.load(Ordering::SeqCst))
+            }
+        );
+        assert_eq!(analyzed_before, !answered_first);
+        // Answered from the new content either way
+        assert_eq!(
+            definition,
+            Some(GotoDefinitionResponse::Scalar(Location::new(
+                uri(),
+                Range::new(
+                    Position::new(main_line + 1, 8),
+                    Position::new(main_line + 1, 13)
+                )
+            )))
+        );
+    }
+}
+
+#[tokio::test]
+async fn reindexing_reports_progress_when_supported() {
+    let (backend, client) = server_with(
+        json!({}),
+        ClientCapabilities {
+            window: Some(WindowClientCapabilities {
+                work_done_progress: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+    .await;
+    open(&backend, "fn main() {\n    1\n}\n").await;
+    assert_eq!(
+        execute(&backend, "gen.reindexWorkspace", json!(null)).await,
+        Ok(None)
+    );
+    assert_eq!(client.progress_tokens().len(), 1);
+    let progress = client.notifications("$/progress");
+    let kinds = progress
+        .iter()
+        .map(|progress| progress["value"]["kind"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, ["begin", "report", "end"]);
+    assert_eq!(progress[2]["value"]["message"], "1 documents reindexed");
+}
+
+#[tokio::test]
+async fn completions_replace_the_word_under_the_cursor() {
+    let (backend, _) = server().await;
+    open(&backend, "fn value() { 1 }\nfn main() {\n    valu\n}\n").await;
+    let edit_at = |character| {
+        let backend = &backend;
+        async move {
+            let item = completion_items(backend, 2, character)
+                .await
+                .into_iter()
+                .find(|item| item.label == "value")
+                .unwrap();
+            match item.text_edit {
+                Some(CompletionTextEdit::Edit(edit)) => edit,
+                edit => panic!("expected a text edit, got {:?}", edit),
+            }
+        }
+    };
+    let edit = |start, end| {
+        TextEdit::new(
+            Range::new(Position::new(2, start), Position::new(2, end)),
+            "value".to_string(),
+        )
+    };
+    // Inserted in front of the word at its start, nothing was typed yet
+    assert_eq!(edit_at(4).await, edit(4, 4));
+    // The rest of the word goes too in the middle of it
+    assert_eq!(edit_at(6).await, edit(4, 8));
+    assert_eq!(edit_at(8).await, edit(4, 8));
+}
+
+#[tokio::test]
+async fn reload_config_pulls_and_returns_the_settings() {
+    let (backend, client) = server_with(
+        json!({}),
+        ClientCapabilities {
+            workspace: Some(WorkspaceClientCapabilities {
+                configuration: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+    .await;
+    open(&backend, "fn main() {\n    0x10\n}\n").await;
+    assert!(hover_at(&backend, 1, 5).await.is_some());
+
+    let pulled = client.configuration_requests().len();
+    client.set_configuration(json!({
+        "features": { "hover": false },
+        "completion": { "maxCompletions": 7 },
+    }));
+    let config = serde_json::to_value(backend.reload_config().await.unwrap()).unwrap();
+    assert_eq!(client.configuration_requests().len(), pulled + 1);
+    assert_eq!(config["features"]["hover"], false);
+    assert_eq!(config["completion"]["maxCompletions"], 7);
+    // Settings left out keep their defaults
+    assert_eq!(config["features"]["diagnostics"], true);
+    assert!(hover_at(&backend, 1, 5).await.is_none());
+
+    let (backend, _) = server().await;
+    let error = backend.reload_config().await.unwrap_err();
+    assert_eq!(error.code, ErrorCode::ServerError(REQUEST_FAILED));
+}
+
+#[tokio::test]
+async fn empty_changes_only_bump_the_version() {
+    let (backend, client) = server().await;
+    open(&backend, "fn main(value) {\n    value\n}\n").await;
+    // Swapped for an AST a reparse would replace
+    backend.ast_map.insert(uri().to_string(), HashMap::new());
+
+    backend
+        .did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri(), 2),
+            content_changes: vec![],
+        })
+        .await;
+    assert_eq!(*backend.version_map.get(uri().as_str()).unwrap(), 2);
+    assert_eq!(*backend.ast_version_map.get(uri().as_str()).unwrap(), 2);
+    assert_eq!(client.published_diagnostics().len(), 1);
+    assert_eq!(definition_at(&backend, 1, 6).await, None);
+    assert!(backend.ast_map.get(uri().as_str()).unwrap().is_empty());
+
+    change(&backend, 3, "fn main(value) {\n    value + 1\n}\n").await;
+    assert_eq!(client.published_diagnostics().len(), 2);
+    assert!(definition_at(&backend, 1, 6).await.is_some());
+}
+
+#[tokio::test]
+async fn symbol_links_resolve_to_the_definition() {
+    let (backend, _) = server().await;
+    let other = Url::parse("file:///workspace/foo.nrs").unwrap();
+    open_uri(&backend, &other, "fn pad() { 0 }\nfn Foo(a) {\n    a\n}\n").await;
+    open(
+        &backend,
+        "// Calls [[Foo]], not [[Missing]].\nfn main() {\n    1\n}\n",
+    )
+    .await;
+
+    let links = backend
+        .document_link(DocumentLinkParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(links.len(), 2);
+    assert_eq!(
+        links[0].range,
+        Range::new(Position::new(0, 9), Position::new(0, 16))
+    );
+    assert!(links.iter().all(|link| link.target.is_none()));
+
+    let resolved = backend
+        .document_link_resolve(links[0].clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        resolved.target.map(String::from),
+        Some("file:///workspace/foo.nrs#L2,4".to_string())
+    );
+    let unresolved = backend
+        .document_link_resolve(links[1].clone())
+        .await
+        .unwrap();
+    assert_eq!(unresolved.target, None);
+}
+
+#[tokio::test]
+async fn selected_statements_are_wrapped_in_a_block() {
+    let (backend, _) = server_with(
+        json!({ "formatter": { "tabSize": 2 } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, "fn main() {\n  print(1);\n  print(2);\n  3\n}\n").await;
+    let actions = |range| {
+        let backend = &backend;
+        async move {
+            backend
+                .code_action(CodeActionParams {
+                    text_document: TextDocumentIdentifier::new(uri()),
+                    range,
+                    context: CodeActionContext::default(),
+                    work_done_progress_params: Default::default(),
+                    partial_result_params: Default::default(),
+                })
+                .await
+                .unwrap()
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action) if action.title == "Wrap in block" => {
+                        Some(action)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        }
+    };
+    // A selection of whole lines ends at the start of the line after them
+    let wrap = actions(Range::new(Position::new(1, 0), Position::new(3, 0))).await;
+    let [action] = wrap.as_slice() else {
+        panic!("expected the wrap action, got {:?}", wrap);
+    };
+    assert_eq!(action.kind, Some(CodeActionKind::REFACTOR_REWRITE));
+    assert_eq!(
+        action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri()],
+        [TextEdit::new(
+            Range::new(Position::new(1, 0), Position::new(2, 11)),
+            "  {\n    print(1);\n    print(2);\n  };".to_string()
+        )]
+    );
+    // Nothing is offered without a selection
+    assert!(
+        actions(Range::new(Position::new(1, 2), Position::new(1, 2)))
+            .await
+            .is_empty()
+    );
+}
+
+async fn full_semantic_tokens(
+    backend: &Backend<MockClient>,
+    uri: &Url,
+) -> Option<SemanticTokensResult> {
+    backend
+        .semantic_tokens_full(SemanticTokensParams {
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn files_with_a_configured_extension_are_watched_and_read() {
+    let settings = json!({ "fileExtensions": ["nrs", "gen"] });
+    let capabilities = capabilities_with(settings.clone()).await;
+    let Some(SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(options)) =
+        capabilities.semantic_tokens_provider
+    else {
+        panic!("expected semantic tokens registration options");
+    };
+    let patterns = options
+        .text_document_registration_options
+        .document_selector
+        .unwrap()
+        .into_iter()
+        .filter_map(|filter| filter.pattern)
+        .collect::<Vec<_>>();
+    assert_eq!(patterns, ["**/*.{nrs,gen}"]);
+
+    let (backend, client) = server_with(
+        settings,
+        ClientCapabilities {
+            workspace: Some(WorkspaceClientCapabilities {
+                did_change_watched_files: Some(DidChangeWatchedFilesClientCapabilities {
+                    dynamic_registration: Some(true),
+                    relative_pattern_support: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    )
+    .await;
+    let registrations = client.registrations();
+    let [registration] = registrations.as_slice() else {
+        panic!("expected the watcher registration, got {:?}", registrations);
+    };
+    assert_eq!(registration.method, "workspace/didChangeWatchedFiles");
+    assert_eq!(
+        registration.register_options.as_ref().unwrap()["watchers"][0]["globPattern"],
+        "**/*.{nrs,gen}"
+    );
+
+    let text = "fn main() {\n    let x = 1;\n    x\n}\n";
+    let gen = DiskFile::new("extra.gen", text);
+    let txt = DiskFile::new("extra.txt", text);
+    assert!(matches!(
+        full_semantic_tokens(&backend, &gen.uri).await,
+        Some(SemanticTokensResult::Tokens(tokens)) if !tokens.data.is_empty()
+    ));
+    assert_eq!(full_semantic_tokens(&backend, &txt.uri).await, None);
+
+    // Without the setting only `.nrs` files are read
+    let (backend, client) = server().await;
+    assert_eq!(full_semantic_tokens(&backend, &gen.uri).await, None);
+    assert!(client.registrations().is_empty());
+}
+
+#[tokio::test]
+async fn a_manifest_scopes_the_workspace_to_its_sources() {
+    let text = "fn main() {\n    let x = 1;\n    x\n}\n";
+    let manifest = DiskFile::new("gen.toml", "src = [\"lib\"]\nexclude = [\"lib/vendor\"]\n");
+    let file = |path: &str| {
+        let path = manifest.dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, text).unwrap();
+        Url::from_file_path(path).unwrap()
+    };
+    let inside = file("lib/main.nrs");
+    let excluded = file("lib/vendor/main.nrs");
+    let outside = file("scripts/main.nrs");
+
+    let client = MockClient::default();
+    let backend = Backend::new(client.clone());
+    backend
+        .initialize(InitializeParams {
+            root_uri: Some(Url::from_file_path(&manifest.dir).unwrap()),
+            capabilities: ClientCapabilities {
+                workspace: Some(WorkspaceClientCapabilities {
+                    did_change_watched_files: Some(DidChangeWatchedFilesClientCapabilities {
+                        dynamic_registration: Some(true),
+                        relative_pattern_support: None,
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    backend.initialized(InitializedParams {}).await;
+
+    assert!(full_semantic_tokens(&backend, &inside).await.is_some());
+    assert_eq!(full_semantic_tokens(&backend, &excluded).await, None);
+    assert_eq!(full_semantic_tokens(&backend, &outside).await, None);
+    let registrations = client.registrations();
+    assert_eq!(
+        registrations[0].register_options.as_ref().unwrap()["watchers"],
+        json!([{ "globPattern": format!("{}/lib/**/*.nrs", manifest.dir.display()) }])
+    );
+
+    // Without a manifest the whole root is read
+    std::fs::remove_file(manifest.dir.join("gen.toml")).unwrap();
+    let backend = Backend::new(MockClient::default());
+    backend
+        .initialize(InitializeParams {
+            root_uri: Some(Url::from_file_path(&manifest.dir).unwrap()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert!(full_semantic_tokens(&backend, &outside).await.is_some());
+}
+
+#[tokio::test]
+async fn repeated_hovers_are_cached_per_version_and_position() {
+    let (backend, _) = server().await;
+    open(&backend, "fn main() {\n    0x10 + 0x30\n}\n").await;
+    let hover = |character| {
+        let backend = &backend;
+        async move {
+            let hover = backend
+                .hover(HoverParams {
+                    text_document_position_params: position_params(1, character),
+                    work_done_progress_params: Default::default(),
+                })
+                .await
+                .unwrap();
+            hover_text(hover).unwrap_or_default()
+        }
+    };
+    let first = hover(5).await;
+    assert!(first.contains("16"), "{}", first);
+
+    // Answered from the cache without looking at the document again
+    backend.document_map.insert(
+        uri().to_string(),
+        Rope::from_str("fn main() {\n    0x20\n}\n"),
+    );
+    assert_eq!(hover(5).await, first);
+    // Another position is computed, and takes the place of the cached hover
+    assert!(hover(6).await.contains("32"));
+    assert!(hover(5).await.contains("32"));
+
+    change(&backend, 2, "fn main() {\n    0x40\n}\n").await;
+    assert!(hover(5).await.contains("64"));
+}
+
+#[tokio::test]
+async fn mixed_line_endings_are_reported_and_normalized() {
+    let text = "fn main() {\r\n    let x = 1;\n    x\r\n}\r\n";
+    let (backend, client) = server().await;
+    open(&backend, text).await;
+    let diagnostics = client.diagnostics(&uri()).unwrap();
+    let [diagnostic] = diagnostics.as_slice() else {
+        panic!("expected one diagnostic, got {:?}", diagnostics);
+    };
+    assert_eq!(codes(&diagnostics), ["mixed-line-endings"]);
+    assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::INFORMATION));
+    assert_eq!(
+        diagnostic.range,
+        Range::new(Position::new(1, 0), Position::new(1, 14))
+    );
+
+    let normalize = |backend| async move {
+        let edits = execute(backend, "gen.normalizeLineEndings", json!({ "uri": uri() }))
+            .await
+            .unwrap();
+        serde_json::from_value::<Vec<TextEdit>>(edits.unwrap()).unwrap()
+    };
+    // To the ending most lines use
+    assert_eq!(
+        normalize(&backend).await,
+        [TextEdit::new(
+            Range::new(Position::new(1, 14), Position::new(2, 0)),
+            "\r\n".to_string()
+        )]
+    );
+
+    // Or to the configured one
+    let (backend, _) = server_with(
+        json!({ "formatter": { "lineEnding": "lf" } }),
+        ClientCapabilities::default(),
+    )
+    .await;
+    open(&backend, text).await;
+    let edits = normalize(&backend).await;
+    assert_eq!(
+        edits
+            .iter()
+            .map(|edit| edit.range.start)
+            .collect::<Vec<_>>(),
+        [
+            Position::new(0, 11),
+            Position::new(2, 5),
+            Position::new(3, 1)
+        ]
+    );
+    assert!(edits.iter().all(|edit| edit.new_text == "\n"));
+}
+
+#[tokio::test]
+async fn completion_kinds_match_the_outline() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "fn helper(count) {\n    count\n}\nfn main() {\n    let total = 1;\n    total\n}\n",
+    )
+    .await;
+    let kinds = completion_items(&backend, 5, 4)
+        .await
+        .into_iter()
+        .map(|item| (item.label, item.kind))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(kinds["helper"], Some(CompletionItemKind::FUNCTION));
+    assert_eq!(kinds["total"], Some(CompletionItemKind::VARIABLE));
+    assert_eq!(kinds["let"], Some(CompletionItemKind::KEYWORD));
+
+    let symbols = backend
+        .document_symbol(DocumentSymbolParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    let Some(DocumentSymbolResponse::Nested(symbols)) = symbols else {
+        panic!("expected nested document symbols");
+    };
+    for symbol in symbols.iter().chain(
+        symbols
+            .iter()
+            .flat_map(|symbol| symbol.children.iter().flatten()),
+    ) {
+        if let Some(kind) = kinds.get(&symbol.name) {
+            assert_eq!(*kind, Some(completion_kind(symbol.kind)), "{}", symbol.name);
+        }
+    }
+}
+
+#[tokio::test]
+async fn symbol_at_sums_up_the_symbol() {
+    let (backend, _) = server().await;
+    open(
+        &backend,
+        "fn add(a, b) {\n    a + b\n}\nfn main() {\n    let total = 1;\n    len([add(total, 2)])\n}\n",
+    )
+    .await;
+    let symbol_at = |line, character| {
+        let backend = &backend;
+        async move {
+            let symbol = backend
+                .symbol_at(UriPositionParams {
+                    uri: uri(),
+                    position: Position::new(line, character),
+                })
+                .await
+                .unwrap();
+            serde_json::to_value(symbol).unwrap()
+        }
+    };
+    let location = |line, start, end| {
+        json!({
+            "uri": uri(),
+            "range": Range::new(Position::new(line, start), Position::new(line, end)),
+        })
+    };
+    // A function, from a call
+    assert_eq!(
+        symbol_at(5, 10).await,
+        json!({
+            "name": "add",
+            "kind": SymbolKind::FUNCTION,
+            "type": "fn add(a, b) -> number",
+            "definition": location(0, 3, 6),
+        })
+    );
+    // A variable, with its inferred type
+    assert_eq!(
+        symbol_at(5, 15).await,
+        json!({
+            "name": "total",
+            "kind": SymbolKind::VARIABLE,
+            "type": "number",
+            "definition": location(4, 8, 13),
+        })
+    );
+    // A built-in is defined nowhere
+    let len = symbol_at(5, 5).await;
+    assert_eq!(len["name"], "len");
+    assert_eq!(len["definition"], Value::Null);
+    // Nothing resolves on a keyword
+    assert_eq!(symbol_at(4, 5).await, Value::Null);
+}
+
+#[tokio::test]
+async fn unchanged_content_only_bumps_the_version() {
+    let text = "fn main(value) {\n    value\n}\n";
+    let (backend, client) = server().await;
+    open(&backend, text).await;
+    // Swapped for an AST a reparse would replace
+    backend.ast_map.insert(uri().to_string(), HashMap::new());
+
+    change(&backend, 2, text).await;
+    assert_eq!(*backend.version_map.get(uri().as_str()).unwrap(), 2);
+    assert_eq!(*backend.ast_version_map.get(uri().as_str()).unwrap(), 2);
+    assert_eq!(client.published_diagnostics().len(), 1);
+    assert_eq!(definition_at(&backend, 1, 6).await, None);
+    assert!(backend.ast_map.get(uri().as_str()).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn ranged_changes_apply_in_order() {
+    let (backend, client) = server().await;
+    open(&backend, "fn main(count) {\n    cuont\n}\n").await;
+    let edit = |start: (u32, u32), end: (u32, u32), text: &str| TextDocumentContentChangeEvent {
+        range: Some(Range::new(
+            Position::new(start.0, start.1),
+            Position::new(end.0, end.1),
+        )),
+        range_length: None,
+        text: text.to_string(),
+    };
+    backend
+        .did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri(), 2),
+            content_changes: vec![
+                edit((1, 4), (1, 9), "count"),
+                // Relative to the content left by the first change
+                edit((1, 9), (1, 9), " + 1"),
+                edit((2, 1), (2, 1), "\nfn other() {\n    2\n}"),
+            ],
+        })
+        .await;
+    assert_eq!(
+        backend
+            .document_map
+            .get(uri().as_str())
+            .unwrap()
+            .to_string(),
+        "fn main(count) {\n    count + 1\n}\nfn other() {\n    2\n}\n"
+    );
+    assert_eq!(client.diagnostics(&uri()), Some(vec![]));
+    assert!(backend
+        .ast_map
+        .get(uri().as_str())
+        .unwrap()
+        .contains_key("other"));
+}
+
+/// `text` after the client applied `edits` to it, positions counting chars.
+fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut rope = Rope::from_str(text);
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|edit| std::cmp::Reverse(edit.range.start));
+    for edit in edits {
+        let start = position_to_offset(&rope, edit.range.start);
+        let end = position_to_offset(&rope, edit.range.end);
+        rope.remove(start..end);
+        rope.insert(start, &edit.new_text);
+    }
+    rope.to_string()
+}
+
+#[tokio::test]
+async fn formatting_keeps_the_byte_order_mark() {
+    let text = format!("{}  fn main() {{\n1\n}}\n", BOM);
+    for minimal_edits in [true, false] {
+        let (backend, _) = server_with(
+            json!({ "formatter": { "minimalEdits": minimal_edits } }),
+            ClientCapabilities::default(),
+        )
+        .await;
+        open(&backend, &text).await;
+        let edits = backend
+            .formatting(formatting_params())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            apply_edits(&text, &edits),
+            format!("{}fn main() {{\n    1\n}}\n", BOM),
+            "{:?}",
+            edits
+        );
+    }
+
+    // Documents without one don't get one
+    let (backend, _) = server().await;
+    open(&backend, "  fn main() {\n1\n}\n").await;
+    let edits = backend
+        .formatting(formatting_params())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        apply_edits("  fn main() {\n1\n}\n", &edits),
+        "fn main() {\n    1\n}\n"
+    );
+}
+
+#[tokio::test]
+async fn ranged_changes_keep_the_byte_order_mark() {
+    let (backend, _) = server().await;
+    open(&backend, &format!("{}fn main() {{\n    1\n}}\n", BOM)).await;
+    let hash = backend.content_hash(&uri());
+    backend
+        .did_change(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(1, 4), Position::new(1, 5))),
+                range_length: None,
+                text: "2".to_string(),
+            }],
+        })
+        .await;
+    assert_eq!(
+        backend
+            .document_map
+            .get(uri().as_str())
+            .unwrap()
+            .to_string(),
+        "fn main() {\n    2\n}\n"
+    );
+    assert!(backend.has_bom(&uri()));
+    assert_ne!(backend.content_hash(&uri()), hash);
+    assert_eq!(
+        backend.content_hash(&uri()),
+        Some(content_hash(&format!("{}fn main() {{\n    2\n}}\n", BOM)))
+    );
+}
+
+#[tokio::test]
+async fn unchanged_functions_keep_their_diagnostics_where_they_moved() {
+    let (backend, client) = server().await;
+    open(
+        &backend,
+        "fn a() {\n    x\n}\nfn b(y) {\n    let y = helper(z);\n    y\n}\n",
+    )
+    .await;
+    let ranges = |diagnostics: Vec<Diagnostic>| {
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                let code = match diagnostic.code {
+                    Some(NumberOrString::String(code)) => code,
+                    _ => String::new(),
+                };
+                (code, diagnostic.range.start)
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        ranges(client.diagnostics(&uri()).unwrap()),
+        [
+            ("unresolved-symbol".to_string(), Position::new(1, 4)),
+            ("unresolved-symbol".to_string(), Position::new(4, 12)),
+            ("unresolved-symbol".to_string(), Position::new(4, 19)),
+            ("shadowed-binding".to_string(), Position::new(4, 8)),
+        ]
+    );
+
+    // `b` moves down two lines, its results move with it
+    change(
+        &backend,
+        2,
+        "fn a() {\n    let x = 1;\n    x\n}\n\nfn b(y) {\n    let y = helper(z);\n    y\n}\n",
+    )
+    .await;
+    assert_eq!(
+        ranges(client.diagnostics(&uri()).unwrap()),
+        [
+            ("unresolved-symbol".to_string(), Position::new(6, 12)),
+            ("unresolved-symbol".to_string(), Position::new(6, 19)),
+            ("shadowed-binding".to_string(), Position::new(6, 8)),
+        ]
+    );
+
+    // A new function can resolve what an unchanged one calls
+    change(
+        &backend,
+        3,
+        "fn a() {\n    let x = 1;\n    x\n}\nfn helper(v) {\n    v\n}\nfn b(y) {\n    let y = helper(z);\n    y\n}\n",
+    )
+    .await;
+    assert_eq!(
+        ranges(client.diagnostics(&uri()).unwrap()),
+        [
+            ("unresolved-symbol".to_string(), Position::new(8, 19)),
+            ("shadowed-binding".to_string(), Position::new(8, 8)),
+        ]
+    );
+}
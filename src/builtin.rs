@@ -0,0 +1,54 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+/// The built-ins of the language, available in every document.
+const DEFAULT_BUILTINS: &str = include_str!("builtins.json");
+
+/// A function provided by the runtime rather than defined in the document.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Builtin {
+    pub name: String,
+    #[serde(default)]
+    pub params: Vec<String>,
+    /// Markdown documentation shown on hover and in completion.
+    #[serde(default)]
+    pub doc: String,
+}
+
+impl Builtin {
+    pub fn signature(&self) -> String {
+        format!("fn {}({})", self.name, self.params.join(", "))
+    }
+}
+
+pub fn default_builtins() -> Vec<Builtin> {
+    serde_json::from_str(DEFAULT_BUILTINS).expect("the embedded built-ins are valid")
+}
+
+/// The built-ins listed in the JSON file at `path`, for dialects of the language.
+pub fn load_builtins(path: &Path) -> Result<Vec<Builtin>, String> {
+    let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    serde_json::from_str(&text).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_builtins_load() {
+        let builtins = default_builtins();
+        let len = builtins
+            .iter()
+            .find(|builtin| builtin.name == "len")
+            .unwrap();
+        assert_eq!(len.signature(), "fn len(value)");
+        assert!(!len.doc.is_empty());
+    }
+
+    #[test]
+    fn missing_files_fail_to_load() {
+        assert!(load_builtins(Path::new("/nonexistent/builtins.json")).is_err());
+    }
+}
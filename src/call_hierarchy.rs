@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use im_rc::Vector;
+
+use crate::chumsky::{Expr, Func, Spanned};
+use crate::jump_definition::get_definition;
+use crate::visitor::{walk_expr, Visitor};
+
+/// The function whose name is at `offset`, either where it is defined or where it is called.
+/// Locals shadowing a function are not mistaken for it.
+pub fn get_function_at(ast: &HashMap<String, Func>, offset: usize) -> Option<&Func> {
+    let (name, span) = get_definition(ast, offset)?;
+    ast.get(&name).filter(|func| func.name.1 == span)
+}
+
+/// Calls in `func` to other functions of `ast`, with the span of the callee name, in source
+/// order.
+pub fn get_calls(ast: &HashMap<String, Func>, func: &Func) -> Vec<Spanned<String>> {
+    let mut collector = CallCollector {
+        locals: func.args.iter().map(|(name, _)| name.clone()).collect(),
+        calls: vec![],
+    };
+    collector.visit_func(func);
+    let mut calls = collector.calls;
+    calls.retain(|(name, _)| ast.contains_key(name));
+    calls.sort_by_key(|(_, span)| span.start);
+    calls
+}
+
+/// Collects the names called in what it visits, except the ones `locals` shadow.
+struct CallCollector {
+    /// Arguments and `let` bindings in scope.
+    locals: Vector<String>,
+    calls: Vec<Spanned<String>>,
+}
+
+impl Visitor for CallCollector {
+    fn visit_expr(&mut self, expr: &Spanned<Expr>) {
+        match &expr.0 {
+            Expr::Let(name, rhs, rest, _) => {
+                self.visit_expr(rhs);
+                let outer = self.locals.clone();
+                self.locals.push_back(name.clone());
+                self.visit_expr(rest);
+                self.locals = outer;
+            }
+            Expr::Call(callee, args) => match &callee.0 {
+                Expr::Local(name) if !self.locals.contains(&name.0) => {
+                    self.calls.push(name.clone());
+                    args.0.iter().for_each(|arg| self.visit_expr(arg));
+                }
+                _ => walk_expr(self, expr),
+            },
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parse;
+
+    fn calls(src: &str, caller: &str) -> Vec<String> {
+        let ast = parse(src).0.unwrap();
+        get_calls(&ast, &ast[caller])
+            .into_iter()
+            .map(|(name, span)| {
+                assert_eq!(&src[span], name);
+                name
+            })
+            .collect()
+    }
+
+    #[test]
+    fn calls_in_source_order() {
+        let src = "fn a() { 1 }\nfn b(x) { x }\nfn main() { b(a()) + a() }\n";
+        assert_eq!(calls(src, "main"), ["b", "a", "a"]);
+        assert!(calls(src, "a").is_empty());
+    }
+
+    #[test]
+    fn shadowed_functions_are_not_calls() {
+        let src = "fn a() { 1 }\nfn main(f) { let a = f; a() + f() }\nfn other() { a() }\n";
+        assert!(calls(src, "main").is_empty());
+        assert_eq!(calls(src, "other"), ["a"]);
+    }
+
+    #[test]
+    fn bindings_only_shadow_after_them() {
+        let src = "fn a() { 1 }\nfn main() { let a = a(); a }\n";
+        assert_eq!(calls(src, "main"), ["a"]);
+    }
+
+    #[test]
+    fn function_at_definition_or_call() {
+        let src = "fn one() { 1 }\nfn main() { one() }\n";
+        let ast = parse(src).0.unwrap();
+        let name = |offset| get_function_at(&ast, offset).map(|f| f.name.0.as_str());
+        assert_eq!(name(src.find("one").unwrap() + 1), Some("one"));
+        assert_eq!(name(src.rfind("one").unwrap() + 1), Some("one"));
+        assert_eq!(name(src.find('1').unwrap()), None);
+    }
+}
@@ -5,16 +5,18 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tower_lsp::lsp_types::SemanticTokenType;
 
-use crate::semantic_token::LEGEND_TYPE;
+use crate::semantic_token::{comment_semantic_tokens, string_semantic_tokens, LEGEND_TYPE};
 
 /// This is the parser and interpreter for the 'Foo' language. See `tutorial.md` in the repository's root to learn
 /// about it.
 pub type Span = std::ops::Range<usize>;
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct ImCompleteSemanticToken {
     pub start: usize,
     pub length: usize,
     pub token_type: usize,
+    /// Bitset of [`LEGEND_MODIFIER`](crate::semantic_token::LEGEND_MODIFIER) indices.
+    pub token_modifiers: u32,
 }
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Token {
@@ -51,10 +53,35 @@ impl fmt::Display for Token {
     }
 }
 
-fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
-    // A parser for numbers
-    let num = text::int(10)
-        .chain::<char, _, _>(just('.').chain(text::digits(10)).or_not().flatten())
+/// Reserved words of the language, and the token each one is lexed to.
+pub const KEYWORDS: &[(&str, Token)] = &[
+    ("fn", Token::Fn),
+    ("let", Token::Let),
+    ("print", Token::Print),
+    ("if", Token::If),
+    ("else", Token::Else),
+    ("true", Token::Bool(true)),
+    ("false", Token::Bool(false)),
+    ("null", Token::Null),
+];
+
+pub fn is_keyword(word: &str) -> bool {
+    KEYWORDS.iter().any(|(keyword, _)| *keyword == word)
+}
+
+pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
+    // A parser for numbers, loose enough to take in radix prefixes, `_` separators and type
+    // suffixes; `parse_number` decides whether the literal is actually valid
+    let num_tail = filter(|c: &char| c.is_ascii_alphanumeric() || *c == '_').repeated();
+    let num = filter(|c: &char| c.is_ascii_digit())
+        .chain::<char, _, _>(num_tail)
+        .chain::<char, _, _>(
+            just('.')
+                .chain(filter(|c: &char| c.is_ascii_digit()))
+                .chain::<char, _, _>(num_tail)
+                .or_not()
+                .flatten(),
+        )
         .collect::<String>()
         .map(Token::Num);
 
@@ -76,16 +103,12 @@ fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
     let ctrl = one_of("()[]{};,").map(Token::Ctrl);
 
     // A parser for identifiers and keywords
-    let ident = text::ident().map(|ident: String| match ident.as_str() {
-        "fn" => Token::Fn,
-        "let" => Token::Let,
-        "print" => Token::Print,
-        "if" => Token::If,
-        "else" => Token::Else,
-        "true" => Token::Bool(true),
-        "false" => Token::Bool(false),
-        "null" => Token::Null,
-        _ => Token::Ident(ident),
+    let ident = text::ident().map(|ident: String| {
+        KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == ident)
+            .map(|(_, token)| token.clone())
+            .unwrap_or(Token::Ident(ident))
     });
 
     // A single token can be one of the above
@@ -105,6 +128,88 @@ fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
         .repeated()
 }
 
+const INT_SUFFIXES: &[&str] = &[
+    "i128", "i16", "i32", "i64", "i8", "isize", "u128", "u16", "u32", "u64", "u8", "usize",
+];
+const FLOAT_SUFFIXES: &[&str] = &["f32", "f64"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumberLiteral {
+    Int(u64),
+    Float(f64),
+}
+
+impl NumberLiteral {
+    pub fn as_f64(self) -> f64 {
+        match self {
+            NumberLiteral::Int(n) => n as f64,
+            NumberLiteral::Float(n) => n,
+        }
+    }
+}
+
+/// Parses the text of a [`Token::Num`], accepting `0x`/`0o`/`0b` prefixes, `_` separators and
+/// integer/float type suffixes.
+pub fn parse_number(literal: &str) -> Option<NumberLiteral> {
+    let literal = literal.replace('_', "");
+    let radix = match literal.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        let digits = &literal[2..];
+        let digits = INT_SUFFIXES
+            .iter()
+            .find_map(|suffix| digits.strip_suffix(suffix))
+            .unwrap_or(digits);
+        return u64::from_str_radix(digits, radix)
+            .ok()
+            .map(NumberLiteral::Int);
+    }
+    if let Some(digits) = INT_SUFFIXES
+        .iter()
+        .find_map(|suffix| literal.strip_suffix(suffix))
+    {
+        return digits.parse().ok().map(NumberLiteral::Int);
+    }
+    if let Some(digits) = FLOAT_SUFFIXES
+        .iter()
+        .find_map(|suffix| literal.strip_suffix(suffix))
+    {
+        return digits.parse().ok().map(NumberLiteral::Float);
+    }
+    match literal.parse() {
+        Ok(n) => Some(NumberLiteral::Int(n)),
+        Err(_) => literal.parse().ok().map(NumberLiteral::Float),
+    }
+}
+
+/// Token spans may start with whitespace and comments, skips them from `offset` to reach the
+/// token itself.
+pub fn skip_padding(chars: &[char], mut offset: usize) -> usize {
+    loop {
+        match chars.get(offset..offset + 2) {
+            Some(['/', '/']) => {
+                while offset < chars.len() && chars[offset] != '\n' {
+                    offset += 1;
+                }
+            }
+            _ if chars.get(offset).is_some_and(|c| c.is_whitespace()) => offset += 1,
+            _ => return offset,
+        }
+    }
+}
+
+/// The token of `src` covering the char `offset`, if any.
+pub fn token_at(src: &str, offset: usize) -> Option<(Token, Span)> {
+    let (tokens, _) = lexer().parse_recovery(src);
+    tokens?
+        .into_iter()
+        .find(|(_, span)| span.start <= offset && offset < span.end)
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Value {
     Null,
@@ -211,13 +316,20 @@ pub struct Func {
     pub span: Span,
 }
 
+#[allow(clippy::result_large_err)]
 fn expr_parser() -> impl Parser<Token, Spanned<Expr>, Error = Simple<Token>> + Clone {
     recursive(|expr| {
         let raw_expr = recursive(|raw_expr| {
             let val = filter_map(|span, tok| match tok {
                 Token::Null => Ok(Expr::Value(Value::Null)),
                 Token::Bool(x) => Ok(Expr::Value(Value::Bool(x))),
-                Token::Num(n) => Ok(Expr::Value(Value::Num(n.parse().unwrap()))),
+                Token::Num(n) => match parse_number(&n) {
+                    Some(n) => Ok(Expr::Value(Value::Num(n.as_f64()))),
+                    None => Err(Simple::custom(
+                        span,
+                        format!("Invalid number literal {}", n),
+                    )),
+                },
                 Token::Str(s) => Ok(Expr::Value(Value::Str(s))),
                 _ => Err(Simple::expected_input_found(span, Vec::new(), Some(tok))),
             })
@@ -412,6 +524,7 @@ fn expr_parser() -> impl Parser<Token, Spanned<Expr>, Error = Simple<Token>> + C
     })
 }
 
+#[allow(clippy::result_large_err)]
 pub fn funcs_parser() -> impl Parser<Token, HashMap<String, Func>, Error = Simple<Token>> + Clone {
     let ident = filter_map(|span, tok| match tok {
         Token::Ident(ident) => Ok(ident),
@@ -506,6 +619,39 @@ pub fn type_inference(expr: &Spanned<Expr>, symbol_type_table: &mut HashMap<Span
     }
 }
 
+/// Infers the type name of the value a function body evaluates to by following its tail
+/// expression. Returns `None` when the type can't be known without evaluating calls or locals.
+pub fn return_type_inference(expr: &Spanned<Expr>) -> Option<String> {
+    match &expr.0 {
+        Expr::Error => None,
+        Expr::Value(value) => match value {
+            Value::Null => Some("null".to_string()),
+            Value::Bool(_) => Some("bool".to_string()),
+            Value::Num(_) => Some("number".to_string()),
+            Value::Str(_) => Some("string".to_string()),
+            Value::List(_) => Some("[]".to_string()),
+            Value::Func(_) => None,
+        },
+        Expr::List(_) => Some("[]".to_string()),
+        Expr::Local(_) => None,
+        Expr::Let(_, _, rest, _) => return_type_inference(rest),
+        Expr::Then(_, second) => return_type_inference(second),
+        Expr::Binary(_, op, _) => match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
+                Some("number".to_string())
+            }
+            BinaryOp::Eq | BinaryOp::NotEq => Some("bool".to_string()),
+        },
+        Expr::Call(_, _) => None,
+        Expr::If(_, consequent, alternative) => {
+            let consequent = return_type_inference(consequent)?;
+            (return_type_inference(alternative)? == consequent).then_some(consequent)
+        }
+        Expr::Print(_) => None,
+    }
+}
+
+#[allow(clippy::type_complexity)]
 pub fn parse(
     src: &str,
 ) -> (
@@ -517,7 +663,7 @@ pub fn parse(
 
     let (ast, tokenize_errors, semantic_tokens) = if let Some(tokens) = tokens {
         // info!("Tokens = {:?}", tokens);
-        let semantic_tokens = tokens
+        let mut semantic_tokens = tokens
             .iter()
             .filter_map(|(token, span)| match token {
                 Token::Null => None,
@@ -530,15 +676,9 @@ pub fn parse(
                         .iter()
                         .position(|item| item == &SemanticTokenType::NUMBER)
                         .unwrap(),
+                    token_modifiers: 0,
                 }),
-                Token::Str(_) => Some(ImCompleteSemanticToken {
-                    start: span.start,
-                    length: span.len(),
-                    token_type: LEGEND_TYPE
-                        .iter()
-                        .position(|item| item == &SemanticTokenType::STRING)
-                        .unwrap(),
-                }),
+                Token::Str(_) => None,
                 Token::Op(_) => Some(ImCompleteSemanticToken {
                     start: span.start,
                     length: span.len(),
@@ -546,6 +686,7 @@ pub fn parse(
                         .iter()
                         .position(|item| item == &SemanticTokenType::OPERATOR)
                         .unwrap(),
+                    token_modifiers: 0,
                 }),
                 Token::Ctrl(_) => None,
                 Token::Ident(_) => None,
@@ -556,6 +697,7 @@ pub fn parse(
                         .iter()
                         .position(|item| item == &SemanticTokenType::KEYWORD)
                         .unwrap(),
+                    token_modifiers: 0,
                 }),
                 Token::Let => Some(ImCompleteSemanticToken {
                     start: span.start,
@@ -564,6 +706,7 @@ pub fn parse(
                         .iter()
                         .position(|item| item == &SemanticTokenType::KEYWORD)
                         .unwrap(),
+                    token_modifiers: 0,
                 }),
                 Token::Print => Some(ImCompleteSemanticToken {
                     start: span.start,
@@ -572,6 +715,7 @@ pub fn parse(
                         .iter()
                         .position(|item| item == &SemanticTokenType::FUNCTION)
                         .unwrap(),
+                    token_modifiers: 0,
                 }),
                 Token::If => Some(ImCompleteSemanticToken {
                     start: span.start,
@@ -580,6 +724,7 @@ pub fn parse(
                         .iter()
                         .position(|item| item == &SemanticTokenType::KEYWORD)
                         .unwrap(),
+                    token_modifiers: 0,
                 }),
                 Token::Else => Some(ImCompleteSemanticToken {
                     start: span.start,
@@ -588,10 +733,26 @@ pub fn parse(
                         .iter()
                         .position(|item| item == &SemanticTokenType::KEYWORD)
                         .unwrap(),
+                    token_modifiers: 0,
                 }),
             })
             .collect::<Vec<_>>();
-        let len = src.chars().count();
+        // A comment at the very start of the file is part of the first token's span
+        let chars = src.chars().collect::<Vec<_>>();
+        for token in semantic_tokens.iter_mut() {
+            let start = skip_padding(&chars, token.start);
+            token.length -= start - token.start;
+            token.start = start;
+        }
+        // Strings are split around their escape sequences
+        for (token, span) in &tokens {
+            if let Token::Str(content) = token {
+                let start = skip_padding(&chars, span.start);
+                semantic_tokens.extend(string_semantic_tokens(start, content));
+            }
+        }
+        semantic_tokens.extend(comment_semantic_tokens(src));
+        let len = chars.len();
         let (ast, parse_errs) =
             funcs_parser().parse_recovery(Stream::from_iter(len..len + 1, tokens.into_iter()));
 
@@ -632,3 +793,44 @@ pub fn parse(
     //     };
     // });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_are_lexed_to_their_tokens() {
+        let tokens = lexer().parse("let lettuce = null").unwrap();
+        let tokens = tokens
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            [
+                Token::Let,
+                Token::Ident("lettuce".to_string()),
+                Token::Op("=".to_string()),
+                Token::Null,
+            ]
+        );
+        assert!(is_keyword("else"));
+        assert!(!is_keyword("lettuce"));
+    }
+
+    #[test]
+    fn keywords_get_keyword_tokens() {
+        let src = "fn main() {\n    let x = 1;\n    x\n}\n";
+        let (_, _, tokens) = parse(src);
+        let keyword = LEGEND_TYPE
+            .iter()
+            .position(|item| item == &SemanticTokenType::KEYWORD)
+            .unwrap();
+        let keywords = tokens
+            .iter()
+            .filter(|token| token.token_type == keyword)
+            .map(|token| &src[token.start..token.start + token.length])
+            .collect::<Vec<_>>();
+        assert_eq!(keywords, ["fn", "let"]);
+    }
+}
@@ -0,0 +1,100 @@
+use serde_json::Value;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::notification::Notification;
+use tower_lsp::lsp_types::request::WorkDoneProgressCreate;
+use tower_lsp::lsp_types::{
+    ApplyWorkspaceEditResponse, ConfigurationItem, Diagnostic, MessageActionItem, MessageType,
+    ProgressToken, Registration, Url, WorkDoneProgressCreateParams, WorkspaceEdit,
+};
+use tower_lsp::Client;
+
+/// The client operations [`Backend`](crate::backend::Backend) relies on. The server talks to a
+/// real [`Client`], tests can substitute an implementation recording what gets sent. Clones talk
+/// to the same client, for work outliving the borrow of the backend.
+#[tower_lsp::async_trait]
+pub trait LspClient: Clone + Send + Sync + 'static {
+    async fn log_message(&self, typ: MessageType, message: String);
+
+    async fn show_message(&self, typ: MessageType, message: String);
+
+    async fn show_message_request(
+        &self,
+        typ: MessageType,
+        message: String,
+        actions: Option<Vec<MessageActionItem>>,
+    ) -> Result<Option<MessageActionItem>>;
+
+    async fn publish_diagnostics(
+        &self,
+        uri: Url,
+        diagnostics: Vec<Diagnostic>,
+        version: Option<i32>,
+    );
+
+    async fn send_notification<N>(&self, params: N::Params)
+    where
+        N: Notification,
+        N::Params: Send;
+
+    async fn apply_edit(&self, edit: WorkspaceEdit) -> Result<ApplyWorkspaceEditResponse>;
+
+    async fn create_work_done_progress(&self, token: ProgressToken) -> Result<()>;
+
+    async fn register_capability(&self, registrations: Vec<Registration>) -> Result<()>;
+
+    async fn configuration(&self, items: Vec<ConfigurationItem>) -> Result<Vec<Value>>;
+}
+
+#[tower_lsp::async_trait]
+impl LspClient for Client {
+    async fn log_message(&self, typ: MessageType, message: String) {
+        Client::log_message(self, typ, message).await
+    }
+
+    async fn show_message(&self, typ: MessageType, message: String) {
+        Client::show_message(self, typ, message).await
+    }
+
+    async fn show_message_request(
+        &self,
+        typ: MessageType,
+        message: String,
+        actions: Option<Vec<MessageActionItem>>,
+    ) -> Result<Option<MessageActionItem>> {
+        Client::show_message_request(self, typ, message, actions).await
+    }
+
+    async fn publish_diagnostics(
+        &self,
+        uri: Url,
+        diagnostics: Vec<Diagnostic>,
+        version: Option<i32>,
+    ) {
+        Client::publish_diagnostics(self, uri, diagnostics, version).await
+    }
+
+    async fn send_notification<N>(&self, params: N::Params)
+    where
+        N: Notification,
+        N::Params: Send,
+    {
+        Client::send_notification::<N>(self, params).await
+    }
+
+    async fn apply_edit(&self, edit: WorkspaceEdit) -> Result<ApplyWorkspaceEditResponse> {
+        Client::apply_edit(self, edit).await
+    }
+
+    async fn create_work_done_progress(&self, token: ProgressToken) -> Result<()> {
+        Client::send_request::<WorkDoneProgressCreate>(self, WorkDoneProgressCreateParams { token })
+            .await
+    }
+
+    async fn configuration(&self, items: Vec<ConfigurationItem>) -> Result<Vec<Value>> {
+        Client::configuration(self, items).await
+    }
+
+    async fn register_capability(&self, registrations: Vec<Registration>) -> Result<()> {
+        Client::register_capability(self, registrations).await
+    }
+}
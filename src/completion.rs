@@ -1,9 +1,27 @@
 use std::collections::HashMap;
 
-use crate::chumsky::{Expr, Func, Spanned};
+use chumsky::Parser;
+use tower_lsp::lsp_types::SymbolKind;
+
+use crate::chumsky::{
+    lexer, skip_padding, type_inference, Expr, Func, Spanned, Token, Value, KEYWORDS,
+};
+use crate::jump_definition::get_definition;
 pub enum ImCompleteCompletionItem {
     Variable(String),
     Function(String, Vec<String>),
+    Keyword(String),
+}
+
+impl ImCompleteCompletionItem {
+    /// The kind of the completed symbol, the one it has in the outline. Keywords aren't symbols.
+    pub fn symbol_kind(&self) -> Option<SymbolKind> {
+        match self {
+            ImCompleteCompletionItem::Variable(_) => Some(SymbolKind::VARIABLE),
+            ImCompleteCompletionItem::Function(_, _) => Some(SymbolKind::FUNCTION),
+            ImCompleteCompletionItem::Keyword(_) => None,
+        }
+    }
 }
 /// return (need_to_continue_search, founded reference)
 pub fn completion(
@@ -11,6 +29,12 @@ pub fn completion(
     ident_offset: usize,
 ) -> HashMap<String, ImCompleteCompletionItem> {
     let mut map = HashMap::new();
+    for (keyword, _) in KEYWORDS {
+        map.insert(
+            keyword.to_string(),
+            ImCompleteCompletionItem::Keyword(keyword.to_string()),
+        );
+    }
     for (_, v) in ast.iter() {
         if v.name.1.end < ident_offset {
             map.insert(
@@ -39,6 +63,65 @@ pub fn completion(
     map
 }
 
+/// Snippet calling `name`, with one tab stop per argument named after the parameter and the
+/// final cursor after the closing parenthesis, e.g. `add(${1:a}, ${2:b})$0`.
+pub fn call_snippet(name: &str, args: &[String]) -> String {
+    let placeholders = args
+        .iter()
+        .enumerate()
+        .map(|(index, arg)| format!("${{{}:{}}}", index + 1, arg))
+        .collect::<Vec<_>>();
+    format!("{}({})$0", name, placeholders.join(", "))
+}
+
+/// Whether the chars of `query` appear in `candidate` in order, ignoring case, so `fb` matches
+/// `foo_bar`. An empty query matches everything.
+pub fn fuzzy_match(query: &str, candidate: &str) -> bool {
+    let mut candidate = candidate.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|q| candidate.any(|c| c == q))
+}
+
+/// Whether the expression starting right after `before` is expected to be a boolean: the
+/// condition of an `if`, or the right operand of `==`/`!=` when the left one is a boolean
+/// literal or a `let` binding of one.
+pub fn expects_bool(ast: &HashMap<String, Func>, before: &str) -> bool {
+    let tokens = match lexer().parse_recovery(before).0 {
+        Some(tokens) => tokens,
+        None => return false,
+    };
+    match tokens.as_slice() {
+        [.., (Token::If, _)] => true,
+        [.., (Token::Bool(_), _), (Token::Op(op), _)] => op == "==" || op == "!=",
+        [.., (Token::Ident(_), span), (Token::Op(op), _)] if op == "==" || op == "!=" => {
+            let chars = before.chars().collect::<Vec<_>>();
+            let (_, definition) = match get_definition(ast, skip_padding(&chars, span.start)) {
+                Some(definition) => definition,
+                None => return false,
+            };
+            let mut types = HashMap::new();
+            ast.values()
+                .for_each(|func| type_inference(&func.body, &mut types));
+            matches!(types.get(&definition), Some(Value::Bool(_)))
+        }
+        _ => false,
+    }
+}
+
+/// Names a new binding at `offset` would collide with: every function, wherever it is
+/// defined, and the parameters and `let` bindings in scope.
+pub fn visible_names(ast: &HashMap<String, Func>, offset: usize) -> Vec<String> {
+    let mut names = ast.keys().cloned().collect::<Vec<_>>();
+    for (name, item) in completion(ast, offset) {
+        if matches!(item, ImCompleteCompletionItem::Variable(_)) {
+            names.push(name);
+        }
+    }
+    names
+}
+
 pub fn get_completion_of(
     expr: &Spanned<Expr>,
     definition_map: &mut HashMap<String, ImCompleteCompletionItem>,
@@ -50,9 +133,7 @@ pub fn get_completion_of(
         // Expr::List(exprs) => exprs
         //     .iter()
         //     .for_each(|expr| get_definition(expr, definition_ass_list)),
-        Expr::Local(local) => {
-            !(ident_offset >= local.1.start && ident_offset < local.1.end)
-        }
+        Expr::Local(local) => !(ident_offset >= local.1.start && ident_offset < local.1.end),
         Expr::Let(name, lhs, rest, _name_span) => {
             definition_map.insert(
                 name.clone(),
@@ -107,3 +188,40 @@ pub fn get_completion_of(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_snippets_have_a_tab_stop_per_argument() {
+        let args = ["a".to_string(), "b".to_string()];
+        assert_eq!(call_snippet("add", &args), "add(${1:a}, ${2:b})$0");
+        assert_eq!(call_snippet("main", &[]), "main()$0");
+    }
+
+    #[test]
+    fn fuzzy_matches_chars_in_order() {
+        assert!(fuzzy_match("fb", "foo_bar"));
+        assert!(fuzzy_match("FOO", "foo_bar"));
+        assert!(fuzzy_match("", "anything"));
+        assert!(!fuzzy_match("bf", "foo_bar"));
+    }
+
+    #[test]
+    fn booleans_are_expected_after_if_and_comparisons_with_one() {
+        let expects = |src: &str, before: &str| {
+            let ast = crate::chumsky::parse(src).0.unwrap();
+            expects_bool(&ast, &src[..src.find(before).unwrap() + before.len()])
+        };
+        let src = "fn main(n) {\n    let flag = true;\n    let count = 1;\n    flag == n\n}\n";
+        assert!(expects(src, "flag == "));
+        assert!(!expects(src, "flag = "));
+        let src = "fn main(n) {\n    let count = 1;\n    count == n\n}\n";
+        assert!(!expects(src, "count == "));
+        let src = "fn main(n) {\n    if n { false != n } else { n }\n}\n";
+        assert!(expects(src, "if "));
+        assert!(expects(src, "false != "));
+        assert!(!expects(src, "main("));
+    }
+}
@@ -0,0 +1,63 @@
+use chumsky::Parser;
+
+use crate::chumsky::{lexer, skip_padding, Span, Token};
+
+/// Single `=` inside `if` conditions, where `==` was most likely meant. The language has no
+/// assignment expression, so these never parse; this only points at the likely fix.
+pub fn assignments_in_conditions(src: &str) -> Vec<Span> {
+    let (tokens, _) = lexer().parse_recovery(src);
+    let tokens = tokens.unwrap_or_default();
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut assignments = vec![];
+    let mut condition = false;
+    // Brackets opened inside the condition, whose `{` don't start the consequent
+    let mut depth = 0usize;
+    for (token, span) in &tokens {
+        match token {
+            Token::If => {
+                condition = true;
+                depth = 0;
+            }
+            _ if !condition => {}
+            Token::Ctrl('{') if depth == 0 => condition = false,
+            Token::Ctrl('(' | '[' | '{') => depth += 1,
+            Token::Ctrl(')' | ']' | '}') => depth = depth.saturating_sub(1),
+            Token::Op(op) if op == "=" => {
+                let start = skip_padding(&chars, span.start);
+                assignments.push(start..start + 1);
+            }
+            _ => {}
+        }
+    }
+    assignments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flagged(src: &str) -> Vec<&str> {
+        assignments_in_conditions(src)
+            .into_iter()
+            .map(|span| &src[span.start - 2..span.end + 2])
+            .collect()
+    }
+
+    #[test]
+    fn single_equals_in_conditions() {
+        assert_eq!(
+            flagged("fn main(x) { if x = 1 { 1 } else { 2 } }"),
+            ["x = 1"]
+        );
+        assert_eq!(
+            flagged("fn main(x) { if f(x) + [x][0] = 1 { 1 } else { 2 } }"),
+            ["] = 1"]
+        );
+    }
+
+    #[test]
+    fn comparisons_and_let_bindings_are_fine() {
+        assert!(flagged("fn main(x) { if x == 1 { let y = 2; y } else { x != 1 } }").is_empty());
+        assert!(flagged("fn main(x) { let y = x; y }").is_empty());
+    }
+}
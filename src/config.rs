@@ -0,0 +1,331 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tower_lsp::lsp_types::{MessageType, TraceValue};
+
+use crate::line_ending::LineEnding;
+
+/// Trigger characters advertised regardless of configuration.
+pub const DEFAULT_TRIGGER_CHARACTERS: &[&str] = &["."];
+
+/// Server settings, read from the `gen` section of the client configuration.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    pub completion: CompletionConfig,
+    pub semantic_tokens: SemanticTokensConfig,
+    pub inlay_hints: InlayHintsConfig,
+    pub formatter: FormatterConfig,
+    pub cache: CacheConfig,
+    pub features: FeaturesConfig,
+    pub lint: LintConfig,
+    pub diagnostics: DiagnosticsConfig,
+    pub rename: RenameConfig,
+    pub trace: TraceConfig,
+    /// Exit when no message arrives for this many seconds, so servers orphaned by a crashed
+    /// editor don't run forever.
+    pub idle_shutdown_seconds: Option<u64>,
+    /// JSON file listing the built-ins of a dialect, replacing the ones of the language.
+    pub builtins_path: Option<PathBuf>,
+    /// Parse documents on a blocking thread, so a long analysis doesn't hold up the requests
+    /// that arrive during it.
+    pub background_analysis: bool,
+    /// Extensions, without the dot, of the files the server handles: the ones it gets semantic
+    /// tokens requests for, watches, and reads from disk.
+    pub file_extensions: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            completion: Default::default(),
+            semantic_tokens: Default::default(),
+            inlay_hints: Default::default(),
+            formatter: Default::default(),
+            cache: Default::default(),
+            features: Default::default(),
+            lint: Default::default(),
+            diagnostics: Default::default(),
+            rename: Default::default(),
+            trace: Default::default(),
+            idle_shutdown_seconds: None,
+            builtins_path: None,
+            background_analysis: false,
+            file_extensions: vec!["nrs".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CompletionConfig {
+    /// Extra characters that trigger completion, on top of [`DEFAULT_TRIGGER_CHARACTERS`].
+    pub trigger_characters: Vec<String>,
+    /// Most completion items returned at once. Longer lists are cut and marked incomplete.
+    pub max_completions: usize,
+}
+
+impl Default for CompletionConfig {
+    fn default() -> Self {
+        CompletionConfig {
+            trigger_characters: vec![],
+            max_completions: 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SemanticTokensConfig {
+    /// Documents longer than this many chars only get `semanticTokens/range` results, so the
+    /// editor never waits on tokens for the whole file.
+    pub range_only_threshold: usize,
+}
+
+impl Default for SemanticTokensConfig {
+    fn default() -> Self {
+        SemanticTokensConfig {
+            range_only_threshold: 1_000_000,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct InlayHintsConfig {
+    /// Show the inferred return type before the body of every function.
+    pub return_types: bool,
+    /// Push the hints of a document with a `gen/inlayHints` notification after every analysis,
+    /// for clients that don't pull them.
+    pub push: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FormatterConfig {
+    /// Refuse to format documents with syntax errors instead of producing nonsense.
+    pub skip_on_error: bool,
+    /// Indentation style that mixed tab and space indentation is normalized to.
+    pub insert_spaces: bool,
+    pub tab_size: u32,
+    /// Formatting requests taking longer than this many milliseconds fail instead of blocking
+    /// the editor.
+    pub timeout_ms: u64,
+    /// Only replace the lines formatting changes, instead of the whole document.
+    pub minimal_edits: bool,
+    /// Line ending `gen.normalizeLineEndings` converts to, `lf` or `crlf`. Without one, the
+    /// ending most lines already use.
+    pub line_ending: Option<LineEnding>,
+}
+
+impl Default for FormatterConfig {
+    fn default() -> Self {
+        FormatterConfig {
+            skip_on_error: true,
+            insert_spaces: true,
+            tab_size: 4,
+            timeout_ms: 5000,
+            minimal_edits: true,
+            line_ending: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CacheConfig {
+    /// Number of documents whose parse results are kept. Documents open in the editor are never
+    /// evicted, even past this capacity.
+    pub capacity: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig { capacity: 256 }
+    }
+}
+
+/// Switches for whole features. A disabled feature isn't advertised in the server capabilities
+/// and its handler answers with nothing.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FeaturesConfig {
+    pub completion: bool,
+    pub hover: bool,
+    pub formatting: bool,
+    pub semantic_tokens: bool,
+    pub inlay_hints: bool,
+    pub diagnostics: bool,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        FeaturesConfig {
+            completion: true,
+            hover: true,
+            formatting: true,
+            semantic_tokens: true,
+            inlay_hints: true,
+            diagnostics: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LintConfig {
+    /// Hint at commas right before the `)` or `]` closing a list.
+    pub trailing_comma: bool,
+    /// Warn about lines wider than this many columns, tabs counting as `formatter.tabSize`.
+    pub max_line_length: Option<usize>,
+    /// Most diagnostics published for a single document, the most severe ones first.
+    pub max_diagnostics: usize,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            trailing_comma: false,
+            max_line_length: None,
+            max_diagnostics: 100,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DiagnosticsConfig {
+    /// Keep showing the diagnostics of files once they are closed. Diagnostics of documents that
+    /// only exist in the editor, like `untitled:` ones, are always cleared.
+    pub keep_closed: bool,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RenameConfig {
+    /// Refuse renames to a name already in scope where the symbol is used, instead of only
+    /// warning about them.
+    pub refuse_collisions: bool,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TraceConfig {
+    /// Initial trace level, taking precedence over the one sent with `initialize`. Clients can
+    /// still change it with `$/setTrace`.
+    pub server: Option<TraceValue>,
+}
+
+/// Whether a `window/logMessage` of type `typ` gets through at the `trace` level: only errors
+/// when tracing is off, everything but `LOG` for `messages`, and everything for `verbose`.
+pub fn trace_allows(trace: TraceValue, typ: MessageType) -> bool {
+    match trace {
+        TraceValue::Off => typ == MessageType::ERROR,
+        TraceValue::Messages => typ != MessageType::LOG,
+        TraceValue::Verbose => true,
+    }
+}
+
+impl Config {
+    /// Reads the `gen` section of `settings`, falling back to the defaults when it is missing or
+    /// malformed.
+    pub fn from_settings(settings: Option<&Value>) -> Config {
+        Config::from_section(settings.and_then(|settings| settings.get("gen")))
+    }
+
+    /// Reads the `gen` section itself, as returned by `workspace/configuration`.
+    pub fn from_section(section: Option<&Value>) -> Config {
+        section
+            .and_then(|section| serde_json::from_value(section.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Whether the file at `path` has one of the configured extensions.
+    pub fn handles_file(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| self.file_extensions.iter().any(|e| e == extension))
+    }
+
+    /// A glob matching the files with one of the configured extensions, anywhere.
+    pub fn file_glob(&self) -> String {
+        match self.file_extensions.as_slice() {
+            [extension] => format!("**/*.{}", extension),
+            extensions => format!("**/*.{{{}}}", extensions.join(",")),
+        }
+    }
+}
+
+impl CompletionConfig {
+    /// The default trigger characters merged with the configured ones, without duplicates.
+    pub fn trigger_characters(&self) -> Vec<String> {
+        let mut characters = DEFAULT_TRIGGER_CHARACTERS
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>();
+        for c in &self.trigger_characters {
+            if !characters.contains(c) {
+                characters.push(c.clone());
+            }
+        }
+        characters
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn trigger_characters_merge_without_duplicates() {
+        let config = Config::from_settings(Some(
+            &json!({ "gen": { "completion": { "triggerCharacters": [":", ".", ":"] } } }),
+        ));
+        assert_eq!(config.completion.trigger_characters(), [".", ":"]);
+    }
+
+    #[test]
+    fn malformed_settings_fall_back_to_the_defaults() {
+        let config = Config::from_settings(Some(&json!({ "gen": { "completion": 3 } })));
+        assert_eq!(config.completion.trigger_characters(), ["."]);
+        assert_eq!(config.completion.max_completions, 200);
+    }
+
+    #[test]
+    fn file_extensions_drive_the_glob_and_the_files_handled() {
+        let config = Config::default();
+        assert_eq!(config.file_glob(), "**/*.nrs");
+        assert!(config.handles_file(Path::new("/src/main.nrs")));
+        assert!(!config.handles_file(Path::new("/src/main.gen")));
+
+        let config = Config::from_settings(Some(
+            &json!({ "gen": { "fileExtensions": ["nrs", "gen"] } }),
+        ));
+        assert_eq!(config.file_glob(), "**/*.{nrs,gen}");
+        assert!(config.handles_file(Path::new("/src/main.gen")));
+        assert!(!config.handles_file(Path::new("/src/gen")));
+    }
+
+    #[test]
+    fn trace_levels_filter_message_types() {
+        let allowed = |trace| {
+            [
+                MessageType::ERROR,
+                MessageType::WARNING,
+                MessageType::INFO,
+                MessageType::LOG,
+            ]
+            .into_iter()
+            .filter(|typ| trace_allows(trace, *typ))
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(allowed(TraceValue::Off), [MessageType::ERROR]);
+        assert_eq!(
+            allowed(TraceValue::Messages),
+            [MessageType::ERROR, MessageType::WARNING, MessageType::INFO]
+        );
+        assert_eq!(allowed(TraceValue::Verbose).len(), 4);
+    }
+}
@@ -0,0 +1,48 @@
+use tower_lsp::lsp_types::Url;
+
+/// Where the diagnostic codes are documented, one section per code.
+const DIAGNOSTICS_DOCS: &str =
+    "https://github.com/PedroWitzel/tower-lsp-boilerplate/blob/main/docs/diagnostics.md";
+
+/// Codes of the diagnostics the server reports, each the anchor of its section in
+/// [`DIAGNOSTICS_DOCS`].
+const DOCUMENTED_CODES: &[&str] = &[
+    "unresolved-symbol",
+    "shadowed-binding",
+    "redeclared-binding",
+    "mixed-indentation",
+    "max-line-length",
+    "trailing-comma",
+    "assignment-in-condition",
+    "mixed-line-endings",
+];
+
+/// The documentation of the diagnostic `code`, if it is one of ours.
+pub fn code_documentation(code: &str) -> Option<Url> {
+    if !DOCUMENTED_CODES.contains(&code) {
+        return None;
+    }
+    Url::parse(&format!("{}#{}", DIAGNOSTICS_DOCS, code)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_link_to_their_section() {
+        assert_eq!(
+            code_documentation("unresolved-symbol").unwrap().fragment(),
+            Some("unresolved-symbol")
+        );
+        assert_eq!(code_documentation("E0425"), None);
+    }
+
+    #[test]
+    fn every_code_has_a_section() {
+        let docs = include_str!("../docs/diagnostics.md");
+        for code in DOCUMENTED_CODES {
+            assert!(docs.contains(&format!("\n## {}\n", code)), "{}", code);
+        }
+    }
+}
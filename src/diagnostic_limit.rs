@@ -0,0 +1,86 @@
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Range};
+
+/// Sorts `diagnostics` by severity, most severe first, then by position, and keeps the first
+/// `max` of them. A final information diagnostic at the start of the document tells how many
+/// were dropped. Diagnostics without a severity count as errors.
+pub fn sort_and_limit(diagnostics: &mut Vec<Diagnostic>, max: usize) {
+    diagnostics.sort_by_key(|diagnostic| {
+        (
+            diagnostic.severity.unwrap_or(DiagnosticSeverity::ERROR),
+            diagnostic.range.start,
+        )
+    });
+    if diagnostics.len() <= max {
+        return;
+    }
+    let dropped = diagnostics.len() - max;
+    diagnostics.truncate(max);
+    diagnostics.push(Diagnostic {
+        range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+        severity: Some(DiagnosticSeverity::INFORMATION),
+        code: Some(NumberOrString::String("truncated-diagnostics".to_string())),
+        message: format!(
+            "{} more diagnostics not shown, raise `gen.lint.maxDiagnostics` to see them",
+            dropped
+        ),
+        ..Default::default()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(line: u32, severity: Option<DiagnosticSeverity>) -> Diagnostic {
+        Diagnostic {
+            range: Range::new(Position::new(line, 0), Position::new(line, 1)),
+            severity,
+            message: format!("line {}", line),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn most_severe_first_then_by_position() {
+        let mut diagnostics = vec![
+            diagnostic(0, Some(DiagnosticSeverity::HINT)),
+            diagnostic(3, Some(DiagnosticSeverity::ERROR)),
+            diagnostic(1, Some(DiagnosticSeverity::WARNING)),
+            diagnostic(2, None),
+        ];
+        sort_and_limit(&mut diagnostics, 10);
+        let lines = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.range.start.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [2, 3, 1, 0]);
+    }
+
+    #[test]
+    fn dropped_diagnostics_are_counted() {
+        let mut diagnostics = (0..5)
+            .map(|line| diagnostic(line, Some(DiagnosticSeverity::WARNING)))
+            .chain([diagnostic(9, Some(DiagnosticSeverity::ERROR))])
+            .collect::<Vec<_>>();
+        sort_and_limit(&mut diagnostics, 3);
+        let lines = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.range.start.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [9, 0, 1, 0]);
+        let marker = diagnostics.last().unwrap();
+        assert_eq!(marker.severity, Some(DiagnosticSeverity::INFORMATION));
+        assert_eq!(
+            marker.code,
+            Some(NumberOrString::String("truncated-diagnostics".to_string()))
+        );
+        assert!(marker.message.starts_with("3 more diagnostics not shown"));
+    }
+
+    #[test]
+    fn exactly_max_is_not_truncated() {
+        let mut diagnostics = vec![diagnostic(0, None), diagnostic(1, None)];
+        sort_and_limit(&mut diagnostics, 2);
+        assert_eq!(diagnostics.len(), 2);
+    }
+}
@@ -0,0 +1,60 @@
+use ropey::Rope;
+
+/// Tag marking a symbol as deprecated in its doc comment, e.g. `/// @deprecated use bar`.
+pub const DEPRECATED_TAG: &str = "@deprecated";
+
+/// The `///` doc comment block directly above the line containing `offset`, with the comment
+/// markers stripped, or `None` when there is none.
+pub fn doc_comment(rope: &Rope, offset: usize) -> Option<String> {
+    let line = rope.try_char_to_line(offset).ok()?;
+    let mut lines = vec![];
+    for line in (0..line).rev() {
+        let text = rope.line(line).to_string();
+        match text.trim().strip_prefix("///") {
+            Some(comment) => lines.push(comment.strip_prefix(' ').unwrap_or(comment).to_string()),
+            None => break,
+        }
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    lines.reverse();
+    Some(lines.join("\n"))
+}
+
+pub fn is_deprecated(doc_comment: &str) -> bool {
+    doc_comment
+        .lines()
+        .any(|line| line.trim_start().starts_with(DEPRECATED_TAG))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc_of(src: &str, name: &str) -> Option<String> {
+        doc_comment(&Rope::from_str(src), src.find(name).unwrap())
+    }
+
+    #[test]
+    fn multi_line_blocks_directly_above() {
+        let src = "// not a doc\n\n/// Adds two numbers.\n///\n///    Indented example.\n  ///No space.\nfn add(a, b) { a + b }\n";
+        assert_eq!(
+            doc_of(src, "add"),
+            Some("Adds two numbers.\n\n   Indented example.\nNo space.".to_string())
+        );
+    }
+
+    #[test]
+    fn blank_lines_or_plain_comments_end_the_block() {
+        assert_eq!(doc_of("/// Detached.\n\nfn main() { 1 }\n", "main"), None);
+        assert_eq!(doc_of("/// Doc.\n// note\nfn main() { 1 }\n", "main"), None);
+        assert_eq!(doc_of("fn main() { 1 }\n", "main"), None);
+    }
+
+    #[test]
+    fn deprecated_tag_on_any_line() {
+        assert!(is_deprecated("Old.\n  @deprecated use `new`"));
+        assert!(!is_deprecated("Mentions @deprecated mid-line."));
+    }
+}
@@ -0,0 +1,105 @@
+use chumsky::Parser;
+use tower_lsp::lsp_types::Color;
+
+use crate::chumsky::{lexer, Span, Token};
+
+/// Color literals of `src`: string literals holding `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`.
+/// The span covers the string contents, without the quotes.
+pub fn document_colors(src: &str) -> Vec<(Color, Span)> {
+    // Every color literal has a `#`, so most files are done without lexing at all
+    if !src.contains('#') {
+        return vec![];
+    }
+    let (tokens, _) = lexer().parse_recovery(src);
+    let chars = src.chars().collect::<Vec<_>>();
+    tokens
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(token, span)| match token {
+            Token::Str(s) => {
+                let color = parse_hex_color(&s)?;
+                // Token spans may include the surrounding padding, so look for the opening quote
+                let quote = chars[span.clone()].iter().position(|c| *c == '"')?;
+                let start = span.start + quote + 1;
+                Some((color, start..start + s.chars().count()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn parse_hex_color(literal: &str) -> Option<Color> {
+    let digits = literal.strip_prefix('#')?;
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channels = match digits.len() {
+        3 | 4 => digits
+            .chars()
+            .map(|c| c.to_digit(16).unwrap() * 0x11)
+            .collect::<Vec<_>>(),
+        6 | 8 => (0..digits.len())
+            .step_by(2)
+            .map(|i| u32::from_str_radix(&digits[i..i + 2], 16).unwrap())
+            .collect::<Vec<_>>(),
+        _ => return None,
+    };
+    let channel = |i: usize| channels.get(i).map_or(1.0, |c| *c as f32 / 255.0);
+    Some(Color {
+        red: channel(0),
+        green: channel(1),
+        blue: channel(2),
+        alpha: channel(3),
+    })
+}
+
+/// `#rrggbb`, or `#rrggbbaa` when the color isn't opaque.
+pub fn color_to_hex(color: &Color) -> String {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let mut hex = format!(
+        "#{:02x}{:02x}{:02x}",
+        channel(color.red),
+        channel(color.green),
+        channel(color.blue)
+    );
+    if channel(color.alpha) != 255 {
+        hex.push_str(&format!("{:02x}", channel(color.alpha)));
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_without_hashes_have_no_colors() {
+        assert!(document_colors("fn main() {\n    \"red\"\n}\n").is_empty());
+        assert!(document_colors("").is_empty());
+    }
+
+    #[test]
+    fn colors_span_the_string_contents() {
+        let src = "fn main() {\n    [\"#ff0000\", \"#0f08\", \"#nothex\"]\n}\n";
+        let colors = document_colors(src);
+        assert_eq!(colors.len(), 2);
+        assert_eq!(&src[colors[0].1.clone()], "#ff0000");
+        assert_eq!(&src[colors[1].1.clone()], "#0f08");
+        assert_eq!(color_to_hex(&colors[0].0), "#ff0000");
+        assert_eq!(color_to_hex(&colors[1].0), "#00ff0088");
+    }
+
+    #[test]
+    fn hex_colors_of_every_length() {
+        assert_eq!(
+            parse_hex_color("#abc").map(|c| color_to_hex(&c)),
+            Some("#aabbcc".to_string())
+        );
+        assert_eq!(
+            parse_hex_color("#11223344").map(|c| color_to_hex(&c)),
+            Some("#11223344".to_string())
+        );
+        assert_eq!(parse_hex_color("#12345"), None);
+        assert_eq!(parse_hex_color("abc"), None);
+    }
+}
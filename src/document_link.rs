@@ -0,0 +1,76 @@
+use crate::chumsky::Span;
+
+/// The `[[Name]]` references to symbols written in the `//` comments of `src`, with the span of
+/// the whole reference, brackets included.
+pub fn symbol_links(src: &str) -> Vec<(String, Span)> {
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut links = vec![];
+    let mut in_string = false;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            // Strings have no escaped quotes, the next quote always ends them
+            '"' => in_string = !in_string,
+            '/' if !in_string && chars.get(i + 1) == Some(&'/') => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|c| *c == '\n')
+                    .map_or(chars.len(), |len| i + len);
+                let mut j = i + 2;
+                while j + 1 < end {
+                    if chars[j] != '[' || chars[j + 1] != '[' {
+                        j += 1;
+                        continue;
+                    }
+                    let name_len = chars[j + 2..end]
+                        .iter()
+                        .take_while(|c| c.is_alphanumeric() || **c == '_')
+                        .count();
+                    let close = j + 2 + name_len;
+                    if name_len > 0 && chars.get(close..close + 2) == Some(&[']', ']'][..]) {
+                        let name = chars[j + 2..close].iter().collect();
+                        links.push((name, j..close + 2));
+                        j = close + 2;
+                    } else {
+                        j += 1;
+                    }
+                }
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(src: &str) -> Vec<(String, &str)> {
+        symbol_links(src)
+            .into_iter()
+            .map(|(name, span)| (name, &src[span]))
+            .collect()
+    }
+
+    #[test]
+    fn references_in_comments() {
+        assert_eq!(
+            links("// See [[Foo]] and [[bar_2]].\nfn main() { 1 } // [[main]]\n"),
+            [
+                ("Foo".to_string(), "[[Foo]]"),
+                ("bar_2".to_string(), "[[bar_2]]"),
+                ("main".to_string(), "[[main]]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_references_and_strings_are_skipped() {
+        assert!(links("// [[]] [[a b]] [[Foo] [Foo]]\n// [[Foo\n]]\n").is_empty());
+        assert!(links("fn main() { \"// [[Foo]]\" }\n").is_empty());
+    }
+}
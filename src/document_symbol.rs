@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use ropey::Rope;
+use tower_lsp::lsp_types::{CompletionItemKind, SymbolKind};
+
+use crate::chumsky::{Expr, Func, Span, Spanned};
+use crate::doc_comment::{doc_comment, is_deprecated};
+use crate::visitor::{walk_expr, Visitor};
+
+#[derive(Debug)]
+pub struct ImCompleteDocumentSymbol {
+    pub name: String,
+    pub detail: Option<String>,
+    pub kind: SymbolKind,
+    pub deprecated: bool,
+    pub span: Span,
+    pub selection_span: Span,
+    pub children: Vec<ImCompleteDocumentSymbol>,
+}
+
+/// Functions of the document in source order, each with its `let` bindings as children.
+pub fn get_document_symbols(
+    ast: &HashMap<String, Func>,
+    rope: &Rope,
+) -> Vec<ImCompleteDocumentSymbol> {
+    let mut symbols = ast
+        .values()
+        .map(|v| {
+            let mut bindings = BindingCollector { symbols: vec![] };
+            bindings.visit_func(v);
+            ImCompleteDocumentSymbol {
+                name: v.name.0.clone(),
+                detail: Some(signature(v)),
+                kind: SymbolKind::FUNCTION,
+                deprecated: doc_comment(rope, v.name.1.start)
+                    .map(|doc| is_deprecated(&doc))
+                    .unwrap_or(false),
+                span: v.span.clone(),
+                selection_span: v.name.1.clone(),
+                children: bindings.symbols,
+            }
+        })
+        .collect::<Vec<_>>();
+    symbols.sort_by_key(|symbol| symbol.span.start);
+    symbols
+}
+
+/// The completion kind of a symbol of kind `kind`, so completions show the icon the outline
+/// shows for the symbol.
+pub fn completion_kind(kind: SymbolKind) -> CompletionItemKind {
+    match kind {
+        SymbolKind::FUNCTION => CompletionItemKind::FUNCTION,
+        SymbolKind::METHOD => CompletionItemKind::METHOD,
+        SymbolKind::CONSTRUCTOR => CompletionItemKind::CONSTRUCTOR,
+        SymbolKind::VARIABLE => CompletionItemKind::VARIABLE,
+        SymbolKind::CONSTANT => CompletionItemKind::CONSTANT,
+        SymbolKind::FIELD => CompletionItemKind::FIELD,
+        SymbolKind::PROPERTY => CompletionItemKind::PROPERTY,
+        SymbolKind::CLASS => CompletionItemKind::CLASS,
+        SymbolKind::STRUCT => CompletionItemKind::STRUCT,
+        SymbolKind::INTERFACE => CompletionItemKind::INTERFACE,
+        SymbolKind::ENUM => CompletionItemKind::ENUM,
+        SymbolKind::ENUM_MEMBER => CompletionItemKind::ENUM_MEMBER,
+        SymbolKind::TYPE_PARAMETER => CompletionItemKind::TYPE_PARAMETER,
+        SymbolKind::MODULE | SymbolKind::NAMESPACE | SymbolKind::PACKAGE => {
+            CompletionItemKind::MODULE
+        }
+        SymbolKind::FILE => CompletionItemKind::FILE,
+        SymbolKind::OPERATOR => CompletionItemKind::OPERATOR,
+        SymbolKind::EVENT => CompletionItemKind::EVENT,
+        _ => CompletionItemKind::VALUE,
+    }
+}
+
+/// `fn name(arg, ...)`, as written in the source.
+pub fn signature(func: &Func) -> String {
+    format!(
+        "fn {}({})",
+        func.name.0,
+        func.args
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// Collects the `let` bindings it visits as variable symbols.
+struct BindingCollector {
+    symbols: Vec<ImCompleteDocumentSymbol>,
+}
+
+impl Visitor for BindingCollector {
+    fn visit_expr(&mut self, expr: &Spanned<Expr>) {
+        if let Expr::Let(name, lhs, _, name_span) = &expr.0 {
+            self.symbols.push(ImCompleteDocumentSymbol {
+                name: name.clone(),
+                detail: None,
+                kind: SymbolKind::VARIABLE,
+                deprecated: false,
+                span: name_span.start..lhs.1.end,
+                selection_span: name_span.clone(),
+                children: vec![],
+            });
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parse;
+
+    #[test]
+    fn functions_with_their_bindings() {
+        let src = "fn add(a, b) {\n    let sum = a + b;\n    sum\n}\n\
+                   fn main() {\n    let x = 1;\n    let y = add(x, 2);\n    y\n}\n";
+        let ast = parse(src).0.unwrap();
+        let symbols = get_document_symbols(&ast, &Rope::from_str(src));
+        let outline = symbols
+            .iter()
+            .map(|symbol| {
+                let children = symbol
+                    .children
+                    .iter()
+                    .map(|child| (child.name.as_str(), child.kind))
+                    .collect::<Vec<_>>();
+                (symbol.name.as_str(), symbol.detail.clone(), children)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outline,
+            [
+                (
+                    "add",
+                    Some("fn add(a, b)".to_string()),
+                    vec![("sum", SymbolKind::VARIABLE)]
+                ),
+                (
+                    "main",
+                    Some("fn main()".to_string()),
+                    vec![("x", SymbolKind::VARIABLE), ("y", SymbolKind::VARIABLE)]
+                ),
+            ]
+        );
+        let y = &symbols[1].children[1];
+        assert_eq!(&src[y.selection_span.clone()], "y");
+        assert_eq!(&src[y.span.clone()], "y = add(x, 2)");
+    }
+
+    #[test]
+    fn deprecated_functions_are_flagged() {
+        let src = "/// @deprecated use `new`\nfn old() { 1 }\nfn new() { 2 }\n";
+        let ast = parse(src).0.unwrap();
+        let symbols = get_document_symbols(&ast, &Rope::from_str(src));
+        let deprecated = symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.deprecated))
+            .collect::<Vec<_>>();
+        assert_eq!(deprecated, [("old", true), ("new", false)]);
+    }
+
+    #[test]
+    fn completion_kinds_follow_symbol_kinds() {
+        assert_eq!(
+            completion_kind(SymbolKind::FUNCTION),
+            CompletionItemKind::FUNCTION
+        );
+        assert_eq!(
+            completion_kind(SymbolKind::VARIABLE),
+            CompletionItemKind::VARIABLE
+        );
+        assert_eq!(
+            completion_kind(SymbolKind::CONSTANT),
+            CompletionItemKind::CONSTANT
+        );
+        assert_eq!(
+            completion_kind(SymbolKind::CLASS),
+            CompletionItemKind::CLASS
+        );
+        assert_eq!(
+            completion_kind(SymbolKind::STRUCT),
+            CompletionItemKind::STRUCT
+        );
+        assert_eq!(
+            completion_kind(SymbolKind::NAMESPACE),
+            CompletionItemKind::MODULE
+        );
+        assert_eq!(
+            completion_kind(SymbolKind::NUMBER),
+            CompletionItemKind::VALUE
+        );
+    }
+}
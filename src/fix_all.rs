@@ -0,0 +1,73 @@
+use tower_lsp::lsp_types::{Range, TextEdit};
+
+/// The edits of every fix in `fixes` that doesn't overlap the ones kept before it, along with
+/// the number of fixes kept. A fix is kept or skipped as a whole, so none is ever half applied.
+pub fn combine_fixes(fixes: Vec<Vec<TextEdit>>) -> (Vec<TextEdit>, usize) {
+    let mut edits: Vec<TextEdit> = vec![];
+    let mut applied = 0;
+    for fix in fixes {
+        let conflicts = fix.iter().enumerate().any(|(i, edit)| {
+            edits
+                .iter()
+                .chain(&fix[..i])
+                .any(|kept| overlap(&kept.range, &edit.range))
+        });
+        if !conflicts {
+            edits.extend(fix);
+            applied += 1;
+        }
+    }
+    edits.sort_by_key(|edit| edit.range.start);
+    (edits, applied)
+}
+
+/// Whether edits of `a` and `b` can't both be applied: they share some text, or they insert at
+/// the same position, where their order would be ambiguous.
+fn overlap(a: &Range, b: &Range) -> bool {
+    a == b || (a.start < b.end && b.start < a.end)
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Position;
+
+    use super::*;
+
+    fn edit(line: u32, start: u32, end: u32, text: &str) -> TextEdit {
+        TextEdit::new(
+            Range::new(Position::new(line, start), Position::new(line, end)),
+            text.to_string(),
+        )
+    }
+
+    #[test]
+    fn disjoint_fixes_are_combined_in_document_order() {
+        let (edits, applied) = combine_fixes(vec![
+            vec![edit(2, 0, 3, "c")],
+            vec![edit(0, 0, 3, "a"), edit(1, 4, 4, "b")],
+        ]);
+        assert_eq!(applied, 2);
+        assert_eq!(
+            edits,
+            [edit(0, 0, 3, "a"), edit(1, 4, 4, "b"), edit(2, 0, 3, "c")]
+        );
+    }
+
+    #[test]
+    fn overlapping_fixes_are_skipped_whole() {
+        let (edits, applied) = combine_fixes(vec![
+            vec![edit(0, 0, 5, "a")],
+            vec![edit(1, 0, 1, "b"), edit(0, 4, 6, "c")],
+            vec![edit(2, 3, 3, "d")],
+            vec![edit(2, 3, 3, "e")],
+        ]);
+        assert_eq!(applied, 2);
+        assert_eq!(edits, [edit(0, 0, 5, "a"), edit(2, 3, 3, "d")]);
+    }
+
+    #[test]
+    fn touching_ranges_do_not_overlap() {
+        let (_, applied) = combine_fixes(vec![vec![edit(0, 0, 2, "a")], vec![edit(0, 2, 4, "b")]]);
+        assert_eq!(applied, 2);
+    }
+}
@@ -0,0 +1,146 @@
+use chumsky::Parser;
+use ropey::Rope;
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind};
+
+use crate::chumsky::{lexer, skip_padding, Token};
+
+const REGION_START: &str = "region";
+const REGION_END: &str = "endregion";
+
+/// Folds for every bracket pair spanning several lines, keeping the closing line visible, and
+/// for every `// region <name>` ... `// endregion` block.
+pub fn folding_ranges(src: &str) -> Vec<FoldingRange> {
+    let (tokens, _) = lexer().parse_recovery(src);
+    let tokens = tokens.unwrap_or_default();
+    let rope = Rope::from_str(src);
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut ranges = vec![];
+
+    let mut open = vec![];
+    for (token, span) in &tokens {
+        match token {
+            Token::Ctrl('{' | '(' | '[') => {
+                open.push(rope.char_to_line(skip_padding(&chars, span.start)))
+            }
+            Token::Ctrl('}' | ')' | ']') => {
+                let end_line = rope.char_to_line(skip_padding(&chars, span.start));
+                if let Some(start_line) = open.pop() {
+                    if end_line > start_line + 1 {
+                        ranges.push(FoldingRange {
+                            start_line: start_line as u32,
+                            end_line: end_line as u32 - 1,
+                            ..Default::default()
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let multi_line_strings = tokens
+        .iter()
+        .filter(|(token, _)| matches!(token, Token::Str(s) if s.contains('\n')))
+        .map(|(_, span)| span.clone())
+        .collect::<Vec<_>>();
+    let mut regions = vec![];
+    for (line, text) in rope.lines().enumerate() {
+        let line_start = rope.line_to_char(line);
+        if multi_line_strings
+            .iter()
+            .any(|span| span.start < line_start && span.end > line_start)
+        {
+            continue;
+        }
+        let text = text.to_string();
+        let marker = match text.trim().strip_prefix("//") {
+            Some(marker) => marker.trim(),
+            None => continue,
+        };
+        if let Some(name) = marker.strip_prefix(REGION_END) {
+            if name.is_empty() || name.starts_with(' ') {
+                if let Some((start_line, name)) = regions.pop() {
+                    ranges.push(FoldingRange {
+                        start_line,
+                        end_line: line as u32,
+                        kind: Some(FoldingRangeKind::Region),
+                        collapsed_text: name,
+                        ..Default::default()
+                    });
+                }
+            }
+        } else if let Some(name) = marker.strip_prefix(REGION_START) {
+            if name.is_empty() || name.starts_with(' ') {
+                let name = name.trim();
+                regions.push((line as u32, (!name.is_empty()).then(|| name.to_string())));
+            }
+        }
+    }
+
+    ranges.sort_by_key(|range| (range.start_line, range.end_line));
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folds(src: &str) -> Vec<(u32, u32, Option<FoldingRangeKind>, Option<String>)> {
+        folding_ranges(src)
+            .into_iter()
+            .map(|range| {
+                (
+                    range.start_line,
+                    range.end_line,
+                    range.kind,
+                    range.collapsed_text,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn region_markers_fold_the_marked_lines() {
+        let src =
+            "// region helpers\nfn one() { 1 }\nfn two() { 2 }\n// endregion\nfn main() { 3 }\n";
+        assert_eq!(
+            folds(src),
+            [(
+                0,
+                3,
+                Some(FoldingRangeKind::Region),
+                Some("helpers".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn regions_nest_and_need_a_whole_word() {
+        let src =
+            "// region\n// regional news\n    //region inner\n    //endregion\n// endregion\n";
+        assert_eq!(
+            folds(src),
+            [
+                (0, 4, Some(FoldingRangeKind::Region), None),
+                (
+                    2,
+                    3,
+                    Some(FoldingRangeKind::Region),
+                    Some("inner".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_line_brackets_keep_the_closing_line() {
+        let src = "fn main() {\n    let x = [\n        1,\n    ];\n    x\n}\n";
+        assert_eq!(folds(src), [(0, 4, None, None), (1, 2, None, None)]);
+    }
+
+    #[test]
+    fn markers_inside_strings_are_ignored() {
+        let src = "fn main() {\n    \"a\n// region\nb\"\n}\n";
+        assert!(folds(src).iter().all(|(_, _, kind, _)| kind.is_none()));
+    }
+}
@@ -0,0 +1,292 @@
+use chumsky::Parser;
+use tower_lsp::lsp_types::{FormattingOptions, Position, Range, TextEdit};
+
+use crate::chumsky::{lexer, Token};
+
+/// Re-indents every line by its bracket depth and strips trailing whitespace, leaving the
+/// content of multi-line string literals untouched. The line count never changes, so line `n`
+/// of the output always corresponds to line `n` of the input.
+pub fn format(src: &str, options: &FormattingOptions) -> String {
+    let (tokens, _) = lexer().parse_recovery(src);
+    let tokens = tokens.unwrap_or_default();
+    let multi_line_strings = tokens
+        .iter()
+        .filter(|(token, _)| matches!(token, Token::Str(s) if s.contains('\n')))
+        .map(|(_, span)| span.clone())
+        .collect::<Vec<_>>();
+    let indent_unit = indent_unit(options);
+
+    let mut formatted = String::with_capacity(src.len());
+    let mut tokens = tokens.iter().peekable();
+    let mut depth = 0usize;
+    let mut line_start = 0;
+    for line in src.split_inclusive('\n') {
+        let line_end = line_start + line.chars().count();
+        let content = line.trim_end_matches(['\n', '\r']);
+        let line_ending = &line[content.len()..];
+
+        while let Some((token, _)) = tokens.next_if(|(_, span)| span.start < line_start) {
+            match token {
+                Token::Ctrl('{' | '(' | '[') => depth += 1,
+                Token::Ctrl('}' | ')' | ']') => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        let content_end = line_start + content.chars().count();
+        let starts_in_string = multi_line_strings
+            .iter()
+            .any(|span| span.start < line_start && span.end > line_start);
+        let ends_in_string = multi_line_strings
+            .iter()
+            .any(|span| span.start < content_end && span.end > content_end);
+        let trimmed = if ends_in_string {
+            content.trim_start()
+        } else {
+            content.trim()
+        };
+        if starts_in_string {
+            formatted.push_str(content);
+        } else if !trimmed.is_empty() {
+            let level = if trimmed.starts_with(['}', ')', ']']) {
+                depth.saturating_sub(1)
+            } else {
+                depth
+            };
+            formatted.push_str(&indent_unit.repeat(level));
+            formatted.push_str(trimmed);
+        }
+        formatted.push_str(line_ending);
+        line_start = line_end;
+    }
+    formatted
+}
+
+/// Edits after Enter was typed at the end of line `line - 1`: the new `line` is indented by its
+/// bracket depth, and when Enter split an opening bracket from its closing one, the closing
+/// bracket is moved to a line of its own, one level less indented.
+pub fn on_enter(src: &str, line: usize, options: &FormattingOptions) -> Vec<TextEdit> {
+    let lines = src.split('\n').collect::<Vec<_>>();
+    if line == 0 || line >= lines.len() {
+        return vec![];
+    }
+    let (previous, current) = (lines[line - 1], lines[line]);
+    let line_start = lines[..line]
+        .iter()
+        .map(|l| l.chars().count() + 1)
+        .sum::<usize>();
+    let (tokens, _) = lexer().parse_recovery(src);
+    let mut depth = 0usize;
+    for (token, span) in tokens.unwrap_or_default() {
+        if span.start >= line_start {
+            break;
+        }
+        match token {
+            Token::Str(_) if span.end > line_start => return vec![],
+            Token::Ctrl('{' | '(' | '[') => depth += 1,
+            Token::Ctrl('}' | ')' | ']') => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    let indent_unit = indent_unit(options);
+    let current_indent = current
+        .chars()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .count();
+    let rest = current.trim_start_matches([' ', '\t']);
+    let mut new_text = indent_unit.repeat(depth);
+    if previous.trim_end().ends_with(['{', '(', '[']) && rest.starts_with(['}', ')', ']']) {
+        new_text.push('\n');
+        new_text.push_str(&indent_unit.repeat(depth.saturating_sub(1)));
+    } else if rest.starts_with(['}', ')', ']']) {
+        new_text = indent_unit.repeat(depth.saturating_sub(1));
+    }
+    vec![TextEdit::new(
+        Range::new(
+            Position::new(line as u32, 0),
+            Position::new(line as u32, current_indent as u32),
+        ),
+        new_text,
+    )]
+}
+
+/// Edits turning `src` into `formatted`, one per run of consecutive changed lines, so the
+/// editor keeps the cursor and folds of untouched lines. Lines break where they did in
+/// `formatted`, `\r\n` included, so the edits keep the line endings of a CRLF document. `None`
+/// when the line counts differ, which `format` never causes.
+pub fn line_edits(src: &str, formatted: &str) -> Option<Vec<TextEdit>> {
+    fn strip_cr(line: &str) -> &str {
+        line.strip_suffix('\r').unwrap_or(line)
+    }
+    let old = src.split('\n').collect::<Vec<_>>();
+    let new = formatted.split('\n').collect::<Vec<_>>();
+    if old.len() != new.len() {
+        return None;
+    }
+    let changed = |line: usize| strip_cr(old[line]) != strip_cr(new[line]);
+    let mut edits = vec![];
+    let mut line = 0;
+    while line < old.len() {
+        if !changed(line) {
+            line += 1;
+            continue;
+        }
+        let first = line;
+        while line < old.len() && changed(line) {
+            line += 1;
+        }
+        let last = line - 1;
+        // The edit stops before the line ending of its last line, which stays as it is
+        let new_text = new[first..line].join("\n");
+        edits.push(TextEdit::new(
+            Range::new(
+                Position::new(first as u32, 0),
+                Position::new(last as u32, strip_cr(old[last]).chars().count() as u32),
+            ),
+            strip_cr(&new_text).to_string(),
+        ));
+    }
+    Some(edits)
+}
+
+fn indent_unit(options: &FormattingOptions) -> String {
+    if options.insert_spaces {
+        " ".repeat(options.tab_size as usize)
+    } else {
+        "\t".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> FormattingOptions {
+        FormattingOptions {
+            tab_size: 4,
+            insert_spaces: true,
+            ..Default::default()
+        }
+    }
+
+    /// `src` with `edits` applied, which must not overlap.
+    fn apply(src: &str, edits: &[TextEdit]) -> String {
+        let rope = ropey::Rope::from_str(src);
+        let mut result = rope.clone();
+        for edit in edits.iter().rev() {
+            let start = rope.line_to_char(edit.range.start.line as usize)
+                + edit.range.start.character as usize;
+            let end =
+                rope.line_to_char(edit.range.end.line as usize) + edit.range.end.character as usize;
+            result.remove(start..end);
+            result.insert(start, &edit.new_text);
+        }
+        result.to_string()
+    }
+
+    #[test]
+    fn reindents_by_bracket_depth() {
+        let src = "fn main() {\nlet x = [\n1,\n2];   \n        x\n    }\n";
+        assert_eq!(
+            format(src, &options()),
+            "fn main() {\n    let x = [\n        1,\n        2];\n    x\n}\n"
+        );
+    }
+
+    #[test]
+    fn only_changed_lines_get_edits() {
+        let src = "fn main() {\n    let a = 1;\nlet b = 2;\n    let c = 3;\n  a\n   b\n}\n";
+        let formatted = format(src, &options());
+        let edits = line_edits(src, &formatted).unwrap();
+        assert_eq!(
+            edits,
+            [
+                TextEdit::new(
+                    Range::new(Position::new(2, 0), Position::new(2, 10)),
+                    "    let b = 2;".to_string()
+                ),
+                TextEdit::new(
+                    Range::new(Position::new(4, 0), Position::new(5, 4)),
+                    "    a\n    b".to_string()
+                ),
+            ]
+        );
+        assert_eq!(apply(src, &edits), formatted);
+    }
+
+    #[test]
+    fn formatted_text_gets_no_edits() {
+        let src = "fn main() {\n    1\n}\n";
+        assert_eq!(line_edits(src, &format(src, &options())), Some(vec![]));
+    }
+
+    #[test]
+    fn edits_keep_crlf_line_endings() {
+        let src = "fn main() {\r\n  let a = 1;\r\n  a\r\n}\r\n";
+        let formatted = format(src, &options());
+        assert_eq!(formatted, "fn main() {\r\n    let a = 1;\r\n    a\r\n}\r\n");
+        let edits = line_edits(src, &formatted).unwrap();
+        assert_eq!(
+            edits,
+            [TextEdit::new(
+                Range::new(Position::new(1, 0), Position::new(2, 3)),
+                "    let a = 1;\r\n    a".to_string()
+            )]
+        );
+        assert_eq!(apply(src, &edits), formatted);
+    }
+
+    #[test]
+    fn differing_line_counts_get_no_edits() {
+        assert_eq!(line_edits("fn main() {}\n", "fn main() {}\n\n"), None);
+    }
+
+    #[test]
+    fn on_enter_moves_the_closing_bracket_down() {
+        let src = "fn main() {\n}";
+        assert_eq!(
+            on_enter(src, 1, &options()),
+            [TextEdit::new(
+                Range::new(Position::new(1, 0), Position::new(1, 0)),
+                "    \n".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn on_enter_in_nested_brackets() {
+        let src = "fn main() {\n    let x = [\n]\n}\n";
+        assert_eq!(
+            on_enter(src, 2, &options()),
+            [TextEdit::new(
+                Range::new(Position::new(2, 0), Position::new(2, 0)),
+                "        \n    ".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn on_enter_reindents_the_new_line() {
+        let src = "fn main() {\n    let x = 1;\n  x\n}\n";
+        assert_eq!(
+            on_enter(src, 2, &options()),
+            [TextEdit::new(
+                Range::new(Position::new(2, 0), Position::new(2, 2)),
+                "    ".to_string()
+            )]
+        );
+        let tabs = FormattingOptions {
+            insert_spaces: false,
+            ..options()
+        };
+        assert_eq!(on_enter(src, 2, &tabs)[0].new_text, "\t");
+    }
+
+    #[test]
+    fn on_enter_inside_a_string_does_nothing() {
+        let src = "fn main() {\n    \"a\nb\"\n}\n";
+        assert!(on_enter(src, 2, &options()).is_empty());
+        assert!(on_enter(src, 0, &options()).is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use chumsky::Parser;
+
+use crate::chumsky::{lexer, parse_number, skip_padding, NumberLiteral, Span, Token};
+
+/// Markdown hover for the number literal at `offset`, showing its value in alternate bases.
+pub fn number_hover(src: &str, offset: usize) -> Option<(String, Span)> {
+    let (tokens, _) = lexer().parse_recovery(src);
+    let chars = src.chars().collect::<Vec<_>>();
+    // Token spans take in the comments around them, the literal is only part of its span
+    let (literal, span) = tokens?.into_iter().find_map(|(token, span)| match token {
+        Token::Num(n) => {
+            let start = skip_padding(&chars, span.start);
+            let span = start..start + n.chars().count();
+            (span.start <= offset && offset < span.end).then_some((n, span))
+        }
+        _ => None,
+    })?;
+    let contents = match parse_number(&literal)? {
+        NumberLiteral::Int(n) => format!(
+            "```nrs\n{}\n```\n---\ndecimal: `{}`  \nhex: `{:#x}`  \nbinary: `{:#b}`",
+            literal, n, n, n
+        ),
+        NumberLiteral::Float(n) => format!(
+            "```nrs\n{}\n```\n---\ndecimal: `{}`  \nIEEE 754 bits: `{:#018x}`  \nbinary: `{:#066b}`",
+            literal,
+            n,
+            n.to_bits(),
+            n.to_bits()
+        ),
+    };
+    Some((contents, span))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_literal_in_other_bases() {
+        let src = "fn main() { 0xff }";
+        let offset = src.find("0xff").unwrap();
+        let (contents, span) = number_hover(src, offset + 1).unwrap();
+        assert_eq!(&src[span], "0xff");
+        assert!(contents.starts_with("```nrs\n0xff\n```"));
+        assert!(contents.contains("decimal: `255`"));
+        assert!(contents.contains("hex: `0xff`"));
+        assert!(contents.contains("binary: `0b11111111`"));
+    }
+
+    #[test]
+    fn underscores_and_suffixes() {
+        let src = "fn main() { 1_000u32 + 0b1010_1010 }";
+        let (contents, _) = number_hover(src, src.find('1').unwrap()).unwrap();
+        assert!(contents.contains("decimal: `1000`"));
+        assert!(contents.contains("hex: `0x3e8`"));
+        let (contents, _) = number_hover(src, src.find("0b").unwrap()).unwrap();
+        assert!(contents.contains("decimal: `170`"));
+    }
+
+    #[test]
+    fn floats_show_their_bits() {
+        let src = "fn main() { 1.5 }";
+        let (contents, _) = number_hover(src, src.find('1').unwrap()).unwrap();
+        assert!(contents.contains("decimal: `1.5`"));
+        assert!(contents.contains("IEEE 754 bits: `0x3ff8000000000000`"));
+    }
+
+    #[test]
+    fn nothing_off_a_number() {
+        let src = "fn main() { 0xff }";
+        assert_eq!(number_hover(src, src.find("main").unwrap()), None);
+        assert_eq!(number_hover(src, src.find('}').unwrap()), None);
+    }
+
+    #[test]
+    fn comments_around_a_literal_are_not_hovered() {
+        let src = "fn main() {\n    // the answer\n    0xff // in hex\n}\n";
+        let literal = src.find("0xff").unwrap();
+        let (_, span) = number_hover(src, literal).unwrap();
+        assert_eq!(span, literal..literal + 4);
+        assert_eq!(number_hover(src, src.find("in hex").unwrap()), None);
+        assert_eq!(number_hover(src, src.find("answer").unwrap()), None);
+    }
+}
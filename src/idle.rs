@@ -0,0 +1,124 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::time::Instant;
+use tower_lsp::jsonrpc::Request;
+use tower_service::Service;
+
+/// How often a disabled timer checks whether it got enabled.
+const DISABLED_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time since the last message from the client, shared between the service recording the
+/// messages and the task waiting for the server to go idle.
+#[derive(Debug, Clone)]
+pub struct IdleTimer {
+    last_activity: Arc<Mutex<Instant>>,
+    /// Idle period in seconds, `0` when the timer is disabled.
+    timeout_secs: Arc<AtomicU64>,
+}
+
+impl Default for IdleTimer {
+    fn default() -> Self {
+        IdleTimer {
+            last_activity: Arc::new(Mutex::new(Instant::now())),
+            timeout_secs: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl IdleTimer {
+    /// Restarts the idle period.
+    pub fn touch(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+
+    pub fn set_timeout(&self, timeout_secs: Option<u64>) {
+        self.timeout_secs
+            .store(timeout_secs.unwrap_or(0), Ordering::SeqCst);
+    }
+
+    /// Resolves once no message arrived for the whole idle period. Never resolves while the
+    /// timer is disabled.
+    pub async fn expired(&self) {
+        loop {
+            let timeout_secs = self.timeout_secs.load(Ordering::SeqCst);
+            if timeout_secs == 0 {
+                tokio::time::sleep(DISABLED_POLL_INTERVAL).await;
+                continue;
+            }
+            let deadline = *self.last_activity.lock().unwrap() + Duration::from_secs(timeout_secs);
+            if Instant::now() >= deadline {
+                return;
+            }
+            tokio::time::sleep_until(deadline).await;
+        }
+    }
+}
+
+/// Wraps the language server service to restart `timer` on every message it handles.
+pub struct IdleService<S> {
+    inner: S,
+    timer: IdleTimer,
+}
+
+impl<S> IdleService<S> {
+    pub fn new(inner: S, timer: IdleTimer) -> Self {
+        IdleService { inner, timer }
+    }
+}
+
+impl<S: Service<Request>> Service<Request> for IdleService<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        self.timer.touch();
+        self.inner.call(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `timer` expires within `within`, time being paused and auto-advanced.
+    async fn expires_within(timer: &IdleTimer, within: Duration) -> bool {
+        tokio::time::timeout(within, timer.expired()).await.is_ok()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expires_after_the_idle_period() {
+        let timer = IdleTimer::default();
+        timer.set_timeout(Some(10));
+        assert!(!expires_within(&timer, Duration::from_secs(9)).await);
+        assert!(expires_within(&timer, Duration::from_secs(2)).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn activity_restarts_the_period() {
+        let timer = IdleTimer::default();
+        timer.set_timeout(Some(10));
+        for _ in 0..3 {
+            tokio::time::advance(Duration::from_secs(8)).await;
+            timer.touch();
+        }
+        assert!(!expires_within(&timer, Duration::from_secs(9)).await);
+        assert!(expires_within(&timer, Duration::from_secs(2)).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn disabled_timers_never_expire() {
+        let timer = IdleTimer::default();
+        assert!(!expires_within(&timer, Duration::from_secs(3600)).await);
+        // Enabling it later counts from the last activity
+        timer.set_timeout(Some(10));
+        assert!(expires_within(&timer, Duration::from_secs(2)).await);
+    }
+}
@@ -0,0 +1,122 @@
+use chumsky::Parser;
+
+use crate::chumsky::{lexer, Token};
+
+/// Replacement for the leading whitespace of a line.
+#[derive(Debug, PartialEq, Eq)]
+pub struct IndentEdit {
+    pub line: usize,
+    /// Length in chars of the current indentation.
+    pub len: usize,
+    pub indent: String,
+}
+
+/// Lines whose indentation contains both tabs and spaces. Lines starting inside a multi-line
+/// string literal have no indentation of their own and are never reported.
+pub fn mixed_indentation_lines(src: &str) -> Vec<usize> {
+    indentations(src)
+        .filter(|(_, indent)| indent.contains(' ') && indent.contains('\t'))
+        .map(|(line, _)| line)
+        .collect()
+}
+
+/// Edits turning the indentation of every line into `tab_size` wide tabs, or spaces when
+/// `insert_spaces` is set. Tabs in the current indentation advance to the next tab stop, so the
+/// visual indentation is kept.
+pub fn normalize_indentation(src: &str, insert_spaces: bool, tab_size: u32) -> Vec<IndentEdit> {
+    let tab_size = tab_size.max(1) as usize;
+    indentations(src)
+        .filter_map(|(line, indent)| {
+            let width = indent.chars().fold(0, |width, c| match c {
+                '\t' => (width / tab_size + 1) * tab_size,
+                _ => width + 1,
+            });
+            let normalized = if insert_spaces {
+                " ".repeat(width)
+            } else {
+                "\t".repeat(width / tab_size) + &" ".repeat(width % tab_size)
+            };
+            (normalized != indent).then(|| IndentEdit {
+                line,
+                len: indent.chars().count(),
+                indent: normalized,
+            })
+        })
+        .collect()
+}
+
+/// The leading whitespace of every line that doesn't start inside a multi-line string.
+fn indentations(src: &str) -> impl Iterator<Item = (usize, &str)> {
+    let (tokens, _) = lexer().parse_recovery(src);
+    let multi_line_strings = tokens
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(token, _)| matches!(token, Token::Str(s) if s.contains('\n')))
+        .map(|(_, span)| span)
+        .collect::<Vec<_>>();
+    let mut line_start = 0;
+    src.split_inclusive('\n')
+        .enumerate()
+        .filter_map(move |(line, text)| {
+            let start = line_start;
+            line_start += text.chars().count();
+            let starts_in_string = multi_line_strings
+                .iter()
+                .any(|span| span.start < start && span.end > start);
+            let content = text.trim_start_matches([' ', '\t']);
+            (!starts_in_string).then(|| (line, &text[..text.len() - content.len()]))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_leading_whitespace_counts() {
+        let src = "fn main() {\n \tlet x = 1;\n    x +\t 1\n\t\t\n}\n";
+        assert_eq!(mixed_indentation_lines(src), [1]);
+    }
+
+    #[test]
+    fn lines_inside_strings_are_skipped() {
+        let src = "fn main() {\n    \"a\n \t b\"\n}\n";
+        assert!(mixed_indentation_lines(src).is_empty());
+        assert!(normalize_indentation(src, false, 4)
+            .iter()
+            .all(|edit| edit.line != 2));
+    }
+
+    #[test]
+    fn normalizes_to_spaces_keeping_tab_stops() {
+        let src = "fn main() {\n  \tlet x = 1;\n\tx\n}\n";
+        assert_eq!(
+            normalize_indentation(src, true, 4),
+            [
+                IndentEdit {
+                    line: 1,
+                    len: 3,
+                    indent: "    ".to_string()
+                },
+                IndentEdit {
+                    line: 2,
+                    len: 1,
+                    indent: "    ".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn normalizes_to_tabs() {
+        let src = "fn main() {\n \t  let x = 1;\n\tx\n}\n";
+        assert_eq!(
+            normalize_indentation(src, false, 4),
+            [IndentEdit {
+                line: 1,
+                len: 4,
+                indent: "\t  ".to_string()
+            }]
+        );
+    }
+}
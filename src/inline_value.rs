@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+
+use crate::chumsky::{Expr, Func, Span, Spanned};
+use crate::visitor::{walk_expr, Visitor};
+
+/// Arguments, `let` bindings and their uses inside `range`, in the function containing the
+/// `frame` offset a debugger is stopped at. Calls to other functions aren't variables and are
+/// left out.
+pub fn get_variables(
+    ast: &HashMap<String, Func>,
+    frame: usize,
+    range: &Span,
+) -> Vec<Spanned<String>> {
+    let func = match ast
+        .values()
+        .find(|v| v.span.start <= frame && frame < v.span.end)
+    {
+        Some(func) => func,
+        None => return vec![],
+    };
+    let mut bindings = func
+        .args
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    let mut variables = func.args.clone();
+    VariableCollector {
+        bindings: &mut bindings,
+        variables: &mut variables,
+    }
+    .visit_func(func);
+    variables.retain(|(name, span)| {
+        bindings.contains(name) && range.start <= span.start && span.end <= range.end
+    });
+    variables.sort_by_key(|(_, span)| span.start);
+    variables
+}
+
+/// Collects the locals and `let` bindings it visits, remembering the names bound.
+struct VariableCollector<'a> {
+    bindings: &'a mut Vec<String>,
+    variables: &'a mut Vec<Spanned<String>>,
+}
+
+impl Visitor for VariableCollector<'_> {
+    fn visit_expr(&mut self, expr: &Spanned<Expr>) {
+        match &expr.0 {
+            Expr::Local(local) => self.variables.push(local.clone()),
+            Expr::Let(name, _, _, span) => {
+                self.bindings.push(name.clone());
+                self.variables.push((name.clone(), span.clone()));
+            }
+            _ => {}
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parse;
+
+    fn variables(src: &str, frame: &str, range: Span) -> Vec<(String, String)> {
+        let ast = parse(src).0.unwrap();
+        get_variables(&ast, src.find(frame).unwrap(), &range)
+            .into_iter()
+            .map(|(name, span)| (name, src[span].to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn variables_of_the_stopped_function() {
+        let src = "fn add(a, b) {\n    let sum = a + b;\n    sum\n}\nfn main() {\n    let x = add(1, 2);\n    x\n}\n";
+        let names = variables(src, "sum\n", 0..src.len())
+            .into_iter()
+            .map(|(name, text)| {
+                assert_eq!(name, text);
+                name
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a", "b", "sum", "a", "b", "sum"]);
+        let names = variables(src, "x\n", 0..src.len())
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        // `add` is called, not a variable
+        assert_eq!(names, ["x", "x"]);
+    }
+
+    #[test]
+    fn only_inside_the_range() {
+        let src = "fn main(a) {\n    let b = a;\n    b\n}\n";
+        let line = src.find("let").unwrap()..src.find(";").unwrap();
+        let names = variables(src, "b\n", line)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["b", "a"]);
+    }
+
+    #[test]
+    fn nothing_outside_functions() {
+        let src = "fn main() {\n    1\n}\n\n";
+        assert!(variables(src, "\n\n", 0..src.len()).is_empty());
+    }
+}
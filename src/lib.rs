@@ -1,5 +1,44 @@
+pub mod backend;
+pub mod builtin;
+pub mod call_hierarchy;
 pub mod chumsky;
+pub mod client;
 pub mod completion;
+pub mod condition_assignment;
+pub mod config;
+pub mod diagnostic_docs;
+pub mod diagnostic_limit;
+pub mod doc_comment;
+pub mod document_color;
+pub mod document_link;
+pub mod document_symbol;
+pub mod fix_all;
+pub mod folding_range;
+pub mod formatter;
+pub mod hover;
+pub mod idle;
+pub mod indentation;
+pub mod inline_value;
 pub mod jump_definition;
+pub mod line_comment;
+pub mod line_ending;
+pub mod line_length;
+pub mod lru;
+pub mod manifest;
+pub mod match_brace;
+pub mod node_index;
+pub mod progress;
 pub mod reference;
+pub mod selection_range;
+pub mod semantic_cache;
 pub mod semantic_token;
+pub mod shadowing;
+pub mod sort_members;
+pub mod synthetic;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod trailing_comma;
+pub mod unresolved_symbol;
+pub mod utils;
+pub mod visitor;
+pub mod wrap_block;
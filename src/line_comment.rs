@@ -0,0 +1,97 @@
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+/// Edits commenting out lines `start_line..=end_line` of `src` with `//`, or uncommenting them
+/// when every non-blank line among them already starts with `//`. Comment markers go at the
+/// smallest indentation of the lines, so the block keeps its shape; blank lines are left alone.
+pub fn toggle_line_comment(src: &str, start_line: usize, end_line: usize) -> Vec<TextEdit> {
+    let lines = src
+        .lines()
+        .enumerate()
+        .skip(start_line)
+        .take(end_line.saturating_sub(start_line) + 1)
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(line, text)| {
+            let content = text.trim_start();
+            (
+                line,
+                text[..text.len() - content.len()].chars().count(),
+                content,
+            )
+        })
+        .collect::<Vec<_>>();
+    let commented = !lines.is_empty()
+        && lines
+            .iter()
+            .all(|(_, _, content)| content.starts_with("//"));
+    if commented {
+        lines
+            .into_iter()
+            .map(|(line, indent, content)| {
+                let len = if content.starts_with("// ") { 3 } else { 2 };
+                let start = Position::new(line as u32, indent as u32);
+                let end = Position::new(line as u32, (indent + len) as u32);
+                TextEdit::new(Range::new(start, end), String::new())
+            })
+            .collect()
+    } else {
+        let indent = lines
+            .iter()
+            .map(|(_, indent, _)| *indent)
+            .min()
+            .unwrap_or_default();
+        lines
+            .into_iter()
+            .map(|(line, _, _)| {
+                let position = Position::new(line as u32, indent as u32);
+                TextEdit::new(Range::new(position, position), "// ".to_string())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(line: u32, character: u32) -> TextEdit {
+        let position = Position::new(line, character);
+        TextEdit::new(Range::new(position, position), "// ".to_string())
+    }
+
+    fn delete(line: u32, character: u32, len: u32) -> TextEdit {
+        TextEdit::new(
+            Range::new(
+                Position::new(line, character),
+                Position::new(line, character + len),
+            ),
+            String::new(),
+        )
+    }
+
+    #[test]
+    fn comments_at_the_smallest_indentation() {
+        let src = "fn main() {\n    let x = [\n\n        1];\n    x\n}\n";
+        assert_eq!(toggle_line_comment(src, 1, 3), [insert(1, 4), insert(3, 4)]);
+    }
+
+    #[test]
+    fn uncomments_a_commented_block() {
+        let src = "fn main() {\n    // let x = 1;\n    //x\n\n    1\n}\n";
+        assert_eq!(
+            toggle_line_comment(src, 1, 3),
+            [delete(1, 4, 3), delete(2, 4, 2)]
+        );
+    }
+
+    #[test]
+    fn partly_commented_blocks_get_commented() {
+        let src = "// one\ntwo\n";
+        assert_eq!(toggle_line_comment(src, 0, 1), [insert(0, 0), insert(1, 0)]);
+    }
+
+    #[test]
+    fn blank_selections_get_no_edits() {
+        assert!(toggle_line_comment("fn main() {\n\n}\n", 1, 1).is_empty());
+        assert!(toggle_line_comment("fn main() {}\n", 5, 9).is_empty());
+    }
+}
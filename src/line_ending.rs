@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+/// The ending most lines of `src` use, `\n` when there are as many of each, along with the first
+/// line ending otherwise. `None` unless `src` mixes `\n` and `\r\n`.
+pub fn mixed_line_endings(src: &str) -> Option<(LineEnding, usize)> {
+    let endings = line_endings(src).collect::<Vec<_>>();
+    let crlf = endings
+        .iter()
+        .filter(|(_, _, ending)| *ending == LineEnding::Crlf)
+        .count();
+    if crlf == 0 || crlf == endings.len() {
+        return None;
+    }
+    let dominant = if crlf * 2 > endings.len() {
+        LineEnding::Crlf
+    } else {
+        LineEnding::Lf
+    };
+    let (first, _, _) = endings
+        .into_iter()
+        .find(|(_, _, ending)| *ending != dominant)?;
+    Some((dominant, first))
+}
+
+/// Edits replacing every line ending of `src` that isn't `target` with it.
+pub fn normalize_line_endings(src: &str, target: LineEnding) -> Vec<TextEdit> {
+    line_endings(src)
+        .filter(|(_, _, ending)| *ending != target)
+        .map(|(line, len, _)| {
+            let range = Range::new(
+                Position::new(line as u32, len as u32),
+                Position::new(line as u32 + 1, 0),
+            );
+            TextEdit::new(range, target.as_str().to_string())
+        })
+        .collect()
+}
+
+/// The line, the length in chars of its content and its ending, for every line of `src` ending
+/// with a line break. A lone `\r` doesn't end a line.
+fn line_endings(src: &str) -> impl Iterator<Item = (usize, usize, LineEnding)> + '_ {
+    src.split_inclusive('\n')
+        .enumerate()
+        .filter_map(|(line, text)| {
+            let content = text.strip_suffix('\n')?;
+            Some(match content.strip_suffix('\r') {
+                Some(content) => (line, content.chars().count(), LineEnding::Crlf),
+                None => (line, content.chars().count(), LineEnding::Lf),
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_dominant_ending_wins() {
+        assert_eq!(
+            mixed_line_endings("a\r\nb\nc\r\nd"),
+            Some((LineEnding::Crlf, 1))
+        );
+        assert_eq!(mixed_line_endings("a\r\nb\nc\n"), Some((LineEnding::Lf, 0)));
+        // A tie goes to `\n`
+        assert_eq!(mixed_line_endings("a\nb\r\n"), Some((LineEnding::Lf, 1)));
+    }
+
+    #[test]
+    fn consistent_endings_are_not_mixed() {
+        assert_eq!(mixed_line_endings("a\nb\nc"), None);
+        assert_eq!(mixed_line_endings("a\r\nb\r\n"), None);
+        // A lone `\r` is part of the line
+        assert_eq!(mixed_line_endings("a\rb\nc\n"), None);
+    }
+
+    #[test]
+    fn edits_replace_the_other_endings() {
+        let src = "fn main() {\r\n    1\n}\r\n";
+        assert_eq!(
+            normalize_line_endings(src, LineEnding::Crlf),
+            [TextEdit::new(
+                Range::new(Position::new(1, 5), Position::new(2, 0)),
+                "\r\n".to_string()
+            )]
+        );
+        assert_eq!(
+            normalize_line_endings(src, LineEnding::Lf),
+            [
+                TextEdit::new(
+                    Range::new(Position::new(0, 11), Position::new(1, 0)),
+                    "\n".to_string()
+                ),
+                TextEdit::new(
+                    Range::new(Position::new(2, 1), Position::new(3, 0)),
+                    "\n".to_string()
+                ),
+            ]
+        );
+    }
+}
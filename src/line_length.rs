@@ -0,0 +1,81 @@
+/// A line wider than the configured maximum.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LongLine {
+    pub line: usize,
+    /// Char index in the line of the first char past the limit.
+    pub overflow_start: usize,
+    /// Char length of the line, without the line ending.
+    pub len: usize,
+    /// Display width of the line in columns.
+    pub width: usize,
+}
+
+/// Lines whose display width exceeds `max_width` columns, tabs advancing to the next multiple of
+/// `tab_size`.
+pub fn long_lines(src: &str, max_width: usize, tab_size: usize) -> Vec<LongLine> {
+    let tab_size = tab_size.max(1);
+    src.lines()
+        .enumerate()
+        .filter_map(|(line, text)| {
+            let mut width = 0;
+            let mut overflow_start = None;
+            for (index, c) in text.chars().enumerate() {
+                width = match c {
+                    '\t' => (width / tab_size + 1) * tab_size,
+                    _ => width + 1,
+                };
+                if width > max_width && overflow_start.is_none() {
+                    overflow_start = Some(index);
+                }
+            }
+            Some(LongLine {
+                line,
+                overflow_start: overflow_start?,
+                len: text.chars().count(),
+                width,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflow_starts_at_the_limit() {
+        let src = "short\n0123456789abc\r\nfits in ten\n";
+        assert_eq!(
+            long_lines(src, 10, 4),
+            [
+                LongLine {
+                    line: 1,
+                    overflow_start: 10,
+                    len: 13,
+                    width: 13,
+                },
+                LongLine {
+                    line: 2,
+                    overflow_start: 10,
+                    len: 11,
+                    width: 11,
+                },
+            ]
+        );
+        assert!(long_lines("0123456789\n", 10, 4).is_empty());
+    }
+
+    #[test]
+    fn tabs_expand_to_the_next_tab_stop() {
+        assert_eq!(
+            long_lines("\t\tab", 8, 4),
+            [LongLine {
+                line: 0,
+                overflow_start: 2,
+                len: 4,
+                width: 10,
+            }]
+        );
+        assert!(long_lines(" \tab", 8, 4).is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+
+/// Recency order of cached documents, least recently used first.
+#[derive(Debug, Default)]
+pub struct LruKeys {
+    keys: VecDeque<String>,
+}
+
+impl LruKeys {
+    /// Marks `key` as the most recently used.
+    pub fn touch(&mut self, key: &str) {
+        self.remove(key);
+        self.keys.push_back(key.to_string());
+    }
+
+    /// How many keys were used more recently than `key`, `None` when it isn't tracked.
+    pub fn recency(&self, key: &str) -> Option<usize> {
+        self.keys.iter().rev().position(|k| k == key)
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.keys.retain(|k| k != key);
+    }
+
+    /// Drops least recently used keys accepted by `evictable` until at most `capacity` keys are
+    /// left, returning the dropped ones. Keys that aren't evictable are kept regardless.
+    pub fn evict(&mut self, capacity: usize, evictable: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut evicted = vec![];
+        let mut index = 0;
+        while self.keys.len() > capacity && index < self.keys.len() {
+            if evictable(&self.keys[index]) {
+                evicted.extend(self.keys.remove(index));
+            } else {
+                index += 1;
+            }
+        }
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(lru: &LruKeys) -> Vec<&str> {
+        lru.keys.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn touching_moves_keys_to_the_back() {
+        let mut lru = LruKeys::default();
+        for key in ["a", "b", "c", "a"] {
+            lru.touch(key);
+        }
+        assert_eq!(keys(&lru), ["b", "c", "a"]);
+        assert_eq!(lru.recency("a"), Some(0));
+        assert_eq!(lru.recency("b"), Some(2));
+        assert_eq!(lru.recency("d"), None);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let mut lru = LruKeys::default();
+        for key in ["a", "b", "c", "d"] {
+            lru.touch(key);
+        }
+        assert_eq!(lru.evict(2, |_| true), ["a", "b"]);
+        assert_eq!(keys(&lru), ["c", "d"]);
+        assert!(lru.evict(2, |_| true).is_empty());
+    }
+
+    #[test]
+    fn keeps_keys_that_are_not_evictable() {
+        let mut lru = LruKeys::default();
+        for key in ["open", "a", "b"] {
+            lru.touch(key);
+        }
+        assert_eq!(lru.evict(1, |key| key != "open"), ["a", "b"]);
+        assert_eq!(keys(&lru), ["open"]);
+        lru.touch("c");
+        assert_eq!(lru.evict(0, |key| key != "open"), ["c"]);
+        assert_eq!(keys(&lru), ["open"]);
+    }
+}
@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// Name of the manifest looked for at the root of each workspace folder.
+pub const MANIFEST_FILE: &str = "gen.toml";
+
+/// The `gen.toml` at a workspace root, scoping the files of the workspace the server reads from
+/// disk and watches. A root without one is scoped to all of it.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ProjectManifest {
+    #[serde(skip)]
+    pub root: PathBuf,
+    /// Directories holding the sources, relative to the root. None means the whole root.
+    pub src: Vec<PathBuf>,
+    /// Directories or files left out of the sources, relative to the root.
+    pub exclude: Vec<PathBuf>,
+}
+
+impl ProjectManifest {
+    pub fn parse(root: &Path, text: &str) -> Result<ProjectManifest, String> {
+        let manifest: ProjectManifest = toml::from_str(text).map_err(|err| err.to_string())?;
+        Ok(ProjectManifest {
+            root: root.to_path_buf(),
+            ..manifest
+        })
+    }
+
+    /// The manifest at `root`, or one scoping all of it when there is none.
+    pub async fn load(root: &Path) -> Result<ProjectManifest, String> {
+        match tokio::fs::read_to_string(root.join(MANIFEST_FILE)).await {
+            Ok(text) => ProjectManifest::parse(root, &text)
+                .map_err(|err| format!("invalid {}: {}", root.join(MANIFEST_FILE).display(), err)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(ProjectManifest {
+                root: root.to_path_buf(),
+                ..Default::default()
+            }),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    /// Whether `path` is under the root of the manifest.
+    pub fn covers(&self, path: &Path) -> bool {
+        path.starts_with(&self.root)
+    }
+
+    /// Whether `path` is in one of the source directories and out of the excludes.
+    pub fn includes(&self, path: &Path) -> bool {
+        self.source_dirs().any(|dir| path.starts_with(dir))
+            && !self
+                .exclude
+                .iter()
+                .any(|exclude| path.starts_with(self.root.join(exclude)))
+    }
+
+    /// Globs matching the files of `file_glob` in the source directories, which still match
+    /// excluded ones.
+    pub fn globs(&self, file_glob: &str) -> Vec<String> {
+        self.source_dirs()
+            .map(|dir| format!("{}/{}", dir.display(), file_glob))
+            .collect()
+    }
+
+    fn source_dirs(&self) -> Box<dyn Iterator<Item = PathBuf> + '_> {
+        if self.src.is_empty() {
+            Box::new(std::iter::once(self.root.clone()))
+        } else {
+            Box::new(self.src.iter().map(|dir| self.root.join(dir)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources_and_excludes_scope_the_root() {
+        let manifest = ProjectManifest::parse(
+            Path::new("/project"),
+            "src = [\"lib\", \"bin\"]\nexclude = [\"lib/generated\"]\n",
+        )
+        .unwrap();
+        assert!(manifest.covers(Path::new("/project/test/main.nrs")));
+        assert!(!manifest.covers(Path::new("/other/main.nrs")));
+        assert!(manifest.includes(Path::new("/project/lib/main.nrs")));
+        assert!(manifest.includes(Path::new("/project/bin/main.nrs")));
+        assert!(!manifest.includes(Path::new("/project/test/main.nrs")));
+        assert!(!manifest.includes(Path::new("/project/lib/generated/main.nrs")));
+        assert_eq!(
+            manifest.globs("**/*.nrs"),
+            ["/project/lib/**/*.nrs", "/project/bin/**/*.nrs"]
+        );
+    }
+
+    #[test]
+    fn an_empty_manifest_includes_the_whole_root() {
+        let manifest = ProjectManifest::parse(Path::new("/project"), "").unwrap();
+        assert!(manifest.includes(Path::new("/project/test/main.nrs")));
+        assert_eq!(manifest.globs("**/*.nrs"), ["/project/**/*.nrs"]);
+    }
+
+    #[test]
+    fn malformed_manifests_fail_to_parse() {
+        assert!(ProjectManifest::parse(Path::new("/project"), "src = \"lib\"").is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use chumsky::Parser;
+
+use crate::chumsky::{lexer, skip_padding, Token};
+
+const BRACE_PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
+/// Returns the char offset of the bracket matching the one at `offset`, or `None` when
+/// `offset` isn't on a bracket or the bracket is unbalanced. Works on lexer tokens, so brackets
+/// inside strings and comments are never considered.
+pub fn match_brace(src: &str, offset: usize) -> Option<usize> {
+    let (tokens, _) = lexer().parse_recovery(src);
+    let chars = src.chars().collect::<Vec<_>>();
+    let brackets = tokens?
+        .into_iter()
+        .filter_map(|(token, span)| match token {
+            Token::Ctrl(c) if BRACE_PAIRS.iter().any(|(l, r)| *l == c || *r == c) => {
+                Some((c, skip_padding(&chars, span.start)))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let index = brackets.iter().position(|(_, start)| *start == offset)?;
+    let (c, _) = brackets[index];
+
+    if let Some((open, close)) = BRACE_PAIRS.iter().find(|(l, _)| *l == c) {
+        let mut depth = 0;
+        for (token, start) in &brackets[index..] {
+            if token == open {
+                depth += 1;
+            } else if token == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(*start);
+                }
+            }
+        }
+    } else if let Some((open, close)) = BRACE_PAIRS.iter().find(|(_, r)| *r == c) {
+        let mut depth = 0;
+        for (token, start) in brackets[..=index].iter().rev() {
+            if token == close {
+                depth += 1;
+            } else if token == open {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(*start);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The innermost bracket opened before `offset` that nothing in the document closes, as its
+/// char offset along with the bracket closing it. A closing bracket closes the innermost
+/// bracket of its kind, so a stray `}` doesn't hide the `(` left open inside its block.
+pub fn unclosed_bracket(src: &str, offset: usize) -> Option<(usize, char)> {
+    let (tokens, _) = lexer().parse_recovery(src);
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut open: Vec<(usize, char)> = vec![];
+    for (token, span) in tokens? {
+        let c = match token {
+            Token::Ctrl(c) => c,
+            _ => continue,
+        };
+        if let Some((_, close)) = BRACE_PAIRS.iter().find(|(l, _)| *l == c) {
+            open.push((skip_padding(&chars, span.start), *close));
+        } else if let Some(index) = open.iter().rposition(|(_, close)| *close == c) {
+            open.remove(index);
+        }
+    }
+    open.into_iter().rev().find(|(start, _)| *start < offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The char offset of the `n`th occurrence of `c` in `src`, counted from 0.
+    fn nth(src: &str, c: char, n: usize) -> usize {
+        src.chars()
+            .enumerate()
+            .filter(|(_, found)| *found == c)
+            .nth(n)
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn matches_in_both_directions() {
+        let src = "fn main() {\n    1\n}\n";
+        assert_eq!(match_brace(src, nth(src, '{', 0)), Some(nth(src, '}', 0)));
+        assert_eq!(match_brace(src, nth(src, '}', 0)), Some(nth(src, '{', 0)));
+        assert_eq!(match_brace(src, nth(src, '(', 0)), Some(nth(src, ')', 0)));
+    }
+
+    #[test]
+    fn matches_nested_brackets() {
+        let src = "fn main() { if a { [b(1)] } else { c } }";
+        assert_eq!(match_brace(src, nth(src, '{', 0)), Some(nth(src, '}', 2)));
+        assert_eq!(match_brace(src, nth(src, '{', 1)), Some(nth(src, '}', 0)));
+        assert_eq!(match_brace(src, nth(src, '[', 0)), Some(nth(src, ']', 0)));
+        assert_eq!(match_brace(src, nth(src, ')', 1)), Some(nth(src, '(', 1)));
+    }
+
+    #[test]
+    fn unbalanced_brackets_have_no_match() {
+        let src = "fn main() { if a { b }";
+        assert_eq!(match_brace(src, nth(src, '{', 0)), None);
+        assert_eq!(match_brace(src, nth(src, '{', 1)), Some(nth(src, '}', 0)));
+        let src = "fn main() { 1 } }";
+        assert_eq!(match_brace(src, nth(src, '}', 1)), None);
+    }
+
+    #[test]
+    fn brackets_in_strings_and_comments_are_ignored() {
+        let src = "fn main() { \"}\" }\n";
+        assert_eq!(match_brace(src, nth(src, '{', 0)), Some(nth(src, '}', 1)));
+        assert_eq!(match_brace(src, nth(src, '}', 0)), None);
+        let src = "fn main() {\n    // }\n    1\n}\n";
+        assert_eq!(match_brace(src, nth(src, '{', 0)), Some(nth(src, '}', 1)));
+        assert_eq!(match_brace(src, nth(src, '}', 0)), None);
+    }
+
+    #[test]
+    fn finds_brackets_following_a_comment() {
+        // The span of the first token starts with the comments before it
+        let src = "// the list\n[1, (2)]\n";
+        assert_eq!(match_brace(src, nth(src, '[', 0)), Some(nth(src, ']', 0)));
+        assert_eq!(match_brace(src, nth(src, ']', 0)), Some(nth(src, '[', 0)));
+        assert_eq!(match_brace(src, 0), None);
+        let src = "fn main() {\n    // the result\n    [1]\n}\n";
+        assert_eq!(match_brace(src, nth(src, '[', 0)), Some(nth(src, ']', 0)));
+        assert_eq!(match_brace(src, nth(src, '{', 0)), Some(nth(src, '}', 0)));
+    }
+
+    #[test]
+    fn no_match_off_a_bracket() {
+        let src = "fn main() { 1 }";
+        assert_eq!(match_brace(src, 0), None);
+        assert_eq!(match_brace(src, nth(src, '1', 0)), None);
+        assert_eq!(match_brace(src, src.len()), None);
+    }
+
+    #[test]
+    fn finds_the_bracket_left_open() {
+        let src = "fn main() {\n    add(1, ";
+        assert_eq!(
+            unclosed_bracket(src, src.len()),
+            Some((nth(src, '(', 1), ')'))
+        );
+        let src = "fn main() {\n    // (\n    1\n";
+        assert_eq!(
+            unclosed_bracket(src, src.len()),
+            Some((nth(src, '{', 0), '}'))
+        );
+    }
+
+    #[test]
+    fn innermost_unclosed_bracket_before_the_offset() {
+        let src = "fn main() {\n    if a {\n        f(1\n";
+        assert_eq!(
+            unclosed_bracket(src, src.len()),
+            Some((nth(src, '(', 1), ')'))
+        );
+        assert_eq!(
+            unclosed_bracket(src, nth(src, '(', 1)),
+            Some((nth(src, '{', 1), '}'))
+        );
+        assert_eq!(unclosed_bracket("fn main() { 1 }", 14), None);
+    }
+
+    #[test]
+    fn stray_closers_only_close_their_kind() {
+        let src = "fn main() {\n    f(1 }\n";
+        assert_eq!(
+            unclosed_bracket(src, src.len()),
+            Some((nth(src, '(', 1), ')'))
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use crate::chumsky::{Expr, Func, Span, Spanned};
+
+/// Index of a node in a [`NodeIndex`].
+pub type NodeId = usize;
+
+#[derive(Debug)]
+struct Node {
+    span: Span,
+    parent: Option<NodeId>,
+    /// Sorted by start, the children of a node never overlapping each other.
+    children: Vec<NodeId>,
+}
+
+/// The spans of the nodes of an AST, nested like the nodes themselves: functions, their names
+/// and parameters, expressions, `let` names and call argument lists. Finding the node under an
+/// offset takes a binary search per level instead of a walk of the whole AST.
+#[derive(Debug, Default)]
+pub struct NodeIndex {
+    nodes: Vec<Node>,
+    /// The functions, sorted by start.
+    roots: Vec<NodeId>,
+}
+
+impl NodeIndex {
+    pub fn new(ast: &HashMap<String, Func>) -> Self {
+        let mut index = NodeIndex::default();
+        index.roots = ast
+            .values()
+            .map(|func| {
+                let id = index.push(func.span.clone(), None);
+                index.push(func.name.1.clone(), Some(id));
+                for (_, span) in &func.args {
+                    index.push(span.clone(), Some(id));
+                }
+                index.push_expr(&func.body, id);
+                id
+            })
+            .collect();
+        index.sort_children();
+        index
+    }
+
+    /// The innermost node containing `offset`.
+    pub fn node_at(&self, offset: usize) -> Option<NodeId> {
+        let mut node = self.child_at(&self.roots, offset)?;
+        while let Some(child) = self.child_at(&self.nodes[node].children, offset) {
+            node = child;
+        }
+        Some(node)
+    }
+
+    pub fn span(&self, id: NodeId) -> &Span {
+        &self.nodes[id].span
+    }
+
+    /// `id` and the nodes containing it, innermost first.
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        std::iter::successors(Some(id), |id| self.nodes[*id].parent)
+    }
+
+    /// The node of `siblings` containing `offset`. Siblings don't overlap, so it can only be
+    /// the last one starting at or before `offset`.
+    fn child_at(&self, siblings: &[NodeId], offset: usize) -> Option<NodeId> {
+        let after = siblings.partition_point(|id| self.nodes[*id].span.start <= offset);
+        let candidate = *siblings.get(after.checked_sub(1)?)?;
+        let span = &self.nodes[candidate].span;
+        (span.start <= offset && offset < span.end).then_some(candidate)
+    }
+
+    fn push(&mut self, span: Span, parent: Option<NodeId>) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            span,
+            parent,
+            children: vec![],
+        });
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(id);
+        }
+        id
+    }
+
+    fn push_expr(&mut self, expr: &Spanned<Expr>, parent: NodeId) {
+        let id = self.push(expr.1.clone(), Some(parent));
+        match &expr.0 {
+            Expr::Error | Expr::Value(_) | Expr::Local(_) => {}
+            Expr::List(exprs) => exprs.iter().for_each(|expr| self.push_expr(expr, id)),
+            Expr::Let(_, lhs, rest, name_span) => {
+                self.push(name_span.clone(), Some(id));
+                self.push_expr(lhs, id);
+                self.push_expr(rest, id);
+            }
+            Expr::Then(first, second) | Expr::Binary(first, _, second) => {
+                self.push_expr(first, id);
+                self.push_expr(second, id);
+            }
+            Expr::Call(callee, args) => {
+                self.push_expr(callee, id);
+                let args_id = self.push(args.1.clone(), Some(id));
+                args.0.iter().for_each(|arg| self.push_expr(arg, args_id));
+            }
+            Expr::If(test, consequent, alternative) => {
+                self.push_expr(test, id);
+                self.push_expr(consequent, id);
+                self.push_expr(alternative, id);
+            }
+            Expr::Print(expr) => self.push_expr(expr, id),
+        }
+    }
+
+    fn sort_children(&mut self) {
+        let starts = self
+            .nodes
+            .iter()
+            .map(|node| node.span.start)
+            .collect::<Vec<_>>();
+        self.roots.sort_by_key(|id| starts[*id]);
+        for node in self.nodes.iter_mut() {
+            node.children.sort_by_key(|id| starts[*id]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parse;
+
+    const SRC: &str = "fn add(a, b) {\n    a + b\n}\nfn main() {\n    let x = add(1, 2 * 3);\n    if x == 7 { print(x) } else { x }\n}\n";
+
+    fn index() -> NodeIndex {
+        NodeIndex::new(&parse(SRC).0.unwrap())
+    }
+
+    fn text(index: &NodeIndex, id: NodeId) -> &'static str {
+        &SRC[index.span(id).clone()]
+    }
+
+    #[test]
+    fn innermost_node_and_its_ancestors() {
+        let index = index();
+        let node = index.node_at(SRC.find("3)").unwrap()).unwrap();
+        let ancestors = index
+            .ancestors(node)
+            .map(|id| text(&index, id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ancestors[..4],
+            ["3", "2 * 3", "(1, 2 * 3)", "add(1, 2 * 3)"]
+        );
+        assert!(ancestors.last().unwrap().starts_with("fn main()"));
+
+        let name = index.node_at(SRC.find("add").unwrap()).unwrap();
+        assert_eq!(text(&index, name), "add");
+        let let_name = index.node_at(SRC.find("x =").unwrap()).unwrap();
+        assert_eq!(text(&index, let_name), "x");
+    }
+
+    #[test]
+    fn nothing_between_functions() {
+        let index = index();
+        assert_eq!(index.node_at(SRC.find("\nfn main").unwrap()), None);
+        assert_eq!(index.node_at(SRC.len()), None);
+    }
+
+    #[test]
+    fn matches_a_linear_scan_at_every_offset() {
+        let index = index();
+        for offset in 0..SRC.len() {
+            let innermost = index
+                .nodes
+                .iter()
+                .map(|node| &node.span)
+                .filter(|span| span.start <= offset && offset < span.end)
+                .min_by_key(|span| span.len());
+            assert_eq!(
+                index.node_at(offset).map(|id| index.span(id)),
+                innermost,
+                "at {}",
+                offset
+            );
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tower_lsp::lsp_types::notification::Progress;
+use tower_lsp::lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, ProgressToken, WorkDoneProgress,
+    WorkDoneProgressBegin, WorkDoneProgressEnd, WorkDoneProgressReport,
+};
+
+use crate::client::LspClient;
+
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+/// Work-done progress created on the client with `window/workDoneProgress/create`. Dropping it
+/// without calling [`end`](ProgressGuard::end), on an early return or a panic, still sends the
+/// `end` notification, so the editor never keeps showing a progress that stopped.
+pub struct ProgressGuard<C: LspClient> {
+    client: C,
+    token: ProgressToken,
+    ended: bool,
+}
+
+impl<C: LspClient> ProgressGuard<C> {
+    /// Creates a token and begins the progress, or returns `None` when the client refused the
+    /// token.
+    pub async fn begin(client: C, title: &str) -> Option<Self> {
+        let token = NumberOrString::String(format!(
+            "gen/progress/{}",
+            NEXT_TOKEN.fetch_add(1, Ordering::Relaxed)
+        ));
+        client.create_work_done_progress(token.clone()).await.ok()?;
+        let guard = ProgressGuard {
+            client,
+            token,
+            ended: false,
+        };
+        guard
+            .send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_string(),
+                cancellable: Some(false),
+                message: None,
+                percentage: Some(0),
+            }))
+            .await;
+        Some(guard)
+    }
+
+    pub async fn report(&self, message: String, percentage: u32) {
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: Some(message),
+            percentage: Some(percentage),
+        }))
+        .await;
+    }
+
+    pub async fn end(mut self, message: Option<String>) {
+        self.ended = true;
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd { message }))
+            .await;
+    }
+
+    async fn send(&self, progress: WorkDoneProgress) {
+        self.client
+            .send_notification::<Progress>(ProgressParams {
+                token: self.token.clone(),
+                value: ProgressParamsValue::WorkDone(progress),
+            })
+            .await;
+    }
+}
+
+impl<C: LspClient> Drop for ProgressGuard<C> {
+    fn drop(&mut self) {
+        if self.ended {
+            return;
+        }
+        // Notifications are sent asynchronously, which `drop` can't wait for
+        let handle = match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle,
+            Err(_) => return,
+        };
+        let client = self.client.clone();
+        let params = ProgressParams {
+            token: self.token.clone(),
+            value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(WorkDoneProgressEnd {
+                message: None,
+            })),
+        };
+        handle.spawn(async move { client.send_notification::<Progress>(params).await });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::test_util::MockClient;
+
+    /// The `kind` of every progress notification sent for `token`.
+    fn kinds(client: &MockClient, token: &ProgressToken) -> Vec<String> {
+        client
+            .notifications("$/progress")
+            .into_iter()
+            .filter(|progress| progress["token"] == serde_json::to_value(token).unwrap())
+            .map(|progress| match &progress["value"]["kind"] {
+                Value::String(kind) => kind.clone(),
+                kind => panic!("unexpected kind {}", kind),
+            })
+            .collect()
+    }
+
+    /// Lets the end notification spawned by a drop get sent.
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn begins_reports_and_ends() {
+        let client = MockClient::default();
+        let progress = ProgressGuard::begin(client.clone(), "Working")
+            .await
+            .unwrap();
+        progress.report("halfway".to_string(), 50).await;
+        progress.end(Some("done".to_string())).await;
+        settle().await;
+        let token = &client.progress_tokens()[0];
+        assert_eq!(kinds(&client, token), ["begin", "report", "end"]);
+    }
+
+    #[tokio::test]
+    async fn errored_runs_still_end() {
+        async fn index(client: MockClient) -> std::result::Result<(), String> {
+            let progress = ProgressGuard::begin(client, "Indexing").await.unwrap();
+            progress.report("first".to_string(), 0).await;
+            Err("unreadable file".to_string())?;
+            progress.end(None).await;
+            Ok(())
+        }
+
+        let client = MockClient::default();
+        assert!(index(client.clone()).await.is_err());
+        settle().await;
+        let token = &client.progress_tokens()[0];
+        assert_eq!(kinds(&client, token), ["begin", "report", "end"]);
+    }
+
+    #[tokio::test]
+    async fn panicking_runs_still_end() {
+        let client = MockClient::default();
+        let task = tokio::spawn({
+            let client = client.clone();
+            async move {
+                let _progress = ProgressGuard::begin(client, "Indexing").await;
+                panic!("the indexer crashed");
+            }
+        });
+        assert!(task.await.unwrap_err().is_panic());
+        settle().await;
+        let token = &client.progress_tokens()[0];
+        assert_eq!(kinds(&client, token), ["begin", "end"]);
+    }
+}
@@ -3,7 +3,6 @@ use std::collections::HashMap;
 use chumsky::Span;
 use im_rc::Vector;
 
-
 use crate::chumsky::{Expr, Func, Spanned};
 #[derive(Debug, Clone)]
 pub enum ReferenceSymbol {
@@ -24,7 +23,7 @@ pub fn get_reference(
     //     }
     // }
     let mut kv_list = ast.iter().collect::<Vec<_>>();
-    kv_list.sort_by(|a, b| a.1.name.start().cmp(&b.1.name.start()));
+    kv_list.sort_by_key(|a| a.1.name.start());
     let mut reference_symbol = ReferenceSymbol::Founding(ident_offset);
     // let mut fn_vector = Vector::new();
     for (_, v) in kv_list {
@@ -60,6 +59,13 @@ pub fn get_reference(
     reference_list
 }
 
+/// Name of the function containing `offset`, used to group references by where they occur.
+pub fn get_container(ast: &HashMap<String, Func>, offset: usize) -> Option<String> {
+    ast.values()
+        .find(|v| v.span.start <= offset && offset < v.span.end)
+        .map(|v| v.name.0.clone())
+}
+
 pub fn get_reference_of_expr(
     expr: &Spanned<Expr>,
     definition_ass_list: Vector<Spanned<String>>,
@@ -0,0 +1,16 @@
+use crate::chumsky::Span;
+use crate::node_index::NodeIndex;
+
+/// Spans of every node enclosing `offset`, from the enclosing function down to the innermost node.
+pub fn get_selection_spans(index: &NodeIndex, offset: usize) -> Vec<Span> {
+    let mut spans = match index.node_at(offset) {
+        Some(node) => index
+            .ancestors(node)
+            .map(|id| index.span(id).clone())
+            .collect::<Vec<_>>(),
+        None => return vec![],
+    };
+    spans.reverse();
+    spans.dedup();
+    spans
+}
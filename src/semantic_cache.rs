@@ -0,0 +1,163 @@
+use std::collections::{HashMap, HashSet};
+
+use im_rc::Vector;
+
+use crate::chumsky::{Func, Span};
+use crate::shadowing::{get_shadowed_bindings_of_func, ShadowedBinding};
+use crate::unresolved_symbol::{get_unresolved_symbols_of_func, UnresolvedSymbol};
+
+/// What the semantic passes found in a function, with spans relative to its start.
+#[derive(Debug)]
+struct FunctionAnalysis {
+    text: String,
+    unresolved: Vec<UnresolvedSymbol>,
+    shadowed: Vec<ShadowedBinding>,
+}
+
+/// The unresolved symbols and shadowed bindings of the functions of a document. The passes only
+/// look at a function and the names of the others, so as long as the document defines the same
+/// functions, a function whose text didn't change keeps its results: they are moved to where it
+/// now starts rather than computed again.
+#[derive(Debug, Default)]
+pub struct SemanticCache {
+    /// Names of the functions the analyses were made in a document defining, sorted.
+    functions: Vec<String>,
+    analyses: HashMap<String, FunctionAnalysis>,
+}
+
+impl SemanticCache {
+    /// The unresolved symbols and the shadowed bindings of `ast`, parsed from `chars`, each
+    /// sorted by start.
+    pub fn analyze(
+        &mut self,
+        ast: &HashMap<String, Func>,
+        chars: &[char],
+    ) -> (Vec<UnresolvedSymbol>, Vec<ShadowedBinding>) {
+        let mut functions = ast.keys().cloned().collect::<Vec<_>>();
+        functions.sort();
+        if functions != self.functions {
+            self.analyses.clear();
+            self.functions = functions;
+        }
+        let function_list = self.functions.iter().cloned().collect::<Vector<_>>();
+        let function_set = self.functions.iter().cloned().collect::<HashSet<_>>();
+        let mut unresolved = vec![];
+        let mut shadowed = vec![];
+        for (name, func) in ast {
+            let start = func.span.start;
+            let text = chars
+                .get(func.span.clone())
+                .unwrap_or_default()
+                .iter()
+                .collect::<String>();
+            if self
+                .analyses
+                .get(name)
+                .is_none_or(|analysis| analysis.text != text)
+            {
+                let analysis = FunctionAnalysis {
+                    text,
+                    unresolved: get_unresolved_symbols_of_func(func, &function_list)
+                        .into_iter()
+                        .map(|mut symbol| {
+                            symbol.name.1 = relative(&symbol.name.1, start);
+                            symbol
+                        })
+                        .collect(),
+                    shadowed: get_shadowed_bindings_of_func(func, &function_set)
+                        .into_iter()
+                        .map(|mut binding| {
+                            binding.name.1 = relative(&binding.name.1, start);
+                            binding.previous = relative(&binding.previous, start);
+                            binding
+                        })
+                        .collect(),
+                };
+                self.analyses.insert(name.clone(), analysis);
+            }
+            let analysis = &self.analyses[name];
+            unresolved.extend(analysis.unresolved.iter().cloned().map(|mut symbol| {
+                symbol.name.1 = absolute(&symbol.name.1, start);
+                symbol
+            }));
+            shadowed.extend(analysis.shadowed.iter().cloned().map(|mut binding| {
+                binding.name.1 = absolute(&binding.name.1, start);
+                binding.previous = absolute(&binding.previous, start);
+                binding
+            }));
+        }
+        // Functions gone from the document don't need their analyses anymore
+        self.analyses.retain(|name, _| ast.contains_key(name));
+        unresolved.sort_by_key(|symbol| symbol.name.1.start);
+        shadowed.sort_by_key(|binding| binding.name.1.start);
+        (unresolved, shadowed)
+    }
+}
+
+fn relative(span: &Span, start: usize) -> Span {
+    span.start - start..span.end - start
+}
+
+fn absolute(span: &Span, start: usize) -> Span {
+    span.start + start..span.end + start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parse;
+
+    fn analyze(cache: &mut SemanticCache, src: &str) -> (Vec<String>, Vec<String>) {
+        let ast = parse(src).0.unwrap();
+        let chars = src.chars().collect::<Vec<_>>();
+        let (unresolved, shadowed) = cache.analyze(&ast, &chars);
+        let text = |span: &Span| chars[span.clone()].iter().collect::<String>();
+        (
+            unresolved
+                .iter()
+                .map(|symbol| format!("{}@{}", symbol.name.0, text(&symbol.name.1)))
+                .collect(),
+            shadowed
+                .iter()
+                .map(|binding| format!("{}<{}", text(&binding.name.1), text(&binding.previous)))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn unchanged_functions_keep_their_analysis() {
+        let mut cache = SemanticCache::default();
+        let src = "fn a() {\n    x\n}\nfn b(y) {\n    let y = z;\n    y\n}\n";
+        assert_eq!(
+            analyze(&mut cache, src),
+            (
+                vec!["x@x".to_string(), "z@z".to_string()],
+                vec!["y<y".to_string()]
+            )
+        );
+        // Dropped from the analysis of `b`, which an edit of `a` doesn't redo
+        cache.analyses.get_mut("b").unwrap().unresolved.clear();
+        let src = "fn a() {\n    let w = 1;\n    w\n}\nfn b(y) {\n    let y = z;\n    y\n}\n";
+        assert_eq!(analyze(&mut cache, src), (vec![], vec!["y<y".to_string()]));
+
+        // Editing `b` does
+        let src = "fn a() {\n    let w = 1;\n    w\n}\nfn b(y) {\n    let y = v;\n    y\n}\n";
+        assert_eq!(
+            analyze(&mut cache, src),
+            (vec!["v@v".to_string()], vec!["y<y".to_string()])
+        );
+    }
+
+    #[test]
+    fn functions_are_analyzed_again_when_others_come_or_go() {
+        let mut cache = SemanticCache::default();
+        let src = "fn a() {\n    c()\n}\n";
+        assert_eq!(analyze(&mut cache, src).0, ["c@c"]);
+        // `c` now resolves to a function though `a` is unchanged
+        let src = "fn a() {\n    c()\n}\nfn c() {\n    1\n}\n";
+        assert_eq!(analyze(&mut cache, src).0, Vec::<String>::new());
+        let src = "fn a() {\n    c()\n}\n";
+        assert_eq!(analyze(&mut cache, src).0, ["c@c"]);
+        assert_eq!(cache.analyses.len(), 1);
+    }
+}
@@ -1,8 +1,14 @@
 use std::collections::HashMap;
 
-use tower_lsp::lsp_types::{SemanticTokenType};
+use tower_lsp::lsp_types::{
+    SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensEdit,
+};
 
 use crate::chumsky::{Expr, Func, ImCompleteSemanticToken, Spanned};
+use crate::visitor::{walk_expr, Visitor};
+
+/// Escape sequences inside string literals, like `\n` or `\u{1F600}`.
+pub const ESCAPE_SEQUENCE: SemanticTokenType = SemanticTokenType::new("escapeSequence");
 
 pub const LEGEND_TYPE: &[SemanticTokenType] = &[
     SemanticTokenType::FUNCTION,
@@ -13,6 +19,7 @@ pub const LEGEND_TYPE: &[SemanticTokenType] = &[
     SemanticTokenType::KEYWORD,
     SemanticTokenType::OPERATOR,
     SemanticTokenType::PARAMETER,
+    ESCAPE_SEQUENCE,
 ];
 
 pub fn semantic_token_from_ast(ast: &HashMap<String, Func>) -> Vec<ImCompleteSemanticToken> {
@@ -27,6 +34,7 @@ pub fn semantic_token_from_ast(ast: &HashMap<String, Func>) -> Vec<ImCompleteSem
                     .iter()
                     .position(|item| item == &SemanticTokenType::PARAMETER)
                     .unwrap(),
+                token_modifiers: 0,
             });
         });
         let (_, span) = &function.name;
@@ -37,6 +45,7 @@ pub fn semantic_token_from_ast(ast: &HashMap<String, Func>) -> Vec<ImCompleteSem
                 .iter()
                 .position(|item| item == &SemanticTokenType::FUNCTION)
                 .unwrap(),
+            token_modifiers: 0,
         });
         semantic_token_from_expr(&function.body, &mut semantic_tokens);
     });
@@ -44,57 +53,355 @@ pub fn semantic_token_from_ast(ast: &HashMap<String, Func>) -> Vec<ImCompleteSem
     semantic_tokens
 }
 
-pub fn semantic_token_from_expr(
-    expr: &Spanned<Expr>,
-    semantic_tokens: &mut Vec<ImCompleteSemanticToken>,
-) {
-    match &expr.0 {
-        Expr::Error => {}
-        Expr::Value(_) => {}
-        Expr::List(_) => {}
-        Expr::Local((_name, span)) => {
-            semantic_tokens.push(ImCompleteSemanticToken {
-                start: span.start,
-                length: span.len(),
-                token_type: LEGEND_TYPE
+/// Tags like `TODO` inside comments, which editors can emphasize.
+pub const TAG: SemanticTokenModifier = SemanticTokenModifier::new("tag");
+
+pub const LEGEND_MODIFIER: &[SemanticTokenModifier] = &[TAG];
+
+/// Words marking a comment as a note to act upon.
+const COMMENT_TAGS: &[&str] = &["TODO", "FIXME", "NOTE", "HACK", "XXX"];
+
+/// `COMMENT` tokens for the `//` comments of `src`. The tags they contain get tokens of their
+/// own, carrying the [`TAG`] modifier.
+pub fn comment_semantic_tokens(src: &str) -> Vec<ImCompleteSemanticToken> {
+    let comment = LEGEND_TYPE
+        .iter()
+        .position(|item| item == &SemanticTokenType::COMMENT)
+        .unwrap();
+    let tag = 1
+        << LEGEND_MODIFIER
+            .iter()
+            .position(|item| item == &TAG)
+            .unwrap();
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut in_string = false;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            // Strings have no escaped quotes, the next quote always ends them
+            '"' => in_string = !in_string,
+            '/' if !in_string && chars.get(i + 1) == Some(&'/') => {
+                let end = chars[i..]
                     .iter()
-                    .position(|item| item == &SemanticTokenType::VARIABLE)
-                    .unwrap(),
-            });
+                    .position(|c| *c == '\n')
+                    .map_or(chars.len(), |len| i + len);
+                let mut text_start = i;
+                let mut j = i + 2;
+                while j < end {
+                    let word_len = chars[j..end]
+                        .iter()
+                        .take_while(|c| c.is_alphanumeric() || **c == '_')
+                        .count();
+                    if word_len == 0 {
+                        j += 1;
+                        continue;
+                    }
+                    let word = chars[j..j + word_len].iter().collect::<String>();
+                    if COMMENT_TAGS.contains(&word.as_str()) {
+                        if text_start < j {
+                            tokens.push(ImCompleteSemanticToken {
+                                start: text_start,
+                                length: j - text_start,
+                                token_type: comment,
+                                token_modifiers: 0,
+                            });
+                        }
+                        tokens.push(ImCompleteSemanticToken {
+                            start: j,
+                            length: word_len,
+                            token_type: comment,
+                            token_modifiers: tag,
+                        });
+                        text_start = j + word_len;
+                    }
+                    j += word_len;
+                }
+                if text_start < end {
+                    tokens.push(ImCompleteSemanticToken {
+                        start: text_start,
+                        length: end - text_start,
+                        token_type: comment,
+                        token_modifiers: 0,
+                    });
+                }
+                i = end;
+            }
+            _ => {}
         }
-        Expr::Let(_, rhs, rest, name_span) => {
-            semantic_tokens.push(ImCompleteSemanticToken {
-                start: name_span.start,
-                length: name_span.len(),
+        i += 1;
+    }
+    tokens
+}
+
+/// Tokens of the locals and `let` bindings in the expressions it visits.
+struct VariableTokens<'a> {
+    semantic_tokens: &'a mut Vec<ImCompleteSemanticToken>,
+}
+
+impl Visitor for VariableTokens<'_> {
+    fn visit_expr(&mut self, expr: &Spanned<Expr>) {
+        if let Expr::Local((_, span)) | Expr::Let(_, _, _, span) = &expr.0 {
+            self.semantic_tokens.push(ImCompleteSemanticToken {
+                start: span.start,
+                length: span.len(),
                 token_type: LEGEND_TYPE
                     .iter()
                     .position(|item| item == &SemanticTokenType::VARIABLE)
                     .unwrap(),
+                token_modifiers: 0,
             });
-            semantic_token_from_expr(rhs, semantic_tokens);
-            semantic_token_from_expr(rest, semantic_tokens);
-        }
-        Expr::Then(first, rest) => {
-            semantic_token_from_expr(first, semantic_tokens);
-            semantic_token_from_expr(rest, semantic_tokens);
         }
-        Expr::Binary(lhs, _op, rhs) => {
-            semantic_token_from_expr(lhs, semantic_tokens);
-            semantic_token_from_expr(rhs, semantic_tokens);
-        }
-        Expr::Call(expr, params) => {
-            semantic_token_from_expr(expr, semantic_tokens);
-            params.0.iter().for_each(|p| {
-                semantic_token_from_expr(p, semantic_tokens);
+        walk_expr(self, expr);
+    }
+}
+
+pub fn semantic_token_from_expr(
+    expr: &Spanned<Expr>,
+    semantic_tokens: &mut Vec<ImCompleteSemanticToken>,
+) {
+    VariableTokens { semantic_tokens }.visit_expr(expr);
+}
+
+/// Tokens of the string literal whose opening quote is at `start` and whose text is `content`:
+/// the escape sequences get tokens of their own, the rest of the literal `STRING` ones.
+pub fn string_semantic_tokens(start: usize, content: &str) -> Vec<ImCompleteSemanticToken> {
+    let token_type = |token_type: &SemanticTokenType| {
+        LEGEND_TYPE
+            .iter()
+            .position(|item| item == token_type)
+            .unwrap()
+    };
+    let chars = content.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    // Offsets are relative to the opening quote, which belongs to the first `STRING` token
+    let mut text_start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let len = match escape_len(&chars[i..]) {
+            Some(len) => len,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        if text_start < i + 1 {
+            tokens.push(ImCompleteSemanticToken {
+                start: start + text_start,
+                length: i + 1 - text_start,
+                token_type: token_type(&SemanticTokenType::STRING),
+                token_modifiers: 0,
             });
         }
-        Expr::If(test, consequent, alternative) => {
-            semantic_token_from_expr(test, semantic_tokens);
-            semantic_token_from_expr(consequent, semantic_tokens);
-            semantic_token_from_expr(alternative, semantic_tokens);
+        tokens.push(ImCompleteSemanticToken {
+            start: start + 1 + i,
+            length: len,
+            token_type: token_type(&ESCAPE_SEQUENCE),
+            token_modifiers: 0,
+        });
+        i += len;
+        text_start = i + 1;
+    }
+    tokens.push(ImCompleteSemanticToken {
+        start: start + text_start,
+        length: chars.len() + 2 - text_start,
+        token_type: token_type(&SemanticTokenType::STRING),
+        token_modifiers: 0,
+    });
+    tokens
+}
+
+/// Length of the escape sequence `chars` starts with, if it starts with one.
+fn escape_len(chars: &[char]) -> Option<usize> {
+    match chars {
+        ['\\', 'n' | 't' | 'r' | '0' | '\\' | '\'', ..] => Some(2),
+        ['\\', 'x', a, b, ..] if a.is_ascii_hexdigit() && b.is_ascii_hexdigit() => Some(4),
+        ['\\', 'u', '{', rest @ ..] => {
+            let digits = rest.iter().take_while(|c| c.is_ascii_hexdigit()).count();
+            (digits > 0 && rest.get(digits) == Some(&'}')).then_some(digits + 4)
         }
-        Expr::Print(expr) => {
-            semantic_token_from_expr(expr, semantic_tokens);
+        _ => None,
+    }
+}
+
+/// The edit turning the encoded `previous` tokens into `current`. Tokens are compared in their
+/// relative encoding, so the first token after a multi-line edit, now relative to a different
+/// token, ends up in the edit instead of being kept with a stale line offset.
+pub fn semantic_token_edits(
+    previous: &[SemanticToken],
+    current: &[SemanticToken],
+) -> Vec<SemanticTokensEdit> {
+    let prefix = previous
+        .iter()
+        .zip(current)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = previous[prefix..]
+        .iter()
+        .rev()
+        .zip(current[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let deleted = previous.len() - prefix - suffix;
+    let inserted = &current[prefix..current.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return vec![];
+    }
+    // Edits index into the flattened array, where every token takes five integers
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: (deleted * 5) as u32,
+        data: (!inserted.is_empty()).then(|| inserted.to_vec()),
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(delta_line: u32, delta_start: u32, length: u32) -> SemanticToken {
+        SemanticToken {
+            delta_line,
+            delta_start,
+            length,
+            token_type: 0,
+            token_modifiers_bitset: 0,
         }
     }
+
+    /// `(text, token type)` of the tokens of the string literal `literal`.
+    fn string_tokens(literal: &str) -> Vec<(String, SemanticTokenType)> {
+        let chars = literal.chars().collect::<Vec<_>>();
+        let content = chars[1..chars.len() - 1].iter().collect::<String>();
+        string_semantic_tokens(0, &content)
+            .into_iter()
+            .map(|token| {
+                (
+                    chars[token.start..token.start + token.length]
+                        .iter()
+                        .collect(),
+                    LEGEND_TYPE[token.token_type].clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn escapes_split_strings() {
+        let string = SemanticTokenType::STRING;
+        assert_eq!(
+            string_tokens(r#""a\nb\u{1F600}""#),
+            [
+                ("\"a".to_string(), string.clone()),
+                ("\\n".to_string(), ESCAPE_SEQUENCE),
+                ("b".to_string(), string.clone()),
+                ("\\u{1F600}".to_string(), ESCAPE_SEQUENCE),
+                ("\"".to_string(), string.clone()),
+            ]
+        );
+        assert_eq!(
+            string_tokens(r#""\t\x41""#),
+            [
+                ("\"".to_string(), string.clone()),
+                ("\\t".to_string(), ESCAPE_SEQUENCE),
+                ("\\x41".to_string(), ESCAPE_SEQUENCE),
+                ("\"".to_string(), string.clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_escapes_stay_in_the_string() {
+        assert_eq!(
+            string_tokens(r#""\q\u{}\x4""#),
+            [(r#""\q\u{}\x4""#.to_string(), SemanticTokenType::STRING)]
+        );
+    }
+
+    /// `(text, has the tag modifier)` of the comment tokens of `src`.
+    fn comment_tokens(src: &str) -> Vec<(String, bool)> {
+        let chars = src.chars().collect::<Vec<_>>();
+        comment_semantic_tokens(src)
+            .into_iter()
+            .map(|token| {
+                assert_eq!(LEGEND_TYPE[token.token_type], SemanticTokenType::COMMENT);
+                (
+                    chars[token.start..token.start + token.length]
+                        .iter()
+                        .collect(),
+                    token.token_modifiers != 0,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn comment_tags_get_their_own_token() {
+        assert_eq!(
+            comment_tokens("fn main() { 1 } // TODO: x\n"),
+            [
+                ("// ".to_string(), false),
+                ("TODO".to_string(), true),
+                (": x".to_string(), false),
+            ]
+        );
+        assert_eq!(
+            comment_tokens("//FIXME NOTE\n"),
+            [
+                ("//".to_string(), false),
+                ("FIXME".to_string(), true),
+                (" ".to_string(), false),
+                ("NOTE".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn tags_need_a_whole_word_outside_strings() {
+        assert_eq!(
+            comment_tokens("// TODOS and todo\n"),
+            [("// TODOS and todo".to_string(), false)]
+        );
+        assert!(comment_tokens("fn main() { \"// NOTE\" }\n").is_empty());
+    }
+
+    #[test]
+    fn equal_tokens_need_no_edit() {
+        let tokens = [token(0, 0, 2), token(1, 4, 3)];
+        assert!(semantic_token_edits(&tokens, &tokens).is_empty());
+    }
+
+    #[test]
+    fn edits_cover_only_the_changed_tokens() {
+        let previous = [
+            token(0, 0, 2),
+            token(1, 4, 3),
+            token(0, 4, 1),
+            token(2, 0, 1),
+        ];
+        let current = [token(0, 0, 2), token(1, 4, 5), token(2, 0, 1)];
+        assert_eq!(
+            semantic_token_edits(&previous, &current),
+            [SemanticTokensEdit {
+                start: 5,
+                delete_count: 10,
+                data: Some(vec![token(1, 4, 5)]),
+            }]
+        );
+    }
+
+    #[test]
+    fn the_token_after_deleted_lines_is_rebased() {
+        // A two-line block between the tokens was deleted
+        let previous = [token(0, 0, 2), token(3, 4, 1)];
+        let current = [token(0, 0, 2), token(1, 4, 1)];
+        assert_eq!(
+            semantic_token_edits(&previous, &current),
+            [SemanticTokensEdit {
+                start: 5,
+                delete_count: 5,
+                data: Some(vec![token(1, 4, 1)]),
+            }]
+        );
+    }
 }
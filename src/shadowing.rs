@@ -0,0 +1,198 @@
+use std::collections::{HashMap, HashSet};
+
+use im_rc::Vector;
+
+use crate::chumsky::{Expr, Func, Span, Spanned};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadowKind {
+    /// The binding hides one from an enclosing scope, which is legal.
+    Shadow,
+    /// The binding reuses a name already declared in the same scope.
+    Redeclaration,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShadowedBinding {
+    pub name: Spanned<String>,
+    pub kind: ShadowKind,
+    /// Span of the name of the binding being hidden.
+    pub previous: Span,
+    /// A name nothing in the function uses yet, to rename the binding to.
+    pub fresh_name: String,
+}
+
+/// Bindings hiding an argument or an earlier `let` of the same name. Arguments form the
+/// outermost scope, the function body is nested in it and every `if` branch opens a new scope.
+pub fn get_shadowed_bindings(ast: &HashMap<String, Func>) -> Vec<ShadowedBinding> {
+    let functions = ast.keys().cloned().collect::<HashSet<_>>();
+    let mut shadowed = ast
+        .values()
+        .flat_map(|func| get_shadowed_bindings_of_func(func, &functions))
+        .collect::<Vec<_>>();
+    shadowed.sort_by_key(|binding| binding.name.1.start);
+    shadowed
+}
+
+/// The shadowed bindings of `func`, in a document defining `functions`.
+pub fn get_shadowed_bindings_of_func(
+    func: &Func,
+    functions: &HashSet<String>,
+) -> Vec<ShadowedBinding> {
+    let mut shadowed = vec![];
+    let mut taken = functions.clone();
+    collect_names(&func.body, &mut taken);
+    let mut scope = Vector::new();
+    for (name, span) in &func.args {
+        taken.insert(name.clone());
+        check_binding(name, span, 0, &scope, &taken, &mut shadowed);
+        scope.push_front((name.clone(), span.clone(), 0));
+    }
+    get_shadowed_bindings_of_expr(&func.body, scope, 1, &taken, &mut shadowed);
+    shadowed
+}
+
+fn get_shadowed_bindings_of_expr(
+    expr: &Spanned<Expr>,
+    scope: Vector<(String, Span, usize)>,
+    depth: usize,
+    taken: &HashSet<String>,
+    shadowed: &mut Vec<ShadowedBinding>,
+) {
+    match &expr.0 {
+        Expr::Error | Expr::Value(_) | Expr::Local(_) => {}
+        Expr::Let(name, lhs, rest, span) => {
+            get_shadowed_bindings_of_expr(lhs, scope.clone(), depth, taken, shadowed);
+            check_binding(name, span, depth, &scope, taken, shadowed);
+            let scope = Vector::unit((name.clone(), span.clone(), depth)) + scope;
+            get_shadowed_bindings_of_expr(rest, scope, depth, taken, shadowed);
+        }
+        Expr::Then(first, second) | Expr::Binary(first, _, second) => {
+            get_shadowed_bindings_of_expr(first, scope.clone(), depth, taken, shadowed);
+            get_shadowed_bindings_of_expr(second, scope, depth, taken, shadowed);
+        }
+        Expr::Call(callee, args) => {
+            get_shadowed_bindings_of_expr(callee, scope.clone(), depth, taken, shadowed);
+            for expr in &args.0 {
+                get_shadowed_bindings_of_expr(expr, scope.clone(), depth, taken, shadowed);
+            }
+        }
+        Expr::If(test, consequent, alternative) => {
+            get_shadowed_bindings_of_expr(test, scope.clone(), depth, taken, shadowed);
+            get_shadowed_bindings_of_expr(consequent, scope.clone(), depth + 1, taken, shadowed);
+            get_shadowed_bindings_of_expr(alternative, scope, depth + 1, taken, shadowed);
+        }
+        Expr::Print(expr) => get_shadowed_bindings_of_expr(expr, scope, depth, taken, shadowed),
+        Expr::List(lst) => {
+            for expr in lst {
+                get_shadowed_bindings_of_expr(expr, scope.clone(), depth, taken, shadowed);
+            }
+        }
+    }
+}
+
+fn check_binding(
+    name: &str,
+    span: &Span,
+    depth: usize,
+    scope: &Vector<(String, Span, usize)>,
+    taken: &HashSet<String>,
+    shadowed: &mut Vec<ShadowedBinding>,
+) {
+    if let Some((_, previous, previous_depth)) = scope.iter().find(|(n, _, _)| n == name) {
+        shadowed.push(ShadowedBinding {
+            name: (name.to_string(), span.clone()),
+            kind: if *previous_depth == depth {
+                ShadowKind::Redeclaration
+            } else {
+                ShadowKind::Shadow
+            },
+            previous: previous.clone(),
+            fresh_name: (2..)
+                .map(|n| format!("{}{}", name, n))
+                .find(|candidate| !taken.contains(candidate))
+                .unwrap(),
+        });
+    }
+}
+
+/// Every name bound or referenced in `expr`.
+fn collect_names(expr: &Spanned<Expr>, names: &mut HashSet<String>) {
+    match &expr.0 {
+        Expr::Error | Expr::Value(_) => {}
+        Expr::Local((name, _)) => {
+            names.insert(name.clone());
+        }
+        Expr::Let(name, lhs, rest, _) => {
+            names.insert(name.clone());
+            collect_names(lhs, names);
+            collect_names(rest, names);
+        }
+        Expr::Then(first, second) | Expr::Binary(first, _, second) => {
+            collect_names(first, names);
+            collect_names(second, names);
+        }
+        Expr::Call(callee, args) => {
+            collect_names(callee, names);
+            args.0.iter().for_each(|expr| collect_names(expr, names));
+        }
+        Expr::If(test, consequent, alternative) => {
+            collect_names(test, names);
+            collect_names(consequent, names);
+            collect_names(alternative, names);
+        }
+        Expr::Print(expr) => collect_names(expr, names),
+        Expr::List(lst) => lst.iter().for_each(|expr| collect_names(expr, names)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parse;
+
+    fn shadowed(src: &str) -> Vec<(String, ShadowKind, String)> {
+        let ast = parse(src).0.unwrap();
+        get_shadowed_bindings(&ast)
+            .into_iter()
+            .map(|binding| (binding.name.0, binding.kind, binding.fresh_name))
+            .collect()
+    }
+
+    #[test]
+    fn nested_scopes_shadow() {
+        let src = "fn main(a) {\n    let a = a + 1;\n    if a == 2 {\n        let a = 3;\n        a\n    } else {\n        a\n    }\n}\n";
+        assert_eq!(
+            shadowed(src),
+            [
+                ("a".to_string(), ShadowKind::Shadow, "a2".to_string()),
+                ("a".to_string(), ShadowKind::Shadow, "a2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_scope_redeclares() {
+        let src = "fn main() {\n    let x = 1;\n    let x = 2;\n    x\n}\n";
+        let ast = parse(src).0.unwrap();
+        let bindings = get_shadowed_bindings(&ast);
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].kind, ShadowKind::Redeclaration);
+        assert_eq!(bindings[0].previous, 20..21);
+        assert_eq!(bindings[0].name.1, 35..36);
+    }
+
+    #[test]
+    fn fresh_names_are_unused() {
+        let src = "fn x2() { 0 }\nfn main(x) {\n    let x = x3();\n    x\n}\nfn x3() { 1 }\n";
+        assert_eq!(
+            shadowed(src),
+            [("x".to_string(), ShadowKind::Shadow, "x4".to_string())]
+        );
+    }
+
+    #[test]
+    fn distinct_names_are_fine() {
+        assert!(shadowed("fn main(a) {\n    let b = a;\n    b\n}\n").is_empty());
+    }
+}
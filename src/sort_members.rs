@@ -0,0 +1,134 @@
+use chumsky::Parser;
+
+use crate::chumsky::{lexer, skip_padding, Span, Token};
+
+/// A top-level function, along with the `///` doc comment above it, as whole lines.
+struct Member {
+    name: String,
+    /// Char offset of the start of the first line.
+    start: usize,
+    /// Char offset of the end of the last line, the one with the closing brace.
+    end: usize,
+}
+
+/// The top-level functions of `src` sorted by name, each moving with its doc comment while the
+/// text between them (blank lines, plain comments) stays in place. Returns the replaced span,
+/// from the first function to the end of the last one, and its new text. `None` when the
+/// functions are already sorted, or when one shares a line with other code.
+pub fn sort_functions(src: &str) -> Option<(Span, String)> {
+    let chars = src.chars().collect::<Vec<_>>();
+    let members = members(src, &chars)?;
+    let mut sorted = members.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+    if sorted.iter().zip(&members).all(|(a, b)| a.start == b.start) {
+        return None;
+    }
+    let text = |start: usize, end: usize| chars[start..end].iter().collect::<String>();
+    let mut new_text = String::new();
+    for (index, member) in sorted.iter().enumerate() {
+        new_text.push_str(&text(member.start, member.end));
+        if let Some(next) = members.get(index + 1) {
+            new_text.push_str(&text(members[index].end, next.start));
+        }
+    }
+    let span = members.first()?.start..members.last()?.end;
+    Some((span, new_text))
+}
+
+fn members(src: &str, chars: &[char]) -> Option<Vec<Member>> {
+    let (tokens, _) = lexer().parse_recovery(src);
+    let tokens = tokens?;
+    let mut members = vec![];
+    let mut depth = 0usize;
+    // The function being read: its name, the start of its `fn` and whether its body started
+    let mut current: Option<(String, usize, bool)> = None;
+    for (index, (token, span)) in tokens.iter().enumerate() {
+        match token {
+            Token::Fn if depth == 0 => {
+                if let Some((Token::Ident(name), _)) = tokens.get(index + 1) {
+                    current = Some((name.clone(), skip_padding(chars, span.start), false));
+                }
+            }
+            Token::Ctrl('{' | '(' | '[') => {
+                if let Some((_, _, body)) = current.as_mut().filter(|_| depth == 0) {
+                    *body |= *token == Token::Ctrl('{');
+                }
+                depth += 1;
+            }
+            Token::Ctrl('}' | ')' | ']') => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 && *token == Token::Ctrl('}') {
+                    if let Some((name, fn_start, true)) = current.take() {
+                        let end = skip_padding(chars, span.start) + 1;
+                        members.push(whole_lines(chars, name, fn_start, end)?);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(members)
+}
+
+/// The function from `fn_start` to `end` extended to whole lines and to its doc comment, or
+/// `None` when other code shares its first or last line. Only a comment may follow it.
+fn whole_lines(chars: &[char], name: String, fn_start: usize, end: usize) -> Option<Member> {
+    let line_start = |offset: usize| {
+        chars[..offset]
+            .iter()
+            .rposition(|c| *c == '\n')
+            .map_or(0, |newline| newline + 1)
+    };
+    let mut start = line_start(fn_start);
+    let indent_only = chars[start..fn_start].iter().all(|c| c.is_whitespace());
+    let rest_of_line = chars[end..]
+        .iter()
+        .take_while(|c| **c != '\n')
+        .collect::<String>();
+    let rest_of_line = rest_of_line.trim();
+    if !indent_only || !(rest_of_line.is_empty() || rest_of_line.starts_with("//")) {
+        return None;
+    }
+    // A comment after the closing brace moves along
+    let end = end + chars[end..].iter().take_while(|c| **c != '\n').count();
+    while start > 0 {
+        let previous = line_start(start - 1);
+        let line = chars[previous..start - 1].iter().collect::<String>();
+        if !line.trim_start().starts_with("///") {
+            break;
+        }
+        start = previous;
+    }
+    Some(Member { name, start, end })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(src: &str) -> Option<String> {
+        let (span, new_text) = sort_functions(src)?;
+        let mut chars = src.chars().collect::<Vec<_>>();
+        chars.splice(span, new_text.chars());
+        Some(chars.into_iter().collect())
+    }
+
+    #[test]
+    fn doc_comments_move_with_their_function() {
+        let src = "// header\n\n/// Charlie.\nfn c() { 3 }\n\n/// Alpha.\n/// Two lines.\nfn a() {\n    1\n} // trailing\n\n// between\nfn b() { 2 }\n";
+        assert_eq!(
+            sorted(src).unwrap(),
+            "// header\n\n/// Alpha.\n/// Two lines.\nfn a() {\n    1\n} // trailing\n\nfn b() { 2 }\n\n// between\n/// Charlie.\nfn c() { 3 }\n"
+        );
+    }
+
+    #[test]
+    fn sorted_functions_need_no_edit() {
+        assert_eq!(sorted("fn a() { 1 }\nfn b() { 2 }\n"), None);
+    }
+
+    #[test]
+    fn functions_sharing_a_line_are_not_sorted() {
+        assert_eq!(sorted("fn b() { 2 } fn a() { 1 }\n"), None);
+    }
+}
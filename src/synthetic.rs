@@ -0,0 +1,47 @@
+/// Generates a syntactically valid document of `functions` functions, each calling the previous
+/// one, for measuring how the analysis scales with document size.
+pub fn synthetic_source(functions: usize) -> String {
+    let mut src = String::new();
+    for i in 0..functions {
+        src.push_str(&format!("// helper number {}\n", i));
+        src.push_str(&format!("fn f{}(a, b) {{\n", i));
+        src.push_str("    let sum = a + b * 2;\n");
+        src.push_str("    let list = [sum, 0x1f, 3.5, \"text\"];\n");
+        if i == 0 {
+            src.push_str("    if sum == 0 { list } else { sum }\n");
+        } else {
+            src.push_str(&format!(
+                "    if sum == 0 {{ list }} else {{ f{}(sum, a) }}\n",
+                i - 1
+            ));
+        }
+        src.push_str("}\n\n");
+    }
+    src
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parse;
+    use crate::unresolved_symbol::get_unresolved_symbols;
+
+    #[test]
+    fn synthetic_source_is_valid() {
+        let src = synthetic_source(20);
+        let (ast, errors, _) = parse(&src);
+        assert!(errors.is_empty());
+        let ast = ast.unwrap();
+        assert_eq!(ast.len(), 20);
+        assert!(get_unresolved_symbols(&ast).is_empty());
+    }
+
+    #[test]
+    fn size_grows_with_the_function_count() {
+        assert!(synthetic_source(0).is_empty());
+        assert_eq!(
+            synthetic_source(10).lines().count(),
+            10 * synthetic_source(1).lines().count()
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use tokio::io::{
+    AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream, ReadHalf, WriteHalf,
+};
+use tokio::task::JoinHandle;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::notification::Notification;
+use tower_lsp::lsp_types::{
+    ApplyWorkspaceEditResponse, ConfigurationItem, Diagnostic, MessageActionItem, MessageType,
+    ProgressToken, PublishDiagnosticsParams, Registration, Url, WorkspaceEdit,
+};
+use tower_lsp::Server;
+
+use crate::backend::Backend;
+use crate::client::LspClient;
+
+/// Size of the in-memory pipes between the client and the server.
+const BUFFER_SIZE: usize = 1 << 20;
+
+/// Serves [`Backend::service`] on in-memory pipes instead of stdio, and returns the client end
+/// along with the task running the server, which ends once the client is dropped. Must be called
+/// within a tokio runtime, e.g. from a `#[tokio::test]`.
+///
+/// ```no_run
+/// use serde_json::json;
+///
+/// # async fn example() {
+/// let (mut client, _server) = nrs_language_server::test_util::serve_in_memory();
+/// client.request("initialize", json!({ "capabilities": {} })).await;
+/// client.notify("initialized", json!({})).await;
+/// client
+///     .notify(
+///         "textDocument/didOpen",
+///         json!({ "textDocument": {
+///             "uri": "file:///main.nrs",
+///             "languageId": "nrs",
+///             "version": 1,
+///             "text": "fn main() { x }",
+///         }}),
+///     )
+///     .await;
+/// let diagnostics = client.notification("textDocument/publishDiagnostics").await;
+/// assert_eq!(diagnostics["params"]["diagnostics"][0]["code"], "unresolved-symbol");
+/// # }
+/// ```
+pub fn serve_in_memory() -> (TestClient, JoinHandle<()>) {
+    let (client, server) = tokio::io::duplex(BUFFER_SIZE);
+    let (server_read, server_write) = tokio::io::split(server);
+    let (service, socket) = Backend::service();
+    let server = tokio::spawn(Server::new(server_read, server_write, socket).serve(service));
+    let (client_read, client_write) = tokio::io::split(client);
+    let client = TestClient {
+        reader: BufReader::new(client_read),
+        writer: client_write,
+        next_id: 0,
+        notifications: VecDeque::new(),
+    };
+    (client, server)
+}
+
+/// The client end of [`serve_in_memory`], speaking JSON-RPC with the server. Requests the
+/// server sends, like `workspace/configuration`, are answered with `null`.
+pub struct TestClient {
+    reader: BufReader<ReadHalf<DuplexStream>>,
+    writer: WriteHalf<DuplexStream>,
+    next_id: i64,
+    /// Notifications received while waiting for a response, not yet asked for.
+    notifications: VecDeque<Value>,
+}
+
+impl TestClient {
+    /// Sends a request and waits for its response, the whole message including `result` or
+    /// `error`.
+    pub async fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .await;
+        loop {
+            let message = self.receive().await;
+            if message.get("method").is_none() && message["id"] == id {
+                return message;
+            }
+            self.notifications.push_back(message);
+        }
+    }
+
+    pub async fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+            .await;
+    }
+
+    /// Waits for the next notification with `method`, skipping the others.
+    pub async fn notification(&mut self, method: &str) -> Value {
+        if let Some(i) = self
+            .notifications
+            .iter()
+            .position(|message| message["method"] == method)
+        {
+            return self.notifications.remove(i).unwrap();
+        }
+        loop {
+            let message = self.receive().await;
+            if message["method"] == method {
+                return message;
+            }
+        }
+    }
+
+    /// The next notification or response from the server.
+    async fn receive(&mut self) -> Value {
+        loop {
+            let message = self.read().await;
+            if message.get("method").is_some() && message.get("id").is_some() {
+                self.send(json!({ "jsonrpc": "2.0", "id": message["id"], "result": null }))
+                    .await;
+                continue;
+            }
+            return message;
+        }
+    }
+
+    async fn read(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            self.reader
+                .read_line(&mut line)
+                .await
+                .expect("the server is running");
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                length = value.parse().expect("the length is a number");
+            }
+        }
+        let mut content = vec![0; length];
+        self.reader
+            .read_exact(&mut content)
+            .await
+            .expect("the server is running");
+        serde_json::from_slice(&content).expect("the server sends JSON")
+    }
+
+    async fn send(&mut self, message: Value) {
+        let content = message.to_string();
+        let framed = format!("Content-Length: {}\r\n\r\n{}", content.len(), content);
+        self.writer
+            .write_all(framed.as_bytes())
+            .await
+            .expect("the server is running");
+    }
+}
+
+/// An [`LspClient`] recording everything the server sends it, for calling the handlers of a
+/// `Backend<MockClient>` directly. Clones share their recordings, so a test keeps one to inspect
+/// what the backend it gave the other sent.
+///
+/// Requests are answered like a client supporting them would: edits are applied, progress
+/// tokens and registrations accepted, and the configuration and message actions are the ones
+/// set with [`set_configuration`](MockClient::set_configuration) and
+/// [`set_message_action`](MockClient::set_message_action).
+#[derive(Debug, Clone, Default)]
+pub struct MockClient {
+    recorded: Arc<Mutex<Recorded>>,
+}
+
+#[derive(Debug, Default)]
+struct Recorded {
+    log_messages: Vec<(MessageType, String)>,
+    shown_messages: Vec<(MessageType, String)>,
+    diagnostics: Vec<PublishDiagnosticsParams>,
+    notifications: Vec<(String, Value)>,
+    applied_edits: Vec<WorkspaceEdit>,
+    progress_tokens: Vec<ProgressToken>,
+    registrations: Vec<Registration>,
+    configuration_requests: Vec<Vec<ConfigurationItem>>,
+    configuration: Value,
+    message_action: Option<String>,
+}
+
+impl MockClient {
+    /// The `gen` section answered to `workspace/configuration`, `null` until set.
+    pub fn set_configuration(&self, section: Value) {
+        self.recorded.lock().unwrap().configuration = section;
+    }
+
+    /// The title of the action picked in `window/showMessageRequest`, `None` dismissing it.
+    pub fn set_message_action(&self, title: Option<&str>) {
+        self.recorded.lock().unwrap().message_action = title.map(str::to_string);
+    }
+
+    /// `window/logMessage` notifications that got through the trace level.
+    pub fn log_messages(&self) -> Vec<(MessageType, String)> {
+        self.recorded.lock().unwrap().log_messages.clone()
+    }
+
+    /// `window/showMessage` notifications and `window/showMessageRequest` requests.
+    pub fn shown_messages(&self) -> Vec<(MessageType, String)> {
+        self.recorded.lock().unwrap().shown_messages.clone()
+    }
+
+    pub fn published_diagnostics(&self) -> Vec<PublishDiagnosticsParams> {
+        self.recorded.lock().unwrap().diagnostics.clone()
+    }
+
+    /// The diagnostics last published for `uri`.
+    pub fn diagnostics(&self, uri: &Url) -> Option<Vec<Diagnostic>> {
+        let recorded = self.recorded.lock().unwrap();
+        recorded
+            .diagnostics
+            .iter()
+            .rev()
+            .find(|params| params.uri == *uri)
+            .map(|params| params.diagnostics.clone())
+    }
+
+    /// The params of every other notification with `method`, e.g. `$/progress`.
+    pub fn notifications(&self, method: &str) -> Vec<Value> {
+        let recorded = self.recorded.lock().unwrap();
+        recorded
+            .notifications
+            .iter()
+            .filter(|(sent, _)| sent == method)
+            .map(|(_, params)| params.clone())
+            .collect()
+    }
+
+    /// Edits sent with `workspace/applyEdit`.
+    pub fn applied_edits(&self) -> Vec<WorkspaceEdit> {
+        self.recorded.lock().unwrap().applied_edits.clone()
+    }
+
+    /// Tokens created with `window/workDoneProgress/create`.
+    pub fn progress_tokens(&self) -> Vec<ProgressToken> {
+        self.recorded.lock().unwrap().progress_tokens.clone()
+    }
+
+    /// Capabilities registered with `client/registerCapability`.
+    pub fn registrations(&self) -> Vec<Registration> {
+        self.recorded.lock().unwrap().registrations.clone()
+    }
+
+    /// The items of every `workspace/configuration` request.
+    pub fn configuration_requests(&self) -> Vec<Vec<ConfigurationItem>> {
+        self.recorded.lock().unwrap().configuration_requests.clone()
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LspClient for MockClient {
+    async fn log_message(&self, typ: MessageType, message: String) {
+        self.recorded
+            .lock()
+            .unwrap()
+            .log_messages
+            .push((typ, message));
+    }
+
+    async fn show_message(&self, typ: MessageType, message: String) {
+        self.recorded
+            .lock()
+            .unwrap()
+            .shown_messages
+            .push((typ, message));
+    }
+
+    async fn show_message_request(
+        &self,
+        typ: MessageType,
+        message: String,
+        actions: Option<Vec<MessageActionItem>>,
+    ) -> Result<Option<MessageActionItem>> {
+        let mut recorded = self.recorded.lock().unwrap();
+        recorded.shown_messages.push((typ, message));
+        let title = recorded.message_action.clone();
+        Ok(actions
+            .unwrap_or_default()
+            .into_iter()
+            .find(|action| Some(&action.title) == title.as_ref()))
+    }
+
+    async fn publish_diagnostics(
+        &self,
+        uri: Url,
+        diagnostics: Vec<Diagnostic>,
+        version: Option<i32>,
+    ) {
+        self.recorded
+            .lock()
+            .unwrap()
+            .diagnostics
+            .push(PublishDiagnosticsParams::new(uri, diagnostics, version));
+    }
+
+    async fn send_notification<N>(&self, params: N::Params)
+    where
+        N: Notification,
+        N::Params: Send,
+    {
+        let params = serde_json::to_value(params).expect("notification params are JSON");
+        self.recorded
+            .lock()
+            .unwrap()
+            .notifications
+            .push((N::METHOD.to_string(), params));
+    }
+
+    async fn apply_edit(&self, edit: WorkspaceEdit) -> Result<ApplyWorkspaceEditResponse> {
+        self.recorded.lock().unwrap().applied_edits.push(edit);
+        Ok(ApplyWorkspaceEditResponse {
+            applied: true,
+            failure_reason: None,
+            failed_change: None,
+        })
+    }
+
+    async fn create_work_done_progress(&self, token: ProgressToken) -> Result<()> {
+        self.recorded.lock().unwrap().progress_tokens.push(token);
+        Ok(())
+    }
+
+    async fn register_capability(&self, registrations: Vec<Registration>) -> Result<()> {
+        self.recorded
+            .lock()
+            .unwrap()
+            .registrations
+            .extend(registrations);
+        Ok(())
+    }
+
+    async fn configuration(&self, items: Vec<ConfigurationItem>) -> Result<Vec<Value>> {
+        let mut recorded = self.recorded.lock().unwrap();
+        let configuration = items
+            .iter()
+            .map(|_| recorded.configuration.clone())
+            .collect();
+        recorded.configuration_requests.push(items);
+        Ok(configuration)
+    }
+}
@@ -0,0 +1,52 @@
+use chumsky::Parser;
+
+use crate::chumsky::{lexer, skip_padding, Span, Token};
+
+/// Commas directly followed by the `)` or `]` closing their list. The parser accepts them, this
+/// is only used for linting.
+pub fn trailing_commas(src: &str) -> Vec<Span> {
+    let (tokens, _) = lexer().parse_recovery(src);
+    let tokens = tokens.unwrap_or_default();
+    let chars = src.chars().collect::<Vec<_>>();
+    tokens
+        .windows(2)
+        .filter_map(|pair| match pair {
+            [(Token::Ctrl(','), span), (Token::Ctrl(')' | ']'), _)] => {
+                let start = skip_padding(&chars, span.start);
+                Some(start..start + 1)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parse;
+
+    #[test]
+    fn trailing_commas_parse_without_errors() {
+        let src = "fn add(a, b,) {\n    a + b\n}\nfn main() {\n    add([1, 2,], 3,)\n}\n";
+        let (ast, errors, _) = parse(src);
+        assert!(errors.is_empty());
+        assert_eq!(ast.unwrap()["add"].args.len(), 2);
+    }
+
+    #[test]
+    fn finds_commas_before_closing_brackets() {
+        let src = "fn main() {\n    f([1, 2,], 3 , // last\n    )\n}\n";
+        let commas = trailing_commas(src);
+        assert_eq!(commas.len(), 2);
+        assert!(commas
+            .iter()
+            .all(|span| &src[span.clone()] == "," && span.len() == 1));
+        assert_eq!(commas[0].start, src.find(",]").unwrap());
+        assert_eq!(commas[1].start, src.find(" , ").unwrap() + 1);
+    }
+
+    #[test]
+    fn commas_between_elements_are_fine() {
+        assert!(trailing_commas("fn main() {\n    [1, 2]\n}\n").is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use im_rc::Vector;
+
+use crate::chumsky::{Expr, Func, Spanned};
+use crate::utils::levenshtein;
+
+/// Maximum edit distance for a name to be suggested as a correction.
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+/// Maximum number of suggested corrections per unresolved symbol.
+const MAX_SUGGESTIONS: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct UnresolvedSymbol {
+    pub name: Spanned<String>,
+    /// Closest in-scope names, nearest first.
+    pub suggestions: Vec<String>,
+}
+
+/// Every local that refers to neither a function, an argument nor a `let` binding in scope.
+pub fn get_unresolved_symbols(ast: &HashMap<String, Func>) -> Vec<UnresolvedSymbol> {
+    let functions = ast.keys().cloned().collect::<Vector<_>>();
+    let mut unresolved = ast
+        .values()
+        .flat_map(|func| get_unresolved_symbols_of_func(func, &functions))
+        .collect::<Vec<_>>();
+    unresolved.sort_by_key(|symbol| symbol.name.1.start);
+    unresolved
+}
+
+/// The unresolved symbols of `func`, in a document defining `functions`.
+pub fn get_unresolved_symbols_of_func(
+    func: &Func,
+    functions: &Vector<String>,
+) -> Vec<UnresolvedSymbol> {
+    let args = func
+        .args
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect::<Vector<_>>();
+    let mut unresolved = vec![];
+    get_unresolved_symbols_of_expr(&func.body, args + functions.clone(), &mut unresolved);
+    unresolved
+}
+
+fn get_unresolved_symbols_of_expr(
+    expr: &Spanned<Expr>,
+    scope: Vector<String>,
+    unresolved: &mut Vec<UnresolvedSymbol>,
+) {
+    match &expr.0 {
+        Expr::Error => {}
+        Expr::Value(_) => {}
+        Expr::Local((name, span)) => {
+            if !scope.contains(name) {
+                unresolved.push(UnresolvedSymbol {
+                    name: (name.clone(), span.clone()),
+                    suggestions: suggestions(name, &scope),
+                });
+            }
+        }
+        Expr::Let(name, lhs, rest, _) => {
+            get_unresolved_symbols_of_expr(lhs, scope.clone(), unresolved);
+            get_unresolved_symbols_of_expr(rest, Vector::unit(name.clone()) + scope, unresolved);
+        }
+        Expr::Then(first, second) => {
+            get_unresolved_symbols_of_expr(first, scope.clone(), unresolved);
+            get_unresolved_symbols_of_expr(second, scope, unresolved);
+        }
+        Expr::Binary(lhs, _, rhs) => {
+            get_unresolved_symbols_of_expr(lhs, scope.clone(), unresolved);
+            get_unresolved_symbols_of_expr(rhs, scope, unresolved);
+        }
+        Expr::Call(callee, args) => {
+            get_unresolved_symbols_of_expr(callee, scope.clone(), unresolved);
+            for expr in &args.0 {
+                get_unresolved_symbols_of_expr(expr, scope.clone(), unresolved);
+            }
+        }
+        Expr::If(test, consequent, alternative) => {
+            get_unresolved_symbols_of_expr(test, scope.clone(), unresolved);
+            get_unresolved_symbols_of_expr(consequent, scope.clone(), unresolved);
+            get_unresolved_symbols_of_expr(alternative, scope, unresolved);
+        }
+        Expr::Print(expr) => get_unresolved_symbols_of_expr(expr, scope, unresolved),
+        Expr::List(lst) => {
+            for expr in lst {
+                get_unresolved_symbols_of_expr(expr, scope.clone(), unresolved);
+            }
+        }
+    }
+}
+
+fn suggestions(name: &str, scope: &Vector<String>) -> Vec<String> {
+    let mut candidates = scope
+        .iter()
+        .map(|candidate| (levenshtein(name, candidate), candidate.clone()))
+        .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates.dedup();
+    candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parse;
+
+    fn unresolved(src: &str) -> Vec<(String, Vec<String>)> {
+        let ast = parse(src).0.unwrap();
+        get_unresolved_symbols(&ast)
+            .into_iter()
+            .map(|symbol| (symbol.name.0, symbol.suggestions))
+            .collect()
+    }
+
+    #[test]
+    fn typos_get_the_closest_names() {
+        let src = "fn count(items) { items }\nfn main(counter) {\n    let total = 1;\n    cuont(totl) + countr\n}\n";
+        assert_eq!(
+            unresolved(src),
+            [
+                ("cuont".to_string(), vec!["count".to_string()]),
+                ("totl".to_string(), vec!["total".to_string()]),
+                (
+                    "countr".to_string(),
+                    vec!["count".to_string(), "counter".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn far_names_are_not_suggested() {
+        let src = "fn main(a) {\n    nothing_alike\n}\n";
+        assert_eq!(unresolved(src), [("nothing_alike".to_string(), vec![])]);
+    }
+
+    #[test]
+    fn at_most_three_suggestions() {
+        let src = "fn main(ab, ac, ad, ae) {\n    aa\n}\n";
+        let (_, suggestions) = &unresolved(src)[0];
+        assert_eq!(suggestions, &["ab", "ac", "ad"]);
+    }
+
+    #[test]
+    fn bindings_are_in_scope_after_them() {
+        let src = "fn main() {\n    let x = x;\n    x\n}\n";
+        let names = unresolved(src)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["x"]);
+    }
+}
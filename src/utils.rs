@@ -0,0 +1,231 @@
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
+use ropey::Rope;
+use rustc_hash::FxHasher;
+use tower_lsp::lsp_types::{Position, Url};
+
+/// Text with more than one U+FFFD per this many chars is taken for decoded binary content.
+const BINARY_REPLACEMENT_RATIO: usize = 10;
+
+/// Returns `true` if `text` does not look like source code: it contains NUL bytes, or so many
+/// U+FFFD replacement characters that it must be non-UTF-8 bytes a client lossily decoded. A few
+/// replacement characters are legitimate, e.g. in a string or a comment.
+pub fn is_binary(text: &str) -> bool {
+    if text.contains('\0') {
+        return true;
+    }
+    let replacements = text
+        .chars()
+        .filter(|c| *c == char::REPLACEMENT_CHARACTER)
+        .count();
+    replacements * BINARY_REPLACEMENT_RATIO > text.chars().count()
+}
+
+/// The byte order mark some editors write at the start of UTF-8 files.
+pub const BOM: char = '\u{feff}';
+
+/// `text` without its leading byte order mark, if any. Positions sent by clients are relative
+/// to the visible text, so the mark must not take up a char in the rope.
+pub fn strip_bom(text: &str) -> Option<&str> {
+    text.strip_prefix(BOM)
+}
+
+/// A fast, non-cryptographic hash of `text`, stable for the lifetime of the process.
+pub fn content_hash(text: &str) -> u64 {
+    let mut hasher = FxHasher::default();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn offset_to_position(offset: usize, rope: &Rope) -> Option<Position> {
+    let line = rope.try_char_to_line(offset).ok()?;
+    let first_char_of_line = rope.try_line_to_char(line).ok()?;
+    let column = offset - first_char_of_line;
+    Some(Position::new(line as u32, column as u32))
+}
+
+/// Clamps a client-supplied `position` to the bounds of `rope`: lines past the end land at the
+/// end of the document, and columns past the end of a line land right before its line ending.
+pub fn clamp_position(rope: &Rope, position: Position) -> Position {
+    let last_line = rope.len_lines().saturating_sub(1);
+    let line = (position.line as usize).min(last_line);
+    let line_slice = rope.line(line);
+    let mut line_len = line_slice.len_chars();
+    while line_len > 0 && matches!(line_slice.char(line_len - 1), '\n' | '\r') {
+        line_len -= 1;
+    }
+    let character = if position.line as usize > last_line {
+        line_len
+    } else {
+        (position.character as usize).min(line_len)
+    };
+    Position::new(line as u32, character as u32)
+}
+
+/// The char offset of `position` after clamping it to the document.
+pub fn position_to_offset(rope: &Rope, position: Position) -> usize {
+    let position = clamp_position(rope, position);
+    rope.line_to_char(position.line as usize) + position.character as usize
+}
+
+/// Edit distance between `a` and `b`, counted in chars.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(previous + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The path of a `file:` URI, percent-decoded. Windows URIs are recognized whatever the host
+/// platform: `file:///c%3A/My%20Project/main.nrs` gives `c:/My Project/main.nrs`, and
+/// `file://server/share/main.nrs` the UNC path `//server/share/main.nrs`.
+pub fn uri_to_path(uri: &Url) -> Option<PathBuf> {
+    if uri.scheme() != "file" {
+        return None;
+    }
+    let path = percent_decode(uri.path())?;
+    let path = match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => path[1..].to_string(),
+        _ => path,
+    };
+    Some(match uri.host_str().filter(|host| !host.is_empty()) {
+        Some(host) => PathBuf::from(format!("//{}{}", host, path)),
+        None => PathBuf::from(path),
+    })
+}
+
+/// `text` with its `%XX` escapes decoded, or `None` when it doesn't decode to UTF-8.
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = match tail {
+            [high, low, ..] if byte == b'%' => std::str::from_utf8(&[*high, *low])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(escaped) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_past_the_end_are_clamped() {
+        let rope = Rope::from_str("fn main() {\r\n    1\r\n}");
+        assert_eq!(
+            clamp_position(&rope, Position::new(1, 2)),
+            Position::new(1, 2)
+        );
+        // Columns stop before the line ending
+        assert_eq!(
+            clamp_position(&rope, Position::new(0, 40)),
+            Position::new(0, 11)
+        );
+        assert_eq!(
+            clamp_position(&rope, Position::new(2, 9)),
+            Position::new(2, 1)
+        );
+        // Lines past the last one land at the end of the document
+        assert_eq!(
+            clamp_position(&rope, Position::new(7, 0)),
+            Position::new(2, 1)
+        );
+        assert_eq!(
+            position_to_offset(&rope, Position::new(7, 0)),
+            rope.len_chars()
+        );
+        assert_eq!(position_to_offset(&rope, Position::new(0, 40)), 11);
+    }
+
+    #[test]
+    fn positions_in_an_empty_document_are_clamped_to_its_start() {
+        let rope = Rope::from_str("");
+        assert_eq!(
+            clamp_position(&rope, Position::new(3, 3)),
+            Position::new(0, 0)
+        );
+        assert_eq!(position_to_offset(&rope, Position::new(3, 3)), 0);
+    }
+
+    #[test]
+    fn nul_bytes_are_binary() {
+        assert!(is_binary("fn main() {\0}"));
+    }
+
+    #[test]
+    fn lossily_decoded_bytes_are_binary() {
+        let bytes = [0x89, b'P', b'N', b'G', 0xff, 0xfe, 0xfd, 0x80, 0x81, 0x82];
+        assert!(is_binary(&String::from_utf8_lossy(&bytes)));
+    }
+
+    #[test]
+    fn replacement_characters_in_source_are_not_binary() {
+        assert!(!is_binary(
+            "// the decoder writes \u{fffd} for invalid bytes\nfn main() { \"\u{fffd}\" }\n"
+        ));
+        assert!(!is_binary("fn main() { 1 }\n"));
+    }
+
+    fn path(uri: &str) -> Option<PathBuf> {
+        uri_to_path(&Url::parse(uri).unwrap())
+    }
+
+    #[test]
+    fn windows_uris_lose_the_leading_slash() {
+        assert_eq!(
+            path("file:///c%3A/My%20Project/main.nrs"),
+            Some(PathBuf::from("c:/My Project/main.nrs"))
+        );
+        assert_eq!(
+            path("file:///D:/src/main.nrs"),
+            Some(PathBuf::from("D:/src/main.nrs"))
+        );
+    }
+
+    #[test]
+    fn percent_escapes_are_decoded() {
+        assert_eq!(
+            path("file:///home/me/my%20files/caf%C3%A9.nrs"),
+            Some(PathBuf::from("/home/me/my files/café.nrs"))
+        );
+        // Only escapes decoding to UTF-8 make a path
+        assert_eq!(path("file:///tmp/%FF.nrs"), None);
+        // A `%` not followed by two hex digits is kept as is
+        assert_eq!(
+            path("file:///tmp/100%25%zz.nrs"),
+            Some(PathBuf::from("/tmp/100%%zz.nrs"))
+        );
+    }
+
+    #[test]
+    fn unc_and_non_file_uris() {
+        assert_eq!(
+            path("file://server/share/main.nrs"),
+            Some(PathBuf::from("//server/share/main.nrs"))
+        );
+        assert_eq!(path("untitled:Untitled-1"), None);
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use crate::chumsky::{Expr, Func, Span, Spanned};
+
+/// A traversal of the AST. Every method defaults to visiting the children of its node, so an
+/// implementation only overrides the nodes it cares about and calls back into the matching
+/// `walk_*` function to keep descending.
+pub trait Visitor {
+    fn visit_func(&mut self, func: &Func) {
+        walk_func(self, func);
+    }
+
+    fn visit_expr(&mut self, expr: &Spanned<Expr>) {
+        walk_expr(self, expr);
+    }
+}
+
+/// Visits the functions of `ast` in source order.
+pub fn walk_ast<V: Visitor + ?Sized>(visitor: &mut V, ast: &HashMap<String, Func>) {
+    let mut funcs = ast.values().collect::<Vec<_>>();
+    funcs.sort_by_key(|func| func.span.start);
+    for func in funcs {
+        visitor.visit_func(func);
+    }
+}
+
+pub fn walk_func<V: Visitor + ?Sized>(visitor: &mut V, func: &Func) {
+    visitor.visit_expr(&func.body);
+}
+
+/// Visits the direct children of `expr`, in source order.
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Spanned<Expr>) {
+    match &expr.0 {
+        Expr::Error | Expr::Value(_) | Expr::Local(_) => {}
+        Expr::List(exprs) => exprs.iter().for_each(|expr| visitor.visit_expr(expr)),
+        Expr::Let(_, rhs, rest, _) => {
+            visitor.visit_expr(rhs);
+            visitor.visit_expr(rest);
+        }
+        Expr::Then(first, second) | Expr::Binary(first, _, second) => {
+            visitor.visit_expr(first);
+            visitor.visit_expr(second);
+        }
+        Expr::Call(callee, args) => {
+            visitor.visit_expr(callee);
+            args.0.iter().for_each(|arg| visitor.visit_expr(arg));
+        }
+        Expr::If(test, consequent, alternative) => {
+            visitor.visit_expr(test);
+            visitor.visit_expr(consequent);
+            visitor.visit_expr(alternative);
+        }
+        Expr::Print(expr) => visitor.visit_expr(expr),
+    }
+}
+
+/// Collects the spans of the expressions `filter` selects, outer expressions before the ones
+/// they contain.
+pub struct SpanCollector<F> {
+    filter: F,
+    pub spans: Vec<Span>,
+}
+
+impl<F: FnMut(&Expr) -> bool> SpanCollector<F> {
+    pub fn new(filter: F) -> Self {
+        SpanCollector {
+            filter,
+            spans: vec![],
+        }
+    }
+}
+
+impl<F: FnMut(&Expr) -> bool> Visitor for SpanCollector<F> {
+    fn visit_expr(&mut self, expr: &Spanned<Expr>) {
+        if (self.filter)(&expr.0) {
+            self.spans.push(expr.1.clone());
+        }
+        walk_expr(self, expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parse;
+
+    /// Counts the functions and the calls it visits.
+    #[derive(Default)]
+    struct Counter {
+        funcs: usize,
+        calls: usize,
+    }
+
+    impl Visitor for Counter {
+        fn visit_func(&mut self, func: &Func) {
+            self.funcs += 1;
+            walk_func(self, func);
+        }
+
+        fn visit_expr(&mut self, expr: &Spanned<Expr>) {
+            if let Expr::Call(..) = expr.0 {
+                self.calls += 1;
+            }
+            walk_expr(self, expr);
+        }
+    }
+
+    #[test]
+    fn counts_function_nodes() {
+        let src = "fn one() { 1 }\nfn two() { one() + one() }\nfn three() { two() + one() }\n";
+        let ast = parse(src).0.unwrap();
+        let mut counter = Counter::default();
+        walk_ast(&mut counter, &ast);
+        assert_eq!(counter.funcs, 3);
+        assert_eq!(counter.calls, 4);
+    }
+
+    #[test]
+    fn visits_functions_in_source_order() {
+        struct Names(Vec<String>);
+        impl Visitor for Names {
+            fn visit_func(&mut self, func: &Func) {
+                self.0.push(func.name.0.clone());
+            }
+        }
+        let ast = parse("fn b() { 1 }\nfn c() { 2 }\nfn a() { 3 }\n")
+            .0
+            .unwrap();
+        let mut names = Names(vec![]);
+        walk_ast(&mut names, &ast);
+        assert_eq!(names.0, ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn collects_spans_outer_first() {
+        let src = "fn main() { [1, [2]] }";
+        let ast = parse(src).0.unwrap();
+        let mut lists = SpanCollector::new(|expr| matches!(expr, Expr::List(_)));
+        walk_ast(&mut lists, &ast);
+        let lists = lists
+            .spans
+            .iter()
+            .map(|span| &src[span.clone()])
+            .collect::<Vec<_>>();
+        assert_eq!(lists, ["[1, [2]]", "[2]"]);
+    }
+}
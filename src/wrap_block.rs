@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use chumsky::Parser;
+use ropey::Rope;
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::chumsky::{lexer, Func, Token};
+
+/// Wraps lines `start_line..=end_line` in a `{ ... }` block, e.g. to put a condition in front of
+/// it. The braces get the indentation of the least indented line and the lines one `indent`
+/// deeper; a `;` after the block separates it from the statements following it. `None` unless
+/// the lines hold whole statements of a single function body.
+pub fn wrap_in_block(
+    rope: &Rope,
+    ast: &HashMap<String, Func>,
+    start_line: usize,
+    end_line: usize,
+    indent: &str,
+) -> Option<TextEdit> {
+    let lines = (start_line..=end_line)
+        .map(|line| {
+            let text = rope.get_line(line)?.to_string();
+            Some(text.trim_end_matches(['\n', '\r']).to_string())
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let first = lines.iter().position(|line| !line.trim().is_empty())?;
+    let last = lines.iter().rposition(|line| !line.trim().is_empty())?;
+    let content_start = rope.line_to_char(start_line + first)
+        + lines[first]
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .count();
+    let content_end = rope.line_to_char(start_line + last) + lines[last].trim_end().chars().count();
+    // Inside the braces of the body, its closing brace included in `span` but not in `body`
+    let in_body = ast
+        .values()
+        .any(|func| func.body.1.start <= content_start && content_end < func.span.end);
+    if !in_body || !balanced(&rope.slice(content_start..content_end).to_string()) {
+        return None;
+    }
+
+    let base = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .min_by_key(|indentation| indentation.chars().count())?;
+    let mut new_text = format!("{}{{\n", base);
+    for line in &lines {
+        if !line.trim().is_empty() {
+            new_text.push_str(base);
+            new_text.push_str(indent);
+            new_text.push_str(line.strip_prefix(base).unwrap_or(line.trim_start()));
+        }
+        new_text.push('\n');
+    }
+    new_text.push_str(base);
+    new_text.push('}');
+    // A block followed by another statement must be ended like one
+    let followed = rope
+        .chars_at(content_end)
+        .find(|c| !c.is_whitespace())
+        .is_some_and(|c| c != '}');
+    if followed {
+        new_text.push(';');
+    }
+
+    let end = Position::new(
+        end_line as u32,
+        lines[lines.len() - 1].chars().count() as u32,
+    );
+    Some(TextEdit::new(
+        Range::new(Position::new(start_line as u32, 0), end),
+        new_text,
+    ))
+}
+
+/// Whether every bracket opened in `src` is closed in it, and none closed before being opened.
+fn balanced(src: &str) -> bool {
+    let (tokens, _) = lexer().parse_recovery(src);
+    let mut depth = 0usize;
+    for (token, _) in tokens.unwrap_or_default() {
+        match token {
+            Token::Ctrl('(' | '[' | '{') => depth += 1,
+            Token::Ctrl(')' | ']' | '}') => match depth.checked_sub(1) {
+                Some(outer) => depth = outer,
+                None => return false,
+            },
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chumsky::parse;
+
+    fn wrapped(src: &str, start_line: usize, end_line: usize) -> Option<String> {
+        let rope = Rope::from_str(src);
+        let ast = parse(src).0.unwrap();
+        let edit = wrap_in_block(&rope, &ast, start_line, end_line, "    ")?;
+        let start = rope.line_to_char(edit.range.start.line as usize);
+        let end =
+            rope.line_to_char(edit.range.end.line as usize) + edit.range.end.character as usize;
+        let mut rope = rope;
+        rope.remove(start..end);
+        rope.insert(start, &edit.new_text);
+        Some(rope.to_string())
+    }
+
+    #[test]
+    fn statements_are_indented_one_level_deeper() {
+        let src = "fn main() {\n    print(1);\n\n    print(2);\n    3\n}\n";
+        let result = wrapped(src, 1, 3).unwrap();
+        assert_eq!(
+            result,
+            "fn main() {\n    {\n        print(1);\n\n        print(2);\n    };\n    3\n}\n"
+        );
+        assert!(parse(&result).1.is_empty());
+    }
+
+    #[test]
+    fn the_last_statement_needs_no_separator() {
+        assert_eq!(
+            wrapped("fn main() {\n    print(1);\n    2\n}\n", 1, 2).unwrap(),
+            "fn main() {\n    {\n        print(1);\n        2\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn partial_expressions_and_lines_outside_bodies_are_not_wrapped() {
+        let src = "fn main() {\n    if true {\n        1\n    } else {\n        2\n    }\n}\n";
+        assert_eq!(wrapped(src, 1, 2), None);
+        assert_eq!(wrapped(src, 0, 1), None);
+        assert!(wrapped(src, 1, 5).is_some());
+    }
+}
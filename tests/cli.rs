@@ -0,0 +1,25 @@
+use std::process::Command;
+
+fn server() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_nrs-language-server"))
+}
+
+#[test]
+fn version_prints_the_version_and_exits() {
+    let output = server().arg("--version").output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("nrs-language-server {}\n", env!("CARGO_PKG_VERSION"))
+    );
+}
+
+#[test]
+fn unknown_flags_print_the_usage() {
+    let output = server().arg("--port").output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("unknown argument `--port`"));
+    assert!(stderr.contains("usage: nrs-language-server"));
+}
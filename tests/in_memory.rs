@@ -0,0 +1,52 @@
+use nrs_language_server::test_util::serve_in_memory;
+use serde_json::json;
+
+#[tokio::test]
+async fn publishes_diagnostics_of_an_opened_document() {
+    let (mut client, _server) = serve_in_memory();
+    let initialize = client
+        .request("initialize", json!({ "capabilities": {} }))
+        .await;
+    assert_eq!(initialize["result"]["capabilities"]["hoverProvider"], true);
+    client.notify("initialized", json!({})).await;
+    client
+        .notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": {
+                "uri": "file:///main.nrs",
+                "languageId": "nrs",
+                "version": 3,
+                "text": "fn main() {\n    x\n}\n",
+            }}),
+        )
+        .await;
+
+    let diagnostics = client.notification("textDocument/publishDiagnostics").await;
+    let params = &diagnostics["params"];
+    assert_eq!(params["uri"], "file:///main.nrs");
+    assert_eq!(params["version"], 3);
+    assert_eq!(params["diagnostics"].as_array().map(Vec::len), Some(1));
+    assert_eq!(params["diagnostics"][0]["code"], "unresolved-symbol");
+    assert_eq!(
+        params["diagnostics"][0]["range"],
+        json!({ "start": { "line": 1, "character": 4 }, "end": { "line": 1, "character": 5 } })
+    );
+}
+
+#[tokio::test]
+async fn answers_requests_after_initialize() {
+    let (mut client, _server) = serve_in_memory();
+    client
+        .request("initialize", json!({ "capabilities": {} }))
+        .await;
+    client.notify("initialized", json!({})).await;
+    let formatted = client
+        .request(
+            "gen/formatString",
+            json!({ "text": "fn main(){1}", "options": { "tabSize": 4, "insertSpaces": true } }),
+        )
+        .await;
+    assert!(formatted["result"].is_string());
+    let shutdown = client.request("shutdown", json!(null)).await;
+    assert_eq!(shutdown["result"], json!(null));
+}
@@ -0,0 +1,47 @@
+use nrs_language_server::backend::Backend;
+use tower_lsp::lsp_types::{
+    DidOpenTextDocumentParams, GotoDefinitionParams, GotoDefinitionResponse, InitializeParams,
+    Location, Position, Range, TextDocumentIdentifier, TextDocumentItem,
+    TextDocumentPositionParams, Url,
+};
+use tower_lsp::LanguageServer;
+
+#[tokio::test]
+async fn handlers_are_callable_on_the_library_backend() {
+    let (service, _socket) = Backend::service();
+    let backend = service.inner();
+    backend
+        .initialize(InitializeParams::default())
+        .await
+        .unwrap();
+    let uri = Url::parse("file:///workspace/main.nrs").unwrap();
+    backend
+        .did_open(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(
+                uri.clone(),
+                "nrs".to_string(),
+                1,
+                "fn add(a, b) {\n    a + b\n}\n\nfn main() {\n    add(1, 2)\n}\n".to_string(),
+            ),
+        })
+        .await;
+
+    let definition = backend
+        .goto_definition(GotoDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(uri.clone()),
+                Position::new(5, 5),
+            ),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        definition,
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            uri,
+            Range::new(Position::new(0, 3), Position::new(0, 6))
+        )))
+    );
+}